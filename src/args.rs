@@ -0,0 +1,719 @@
+use std::ffi::OsString;
+
+#[cfg(feature = "regex")]
+use owo_colors::AnsiColors;
+#[cfg(feature = "regex")]
+use regex::bytes::Regex;
+use thiserror::Error;
+
+use crate::Checksum;
+use crate::ColorChoice;
+#[cfg(feature = "gzip")]
+use crate::Compression;
+use crate::FlushMode;
+#[cfg(feature = "regex")]
+use crate::Highlight;
+use crate::InvalidUtf8;
+use crate::NulMode;
+use crate::NumberingMode;
+use crate::OffsetFormat;
+use crate::Options;
+#[cfg(feature = "encoding")]
+use crate::OutputEncoding;
+use crate::ShowLf;
+#[cfg(feature = "encoding")]
+use crate::Unmappable;
+
+/// Every long option, which may be abbreviated to any unambiguous prefix
+const LONG_OPTIONS: &[&str] = &[
+    "show-all",
+    "number-nonblank",
+    "show-ends",
+    "show-lf",
+    "normalize-crlf",
+    "number",
+    "number-start",
+    "number-reset-per-file",
+    "offsets",
+    "squeeze-blank",
+    "squeeze-repeats",
+    "show-tabs",
+    "expand-leading",
+    "show-nonprinting",
+    "nul",
+    "invalid",
+    "strip-ansi",
+    "trim-trailing",
+    "interpret-escapes",
+    "wrap",
+    "line-terminator",
+    "ensure-trailing-newline",
+    #[cfg(feature = "encoding")]
+    "output-encoding",
+    #[cfg(feature = "encoding")]
+    "unmappable",
+    "lines",
+    "match",
+    #[cfg(feature = "regex")]
+    "highlight",
+    "invert",
+    "uniq",
+    "uniq-count",
+    "head",
+    "tail",
+    "hex",
+    "json",
+    "max-bytes",
+    "skip-bytes",
+    "stats",
+    "count",
+    "checksum",
+    "split-lines",
+    "output-prefix",
+    "tee",
+    "files-from",
+    "files0-from",
+    "fd",
+    "with-filename",
+    "headers",
+    "follow",
+    "binary-check",
+    "progress",
+    "check",
+    "flush",
+    "color",
+    "help",
+    "version",
+];
+/// Long options that take a value, as `--option value` or `--option=value`
+const VALUE_OPTIONS: &[&str] = &[
+    "number-start",
+    "nul",
+    "invalid",
+    "max-bytes",
+    "skip-bytes",
+    "lines",
+    "match",
+    #[cfg(feature = "regex")]
+    "highlight",
+    "head",
+    "tail",
+    "wrap",
+    "files-from",
+    "files0-from",
+    "fd",
+    "line-terminator",
+    "flush",
+    "checksum",
+    "split-lines",
+    "output-prefix",
+    "tee",
+    #[cfg(feature = "encoding")]
+    "output-encoding",
+    #[cfg(feature = "encoding")]
+    "unmappable",
+];
+/// The colors given to `--highlight` patterns, in turn
+#[cfg(feature = "regex")]
+const HIGHLIGHT_COLORS: [AnsiColors; 5] = [
+    AnsiColors::BrightYellow,
+    AnsiColors::BrightMagenta,
+    AnsiColors::BrightCyan,
+    AnsiColors::BrightRed,
+    AnsiColors::BrightBlue,
+];
+/// Long options whose value is a file name, so it may be any bytes
+const PATH_OPTIONS: &[&str] = &["files-from", "files0-from", "output-prefix", "tee"];
+/// Long options whose value may only be given as `--option=value`
+const OPTIONAL_VALUE_OPTIONS: &[&str] = &["show-lf", "expand-leading", "offsets", "color"];
+
+/// The result of parsing a command line
+#[derive(PartialEq, Debug, Clone)]
+pub struct Args {
+    /// Files to concatenate, in order. Names need not be valid UTF-8.
+    pub files: Vec<OsString>,
+
+    /// Formatting options
+    pub options: Options,
+
+    /// Print statistics to stderr when done
+    pub stats: bool,
+
+    /// Print only the number of lines that would be written
+    pub count: bool,
+
+    /// A file listing the files to concatenate instead of `files` (`-` for
+    /// standard input), and the byte that ends each entry
+    pub files_from: Option<(OsString, u8)>,
+
+    /// An inherited file descriptor to read instead of `files`
+    pub fd: Option<i32>,
+
+    /// Hash the output and print the digest to stderr when done
+    pub checksum: Option<Checksum>,
+
+    /// Write the output to files of this many lines each instead of stdout
+    pub split_lines: Option<usize>,
+
+    /// The start of the names of the `split_lines` files, `x` by default
+    pub output_prefix: OsString,
+
+    /// A file to write a copy of the output to, as well as the output itself
+    pub tee: Option<OsString>,
+
+    /// `--wrap auto` was the last `--wrap` given, so `options.wrap` should be
+    /// set from the width of the terminal, if any. The library never looks at
+    /// the terminal.
+    #[cfg(feature = "terminal")]
+    pub wrap_auto: bool,
+}
+
+/// Why a command line did not produce [`Args`]
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseError {
+    /// `--help` or `-h` was given, after any `--color` choice in `color`
+    #[error("help requested")]
+    Help { color: ColorChoice },
+    /// `--version` or `-V` was given, after any `--color` choice in `color`
+    #[error("version requested")]
+    Version { color: ColorChoice },
+    /// An unknown option, as it was given
+    #[error("invalid option -- '{0}'")]
+    InvalidOption(String),
+    /// A long option prefix that matches several options
+    #[error("option '--{option}' is ambiguous")]
+    AmbiguousOption {
+        option: String,
+        candidates: Vec<&'static str>,
+    },
+    /// An option that needs a value was given none
+    #[error("option '{0}' requires an argument")]
+    MissingArgument(String),
+    /// An option was given a value it can't use
+    #[error("invalid argument '{value}' for '{option}'")]
+    InvalidArgument { option: String, value: String },
+    /// A file was given along with `--files-from`, `--files0-from` or `--fd`
+    #[error("extra operand '{0}'")]
+    ExtraOperand(String),
+}
+
+/// Parse a command line like the `ccc` binary does. The first item is the
+/// program name and is skipped.
+///
+/// With no files, standard input (`-`) is read. Arguments after `--` are always
+/// files. Only file names, including the `--files-from` list, may be invalid
+/// UTF-8.
+pub fn parse_args(args: impl IntoIterator<Item = impl Into<OsString>>) -> Result<Args, ParseError> {
+    let mut file_paths = Vec::new();
+    let mut options = Options::new().color(ColorChoice::Auto);
+    #[cfg(feature = "gzip")]
+    {
+        options = options.decompress(Compression::Auto);
+    }
+    #[cfg(feature = "mmap")]
+    {
+        options = options.mmap(true);
+    }
+    let mut stats = false;
+    let mut count = false;
+    let mut files_from = None;
+    let mut fd = None;
+    let mut checksum = None;
+    let mut split_lines = None;
+    let mut output_prefix = OsString::from("x");
+    let mut tee = None;
+    #[cfg(feature = "terminal")]
+    let mut wrap_auto = false;
+    let mut rest = args.into_iter().skip(1).map(Into::into);
+    while let Some(arg) = rest.next() {
+        let arg = match arg.into_string() {
+            Ok(arg) => arg,
+            Err(arg) if arg.as_encoded_bytes().starts_with(b"-") => {
+                return Err(ParseError::InvalidOption(
+                    arg.to_string_lossy().into_owned(),
+                ));
+            }
+            Err(file) => {
+                file_paths.push(file);
+                continue;
+            }
+        };
+        if arg == "--" {
+            // Everything after `--` is a file, even if it starts with `-`
+            file_paths.extend(rest.by_ref());
+            break;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (option, value) = match long.split_once('=') {
+                Some((option, value)) => (option, Some(OsString::from(value))),
+                None => (long, None),
+            };
+            let option = resolve_long_option(&arg, option)?;
+            let value = if VALUE_OPTIONS.contains(&option) {
+                value.or_else(|| rest.next())
+            } else if value.is_some() && !OPTIONAL_VALUE_OPTIONS.contains(&option) {
+                return Err(ParseError::InvalidOption(arg));
+            } else {
+                value
+            };
+            let path = value.clone().filter(|_| PATH_OPTIONS.contains(&option));
+            let value = match &value {
+                Some(value) if path.is_none() => Some(
+                    value
+                        .to_str()
+                        .ok_or_else(|| invalid_argument(option, &value.to_string_lossy()))?,
+                ),
+                _ => None,
+            };
+            match option {
+                "show-all" => {
+                    options = options.show_all();
+                }
+                "number-nonblank" => {
+                    options = options.stack_number(NumberingMode::NonEmpty);
+                }
+                "show-ends" => {
+                    options = options.show_ends(true);
+                }
+                "number" => {
+                    options = options.stack_number(NumberingMode::All);
+                }
+                "number-start" => {
+                    options = options.number_start(parse_count(option, value)?);
+                }
+                "squeeze-blank" => {
+                    options = options.squeeze_blank(true);
+                }
+                "normalize-crlf" => {
+                    options = options.normalize_crlf(true);
+                }
+                "number-reset-per-file" => {
+                    options = options.number_reset_per_file(true);
+                }
+                "squeeze-repeats" => {
+                    options = options.squeeze_repeats(true);
+                }
+                "show-tabs" => {
+                    options = options.show_tabs(true);
+                }
+                "expand-leading" => {
+                    let tab_width = match value {
+                        Some(_) => parse_count(option, value)?,
+                        None => 8,
+                    };
+                    options = options.expand_leading(Some(tab_width));
+                }
+                "show-nonprinting" => {
+                    options = options.show_nonprinting(true);
+                }
+                "nul" => {
+                    options = options.nul(Some(parse_nul(option, value)?));
+                }
+                "lines" => {
+                    options = options.line_range(Some(parse_range(option, value)?));
+                }
+                "match" => {
+                    let value =
+                        value.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?;
+                    options = options.filter(Some(value.to_string()));
+                }
+                "invert" => {
+                    options = options.invert_filter(true);
+                }
+                "uniq" => {
+                    options = options.uniq(true);
+                }
+                "uniq-count" => {
+                    options = options.uniq(true).uniq_count(true);
+                }
+                "max-bytes" => {
+                    options = options.max_bytes(Some(parse_count(option, value)?));
+                }
+                #[cfg(feature = "regex")]
+                "highlight" => {
+                    let value =
+                        value.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?;
+                    let pattern = Regex::new(value).map_err(|_| invalid_argument(option, value))?;
+                    // Each pattern gets the next color
+                    let color = HIGHLIGHT_COLORS[options.highlight.len() % HIGHLIGHT_COLORS.len()];
+                    options.highlight.push(Highlight::new(pattern, color));
+                }
+                "skip-bytes" => {
+                    options = options.skip_bytes(parse_count(option, value)?);
+                }
+                "head" => {
+                    options = options.head(Some(parse_count(option, value)?));
+                }
+                "tail" => {
+                    options = options.tail(Some(parse_count(option, value)?));
+                }
+                #[cfg(feature = "terminal")]
+                "wrap" if value == Some("auto") => {
+                    options = options.wrap(None);
+                    wrap_auto = true;
+                }
+                "wrap" => {
+                    options = options.wrap(Some(parse_count(option, value)?));
+                    #[cfg(feature = "terminal")]
+                    {
+                        wrap_auto = false;
+                    }
+                }
+                "line-terminator" => {
+                    let value =
+                        value.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?;
+                    let terminator = match value {
+                        "NUL" => "\0",
+                        "LF" => "\n",
+                        _ => return Err(invalid_argument(option, value)),
+                    };
+                    options = options.line_terminator(terminator);
+                }
+                "ensure-trailing-newline" => {
+                    options = options.ensure_trailing_newline(true);
+                }
+                #[cfg(feature = "encoding")]
+                "output-encoding" => {
+                    options = options.output_encoding(parse_output_encoding(option, value)?);
+                }
+                #[cfg(feature = "encoding")]
+                "unmappable" => {
+                    options = options.unmappable(parse_unmappable(option, value)?);
+                }
+                "strip-ansi" => {
+                    options = options.strip_ansi(true);
+                }
+                "trim-trailing" => {
+                    options = options.trim_trailing(true);
+                }
+                "interpret-escapes" => {
+                    options = options.interpret_escapes(true);
+                }
+                "hex" => {
+                    options = options.hex_dump(true);
+                }
+                "json" => {
+                    options = options.json(true);
+                }
+                "stats" => {
+                    stats = true;
+                }
+                "count" => {
+                    count = true;
+                }
+                "checksum" => {
+                    checksum = Some(parse_checksum(option, value)?);
+                }
+                "split-lines" => match parse_count(option, value)? {
+                    0 => return Err(invalid_argument(option, "0")),
+                    lines => split_lines = Some(lines),
+                },
+                "output-prefix" => {
+                    output_prefix =
+                        path.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?;
+                }
+                "tee" => {
+                    tee = Some(
+                        path.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?,
+                    );
+                }
+                "files-from" | "files0-from" => {
+                    let list =
+                        path.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?;
+                    let separator = if option == "files0-from" {
+                        b'\0'
+                    } else {
+                        b'\n'
+                    };
+                    files_from = Some((list, separator));
+                }
+                "fd" => {
+                    fd = Some(parse_fd(option, value)?);
+                }
+                "with-filename" => {
+                    options = options.with_filename(true);
+                }
+                "headers" => {
+                    options = options.headers(true);
+                }
+                "follow" => {
+                    options = options.follow(true);
+                    // A file read as it grows can't be decompressed
+                    #[cfg(feature = "gzip")]
+                    {
+                        options = options.decompress(Compression::None);
+                    }
+                }
+                "binary-check" => {
+                    options = options.binary_check(true);
+                }
+                "progress" => {
+                    options = options.progress(true);
+                }
+                "check" => {
+                    options = options.check(true);
+                }
+                "flush" => {
+                    options = options.flush(parse_flush(option, value)?);
+                }
+                "invalid" => {
+                    // Only the UTF-8 aware mode reads the input as UTF-8
+                    options = options
+                        .unicode_aware(true)
+                        .invalid_utf8(parse_invalid(option, value)?);
+                }
+                "show-lf" => {
+                    options = options.show_lf(Some(parse_show_lf(option, value)?));
+                }
+                "offsets" => {
+                    options = options.offsets(Some(parse_offsets(option, value)?));
+                }
+                "color" => {
+                    options = options.color(parse_color(option, value)?);
+                }
+                "help" => {
+                    return Err(ParseError::Help {
+                        color: options.color,
+                    })
+                }
+                "version" => {
+                    return Err(ParseError::Version {
+                        color: options.color,
+                    })
+                }
+                _ => unreachable!("unhandled long option --{option}"),
+            }
+        } else if arg.starts_with('-') && arg != "-" {
+            for c in arg.chars().skip(1) {
+                match c {
+                    'A' => {
+                        options = options.show_all();
+                    }
+                    'b' => {
+                        options = options.stack_number(NumberingMode::NonEmpty);
+                    }
+                    'e' => {
+                        options = options.show_nonprinting(true).show_ends(true);
+                    }
+                    'E' => {
+                        options = options.show_ends(true);
+                    }
+                    'n' => {
+                        options = options.stack_number(NumberingMode::All);
+                    }
+                    's' => {
+                        options = options.squeeze_blank(true);
+                    }
+                    't' => {
+                        options = options.show_nonprinting(true).show_tabs(true);
+                    }
+                    'T' => {
+                        options = options.show_tabs(true);
+                    }
+                    'u' => {
+                        // Ignored
+                    }
+                    'v' => {
+                        options = options.show_nonprinting(true);
+                    }
+                    'h' => {
+                        return Err(ParseError::Help {
+                            color: options.color,
+                        })
+                    }
+                    'V' => {
+                        return Err(ParseError::Version {
+                            color: options.color,
+                        })
+                    }
+                    _ => return Err(ParseError::InvalidOption(arg)),
+                }
+            }
+        } else {
+            file_paths.push(arg.into());
+        }
+    }
+    if files_from.is_some() || fd.is_some() {
+        if let Some(file) = file_paths.into_iter().next() {
+            return Err(ParseError::ExtraOperand(
+                file.to_string_lossy().into_owned(),
+            ));
+        }
+        file_paths = Vec::new();
+    } else if file_paths.is_empty() {
+        file_paths.push("-".into());
+    }
+    Ok(Args {
+        files: file_paths,
+        options,
+        stats,
+        count,
+        files_from,
+        fd,
+        checksum,
+        split_lines,
+        output_prefix,
+        tee,
+        #[cfg(feature = "terminal")]
+        wrap_auto,
+    })
+}
+
+/// Resolve a possibly abbreviated long option to its full name. Exact matches
+/// always win.
+fn resolve_long_option(arg: &str, option: &str) -> Result<&'static str, ParseError> {
+    if let Some(exact) = LONG_OPTIONS.iter().find(|long| **long == option) {
+        return Ok(exact);
+    }
+    let candidates: Vec<&'static str> = LONG_OPTIONS
+        .iter()
+        .copied()
+        .filter(|long| long.starts_with(option))
+        .collect();
+    match candidates[..] {
+        [long] => Ok(long),
+        [] => Err(ParseError::InvalidOption(arg.to_string())),
+        _ => Err(ParseError::AmbiguousOption {
+            option: option.to_string(),
+            candidates,
+        }),
+    }
+}
+
+/// Parse a `--show-lf` value, where no value means `break`
+fn parse_show_lf(option: &str, value: Option<&str>) -> Result<ShowLf, ParseError> {
+    match value {
+        None | Some("break") => Ok(ShowLf::Break),
+        Some("inline") => Ok(ShowLf::Inline),
+        Some(value) => Err(invalid_argument(option, value)),
+    }
+}
+
+/// Parse an `--offsets` value, where no value means `dec`
+fn parse_offsets(option: &str, value: Option<&str>) -> Result<OffsetFormat, ParseError> {
+    match value {
+        None | Some("dec") => Ok(OffsetFormat::Decimal),
+        Some("hex") => Ok(OffsetFormat::Hex),
+        Some(value) => Err(invalid_argument(option, value)),
+    }
+}
+
+/// Parse a `--nul` value: `keep`, `caret` or `replace:CHAR`
+fn parse_nul(option: &str, value: Option<&str>) -> Result<NulMode, ParseError> {
+    let value = value.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?;
+    match value {
+        "keep" => Ok(NulMode::Keep),
+        "caret" => Ok(NulMode::Caret),
+        _ => {
+            let mut chars = value.strip_prefix("replace:").unwrap_or_default().chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(NulMode::Replace(c)),
+                _ => Err(invalid_argument(option, value)),
+            }
+        }
+    }
+}
+
+/// Parse an `--invalid` value: `escape`, `replace` or `skip`
+fn parse_invalid(option: &str, value: Option<&str>) -> Result<InvalidUtf8, ParseError> {
+    match value {
+        Some("escape") => Ok(InvalidUtf8::Escape),
+        Some("replace") => Ok(InvalidUtf8::Replace),
+        Some("skip") => Ok(InvalidUtf8::Skip),
+        Some(value) => Err(invalid_argument(option, value)),
+        None => Err(ParseError::MissingArgument(format!("--{option}"))),
+    }
+}
+
+/// Parse a `--color` value, where no value means `always`
+fn parse_color(option: &str, value: Option<&str>) -> Result<ColorChoice, ParseError> {
+    match value {
+        None | Some("always") => Ok(ColorChoice::Always),
+        Some("auto") => Ok(ColorChoice::Auto),
+        Some("never") => Ok(ColorChoice::Never),
+        Some(value) => Err(invalid_argument(option, value)),
+    }
+}
+
+/// Parse the value of `--flush`
+fn parse_flush(option: &str, value: Option<&str>) -> Result<FlushMode, ParseError> {
+    match value {
+        Some("never") => Ok(FlushMode::Never),
+        Some("line") => Ok(FlushMode::Line),
+        Some("block") => Ok(FlushMode::Block),
+        Some(value) => Err(invalid_argument(option, value)),
+        None => Err(ParseError::MissingArgument(format!("--{option}"))),
+    }
+}
+
+/// Parse the value of `--checksum`
+fn parse_checksum(option: &str, value: Option<&str>) -> Result<Checksum, ParseError> {
+    match value {
+        Some("crc32") => Ok(Checksum::Crc32),
+        #[cfg(feature = "sha256")]
+        Some("sha256") => Ok(Checksum::Sha256),
+        Some(value) => Err(invalid_argument(option, value)),
+        None => Err(ParseError::MissingArgument(format!("--{option}"))),
+    }
+}
+
+/// Parse the value of `--output-encoding`
+#[cfg(feature = "encoding")]
+fn parse_output_encoding(option: &str, value: Option<&str>) -> Result<OutputEncoding, ParseError> {
+    match value {
+        Some("utf-8") => Ok(OutputEncoding::Utf8),
+        Some("latin1") => Ok(OutputEncoding::Latin1),
+        Some("utf-16le") => Ok(OutputEncoding::Utf16Le),
+        Some(value) => Err(invalid_argument(option, value)),
+        None => Err(ParseError::MissingArgument(format!("--{option}"))),
+    }
+}
+
+/// Parse the value of `--unmappable`
+#[cfg(feature = "encoding")]
+fn parse_unmappable(option: &str, value: Option<&str>) -> Result<Unmappable, ParseError> {
+    match value {
+        Some("drop") => Ok(Unmappable::Drop),
+        Some("replace") => Ok(Unmappable::Replace),
+        Some(value) => Err(invalid_argument(option, value)),
+        None => Err(ParseError::MissingArgument(format!("--{option}"))),
+    }
+}
+
+/// Parse the value of a long option as a count
+fn parse_count(option: &str, value: Option<&str>) -> Result<usize, ParseError> {
+    let value = value.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?;
+    value.parse().map_err(|_| invalid_argument(option, value))
+}
+
+/// Parse a file descriptor number for `--fd`
+fn parse_fd(option: &str, value: Option<&str>) -> Result<i32, ParseError> {
+    let value = value.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?;
+    match value.parse() {
+        Ok(fd) if fd >= 0 => Ok(fd),
+        _ => Err(invalid_argument(option, value)),
+    }
+}
+
+/// Parse a `N,M` line range, where either end may be empty
+fn parse_range(
+    option: &str,
+    value: Option<&str>,
+) -> Result<(Option<usize>, Option<usize>), ParseError> {
+    let value = value.ok_or_else(|| ParseError::MissingArgument(format!("--{option}")))?;
+    let (start, end) = value.split_once(',').unwrap_or((value, value));
+    let bound = |bound: &str| match bound {
+        "" => Ok(None),
+        _ => bound.parse().map(Some),
+    };
+    match (bound(start), bound(end)) {
+        (Ok(start), Ok(end)) => Ok((start, end)),
+        _ => Err(invalid_argument(option, value)),
+    }
+}
+
+fn invalid_argument(option: &str, value: &str) -> ParseError {
+    ParseError::InvalidArgument {
+        option: format!("--{option}"),
+        value: value.to_string(),
+    }
+}
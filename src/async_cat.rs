@@ -0,0 +1,92 @@
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+use crate::cat;
+use crate::cat_buffer;
+use crate::finish;
+use crate::limit::LimitState;
+use crate::CatResult;
+use crate::Options;
+use crate::State;
+use crate::FAST_BUFFER_SIZE;
+use crate::LINES_BUFFER_SIZE;
+
+/// Like [`cat`](crate::cat), but read and write with `tokio`.
+///
+/// Input is formatted as it streams in, except with options that need to see
+/// more than one buffer at a time (a non-UTF-8 `encoding`, `strip_bom`,
+/// `skip_bytes`, `cr_is_eol`, `line_ending`, `decompress`, `output_encoding`,
+/// `hex_dump`, `reverse_lines`, `head` and `tail`). With those, the whole input
+/// is read into memory and formatted at once. With `max_bytes`, the rest of the
+/// input is not read once the output is full.
+pub async fn cat_async<R, W>(input: &mut R, output: &mut W, options: &Options) -> CatResult<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if !options.can_stream() {
+        let mut inbuf = Vec::new();
+        input.read_to_end(&mut inbuf).await?;
+        let mut outbuf = Vec::new();
+        cat(&mut inbuf.as_slice(), &mut outbuf, options)?;
+        output.write_all(&outbuf).await?;
+        output.flush().await?;
+        return Ok(());
+    }
+
+    let fast = options.can_write_fast();
+    let buffer_size = if fast {
+        FAST_BUFFER_SIZE
+    } else {
+        LINES_BUFFER_SIZE
+    };
+    let mut inbuf = vec![0; options.buffer_size_or(buffer_size)];
+    let mut outbuf = Vec::new();
+    let mut state = State::new(options);
+    // Output kept within `max_bytes`, waiting to be written
+    let mut limit = options.max_bytes.map(LimitState::new);
+    let mut limited = Vec::new();
+    loop {
+        let n = match input.read(&mut inbuf).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let formatted = if fast {
+            &inbuf[..n]
+        } else {
+            cat_buffer(&inbuf[..n], &mut outbuf, options, &mut state)?;
+            &outbuf[..]
+        };
+        match &mut limit {
+            Some(limit) => {
+                limit.write(&mut limited, options.line_end(), formatted)?;
+                output.write_all(&limited).await?;
+                limited.clear();
+            }
+            None => output.write_all(formatted).await?,
+        }
+        outbuf.clear();
+        if limit.as_ref().is_some_and(LimitState::is_full) {
+            break;
+        }
+        if !fast && options.past_line_range(state.input_line) {
+            break;
+        }
+    }
+    finish(&mut outbuf, options, &mut state)?;
+    match limit {
+        Some(mut limit) => {
+            limit.write(&mut limited, options.line_end(), &outbuf)?;
+            limit.finish(&mut limited, options.line_end())?;
+            output.write_all(&limited).await?;
+        }
+        None => output.write_all(&outbuf).await?,
+    }
+    output.flush().await?;
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::io::Write;
+
+#[cfg(feature = "sha256")]
+use sha2::Digest;
+
+/// A hash of the output, for `--checksum`
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Checksum {
+    /// CRC-32, as used by gzip and zip
+    Crc32,
+    /// SHA-256
+    #[cfg(feature = "sha256")]
+    Sha256,
+}
+
+/// The running state of a [`Checksum`]
+#[derive(Clone)]
+enum Hasher {
+    Crc32(crc32fast::Hasher),
+    #[cfg(feature = "sha256")]
+    Sha256(sha2::Sha256),
+}
+
+/// A writer that hashes the bytes successfully written through it
+pub struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    /// Create a writer that passes everything on to `inner`, hashing it with
+    /// `checksum`
+    pub fn new(inner: W, checksum: Checksum) -> Self {
+        let hasher = match checksum {
+            Checksum::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            #[cfg(feature = "sha256")]
+            Checksum::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+        };
+        Self { inner, hasher }
+    }
+
+    /// The hash of everything written so far, in lowercase hex
+    pub fn digest(&self) -> String {
+        match self.hasher.clone() {
+            Hasher::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+            #[cfg(feature = "sha256")]
+            Hasher::Sha256(hasher) => hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        }
+    }
+
+    /// Return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        match &mut self.hasher {
+            Hasher::Crc32(hasher) => hasher.update(&buf[..n]),
+            #[cfg(feature = "sha256")]
+            Hasher::Sha256(hasher) => hasher.update(&buf[..n]),
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
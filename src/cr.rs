@@ -0,0 +1,65 @@
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+
+/// A reader that translates the carriage returns in line endings.
+///
+/// With `lone_cr_is_eol`, a carriage return not followed by a line feed becomes
+/// a line feed, so that classic Mac line endings read as Unix ones. With
+/// `strip_crlf`, the carriage return of each `\r\n` is removed.
+pub(crate) struct CrReader<R: Read> {
+    inner: BufReader<R>,
+    lone_cr_is_eol: bool,
+    strip_crlf: bool,
+}
+
+impl<R: Read> CrReader<R> {
+    pub(crate) fn new(inner: R, lone_cr_is_eol: bool, strip_crlf: bool) -> Self {
+        Self {
+            inner: BufReader::new(inner),
+            lone_cr_is_eol,
+            strip_crlf,
+        }
+    }
+}
+
+impl<R: Read> Read for CrReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let available = self.inner.fill_buf()?;
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            self.inner.consume(n);
+
+            // Translate in place, writing the kept bytes to the front of `buf`
+            let mut kept = 0;
+            for i in 0..n {
+                let byte = buf[i];
+                if byte == b'\r' {
+                    let next = if i + 1 < n {
+                        Some(buf[i + 1])
+                    } else {
+                        // Peek at the byte after the end of `buf` without consuming it
+                        self.inner.fill_buf()?.first().copied()
+                    };
+                    if next == Some(b'\n') {
+                        if self.strip_crlf {
+                            continue;
+                        }
+                    } else if self.lone_cr_is_eol {
+                        buf[kept] = b'\n';
+                        kept += 1;
+                        continue;
+                    }
+                }
+                buf[kept] = byte;
+                kept += 1;
+            }
+
+            // Only report the end of the input if there really was nothing left
+            if kept > 0 || n == 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::io::Read;
+
+use crate::Encoding;
+
+/// Size of the chunks read from the underlying reader
+const RAW_BUFFER_SIZE: usize = 1024 * 8;
+
+/// A reader that decodes its input to UTF-8.
+///
+/// A byte order mark at the very start of the input selects the encoding when
+/// it is [`Encoding::Auto`], and is removed from the output whenever it matches
+/// the encoding in use. A UTF-8 byte order mark is only removed for
+/// [`Encoding::Utf8`] if `strip_utf8_bom` is set.
+pub(crate) struct DecodeReader<R: Read> {
+    inner: R,
+    encoding: Encoding,
+
+    /// Whether to remove a UTF-8 byte order mark from UTF-8 input
+    strip_utf8_bom: bool,
+
+    /// Whether the byte order mark still needs to be checked for
+    at_start: bool,
+
+    /// Whether the underlying reader is exhausted
+    eof: bool,
+
+    /// Input bytes that have not been decoded yet
+    raw: Vec<u8>,
+
+    /// Decoded bytes waiting to be read, starting at `decoded_pos`
+    decoded: Vec<u8>,
+    decoded_pos: usize,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub(crate) fn new(inner: R, encoding: Encoding, strip_utf8_bom: bool) -> Self {
+        Self {
+            inner,
+            encoding,
+            strip_utf8_bom,
+            at_start: true,
+            eof: false,
+            raw: Vec::new(),
+            decoded: Vec::new(),
+            decoded_pos: 0,
+        }
+    }
+
+    /// Read another chunk of input and decode as much of it as possible
+    fn fill(&mut self) -> std::io::Result<()> {
+        self.decoded.clear();
+        self.decoded_pos = 0;
+
+        let mut chunk = [0; RAW_BUFFER_SIZE];
+        let n = self.inner.read(&mut chunk)?;
+        if n == 0 {
+            self.eof = true;
+        }
+        self.raw.extend_from_slice(&chunk[..n]);
+
+        if self.at_start {
+            // Wait until there are enough bytes to recognize any byte order mark
+            if self.raw.len() < 3 && !self.eof {
+                return Ok(());
+            }
+            self.detect_bom();
+            self.at_start = false;
+        }
+
+        match self.encoding {
+            Encoding::Utf16Le => self.decode_utf16(u16::from_le_bytes),
+            Encoding::Utf16Be => self.decode_utf16(u16::from_be_bytes),
+            Encoding::Auto | Encoding::Utf8 => std::mem::swap(&mut self.decoded, &mut self.raw),
+        }
+
+        Ok(())
+    }
+
+    /// Resolve the encoding from the byte order mark, and strip the mark
+    fn detect_bom(&mut self) {
+        let bom: &[u8] = match (self.encoding, &self.raw[..]) {
+            (Encoding::Utf8, [0xef, 0xbb, 0xbf, ..]) if self.strip_utf8_bom => &[0xef, 0xbb, 0xbf],
+            (Encoding::Auto, [0xef, 0xbb, 0xbf, ..]) => {
+                self.encoding = Encoding::Utf8;
+                &[0xef, 0xbb, 0xbf]
+            }
+            (Encoding::Auto | Encoding::Utf16Le, [0xff, 0xfe, ..]) => {
+                self.encoding = Encoding::Utf16Le;
+                &[0xff, 0xfe]
+            }
+            (Encoding::Auto | Encoding::Utf16Be, [0xfe, 0xff, ..]) => {
+                self.encoding = Encoding::Utf16Be;
+                &[0xfe, 0xff]
+            }
+            (Encoding::Auto, _) => {
+                self.encoding = Encoding::Utf8;
+                &[]
+            }
+            _ => &[],
+        };
+        self.raw.drain(..bom.len());
+    }
+
+    /// Decode the complete UTF-16 code units in `raw`, keeping any incomplete
+    /// unit or unpaired high surrogate for the next read
+    fn decode_utf16(&mut self, from_bytes: fn([u8; 2]) -> u16) {
+        let mut units = self
+            .raw
+            .chunks_exact(2)
+            .map(|b| from_bytes([b[0], b[1]]))
+            .collect::<Vec<_>>();
+        let mut keep = self.raw.len() % 2;
+        if !self.eof && matches!(units.last(), Some(0xd800..=0xdbff)) {
+            units.pop();
+            keep += 2;
+        }
+
+        let mut utf8 = [0; 4];
+        for c in char::decode_utf16(units) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            self.decoded
+                .extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+        }
+        if self.eof && keep > 0 {
+            // A dangling odd byte at the end of the input
+            self.decoded.extend_from_slice(
+                char::REPLACEMENT_CHARACTER
+                    .encode_utf8(&mut utf8)
+                    .as_bytes(),
+            );
+            keep = 0;
+        }
+        self.raw.drain(..self.raw.len() - keep);
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.decoded_pos == self.decoded.len() {
+            if self.eof && self.raw.is_empty() {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+
+        let n = buf.len().min(self.decoded.len() - self.decoded_pos);
+        buf[..n].copy_from_slice(&self.decoded[self.decoded_pos..self.decoded_pos + n]);
+        self.decoded_pos += n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::Write;
+
+use crate::OutputEncoding;
+use crate::Unmappable;
+
+/// A writer that re-encodes UTF-8 written through it.
+///
+/// Bytes that are not valid UTF-8, and characters the encoding has no room
+/// for, are handled as set by `unmappable`. A UTF-8 sequence split across
+/// writes is held back until the next one, so call [`finish`](Self::finish)
+/// at the end.
+pub(crate) struct EncodeWriter<W: Write> {
+    inner: W,
+    encoding: OutputEncoding,
+    unmappable: Unmappable,
+
+    /// The start of a UTF-8 sequence cut off by the end of the last write
+    partial: Vec<u8>,
+
+    /// Encoded bytes waiting to be written
+    encoded: Vec<u8>,
+}
+
+impl<W: Write> EncodeWriter<W> {
+    pub(crate) fn new(inner: W, encoding: OutputEncoding, unmappable: Unmappable) -> Self {
+        Self {
+            inner,
+            encoding,
+            unmappable,
+            partial: Vec::new(),
+            encoded: Vec::new(),
+        }
+    }
+
+    /// Write out a UTF-8 sequence left incomplete at the end of the input
+    pub(crate) fn finish(mut self) -> std::io::Result<()> {
+        if !self.partial.is_empty() {
+            self.encode_unmappable();
+            self.partial.clear();
+            self.inner.write_all(&self.encoded)?;
+        }
+        self.inner.flush()
+    }
+
+    fn encode_char(&mut self, c: char) {
+        match self.encoding {
+            OutputEncoding::Utf8 => {
+                let mut buf = [0; 4];
+                self.encoded
+                    .extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            OutputEncoding::Latin1 => match u8::try_from(c) {
+                Ok(byte) => self.encoded.push(byte),
+                Err(_) => self.encode_unmappable(),
+            },
+            OutputEncoding::Utf16Le => {
+                let mut buf = [0; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    self.encoded.extend_from_slice(&unit.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    /// Write the replacement for something that can't be encoded, if any
+    fn encode_unmappable(&mut self) {
+        if self.unmappable == Unmappable::Drop {
+            return;
+        }
+        match self.encoding {
+            OutputEncoding::Latin1 => self.encoded.push(b'?'),
+            OutputEncoding::Utf8 | OutputEncoding::Utf16Le => {
+                self.encode_char(char::REPLACEMENT_CHARACTER)
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let joined;
+        let input = if self.partial.is_empty() {
+            buf
+        } else {
+            self.partial.extend_from_slice(buf);
+            joined = std::mem::take(&mut self.partial);
+            &joined[..]
+        };
+
+        self.encoded.clear();
+        let mut chunks = input.utf8_chunks().peekable();
+        while let Some(chunk) = chunks.next() {
+            for c in chunk.valid().chars() {
+                self.encode_char(c);
+            }
+            let invalid = chunk.invalid();
+            let incomplete = chunks.peek().is_none()
+                && std::str::from_utf8(invalid).is_err_and(|e| e.error_len().is_none());
+            if incomplete {
+                self.partial.extend_from_slice(invalid);
+            } else if !invalid.is_empty() {
+                self.encode_unmappable();
+            }
+        }
+        self.inner.write_all(&self.encoded)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
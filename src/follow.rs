@@ -0,0 +1,112 @@
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::open_file;
+use crate::prefix::PrefixWriter;
+use crate::stats::CountingWriter;
+use crate::CatFilesError;
+use crate::CatState;
+use crate::CatStats;
+use crate::Options;
+use crate::LINES_BUFFER_SIZE;
+
+/// How long to wait before looking for more data
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Whether a file is being followed, for [`stop_following`] to stop
+static FOLLOWING: AtomicBool = AtomicBool::new(false);
+
+/// Make the file being followed for `follow` finish once it has written what
+/// it has read, and return. Returns whether a file was being followed; one
+/// followed later is not stopped. Only sets flags, so it is safe to call from
+/// a signal handler.
+pub fn stop_following() -> bool {
+    STOP.store(true, Ordering::Relaxed);
+    FOLLOWING.load(Ordering::Relaxed)
+}
+
+/// Format the file at `path` through a single [`CatWriter`](crate::CatWriter),
+/// then keep polling it for appended data until [`stop_following`] is called.
+/// A file that shrinks was truncated, so it is read again from the start. The
+/// formatting carries on from `format` if given.
+pub(crate) fn follow_path<W: Write>(
+    path: &Path,
+    output: &mut W,
+    options: &Options,
+    format: Option<CatState>,
+) -> Result<CatStats, CatFilesError> {
+    STOP.store(false, Ordering::Relaxed);
+    FOLLOWING.store(true, Ordering::Relaxed);
+    let result = follow_file(path, output, options, format);
+    FOLLOWING.store(false, Ordering::Relaxed);
+
+    result
+}
+
+fn follow_file<W: Write>(
+    path: &Path,
+    output: &mut W,
+    options: &Options,
+    format: Option<CatState>,
+) -> Result<CatStats, CatFilesError> {
+    let io_error = |source| CatFilesError::Io {
+        path: path.to_string_lossy().into_owned(),
+        source,
+    };
+    let mut file = open_file(path)?;
+    let mut stats = CatStats::default();
+    let mut output = CountingWriter::new(output);
+    let prefix = format!("{}:", path.to_string_lossy());
+    let mut prefixed;
+    let formatted: &mut dyn Write = if options.with_filename {
+        prefixed = PrefixWriter::new(&mut output, prefix.as_bytes(), options.line_end());
+        &mut prefixed
+    } else {
+        &mut output
+    };
+    let mut writer = options.writer(formatted);
+    if let Some(format) = format {
+        writer.state = format.state;
+    }
+    let state = &writer.state;
+    let (lines, blanks_squeezed, numbered) = (state.lines, state.blanks_squeezed, state.numbered);
+    let mut buf = vec![0; options.buffer_size_or(LINES_BUFFER_SIZE)];
+    let mut position = 0;
+    while !STOP.load(Ordering::Relaxed) {
+        match file.read(&mut buf) {
+            Ok(0) => {}
+            Ok(n) => {
+                writer.write_all(&buf[..n])?;
+                stats.bytes_in += n;
+                position += n as u64;
+                continue;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(io_error(e)),
+        }
+        writer.flush()?;
+        std::thread::sleep(POLL_INTERVAL);
+        if file.metadata().map_err(io_error)?.len() < position {
+            position = file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        }
+    }
+    stats.lines = writer.state.lines - lines;
+    stats.blanks_squeezed = writer.state.blanks_squeezed - blanks_squeezed;
+    stats.numbered = writer.state.numbered - numbered;
+    if !writer.state.at_line_start {
+        stats.lines += 1;
+    }
+    writer.finish()?;
+    output.flush()?;
+    stats.bytes_out = output.count;
+
+    Ok(stats)
+}
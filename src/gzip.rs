@@ -0,0 +1,46 @@
+use std::io::Read;
+use std::io::Write;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::cat_uncompressed;
+use crate::CatResult;
+use crate::CatStats;
+use crate::Compression;
+use crate::Options;
+
+/// The magic bytes at the start of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Decompress the input according to `options.decompress`, then format it
+pub(crate) fn cat_compressed<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    cat_uncompressed(
+        &mut decompressed(input, options.decompress)?,
+        output,
+        options,
+    )
+}
+
+/// The input decompressed according to `decompress`
+pub(crate) fn decompressed<'a, R: Read>(
+    input: &'a mut R,
+    decompress: Compression,
+) -> std::io::Result<Box<dyn Read + 'a>> {
+    let mut magic = Vec::with_capacity(GZIP_MAGIC.len());
+    input
+        .by_ref()
+        .take(GZIP_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    let is_gzip = magic == GZIP_MAGIC;
+    let input = std::io::Cursor::new(magic).chain(input);
+
+    Ok(match decompress {
+        Compression::Gzip => Box::new(MultiGzDecoder::new(input)),
+        Compression::Auto if is_gzip => Box::new(MultiGzDecoder::new(input)),
+        Compression::Auto | Compression::None => Box::new(input),
+    })
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use owo_colors::AnsiColors;
+use owo_colors::DynColor;
+use regex::bytes::Regex;
+
+use crate::options::RESET_COLOR;
+
+/// A pattern to color wherever it matches, for
+/// [`Options::highlight`](crate::Options::highlight)
+#[derive(Debug, Clone)]
+pub struct Highlight {
+    /// What to color
+    pub pattern: Regex,
+
+    /// The color to use
+    pub color: AnsiColors,
+}
+
+impl Highlight {
+    /// Create a new `Highlight` coloring matches of `pattern` with `color`
+    pub fn new(pattern: Regex, color: AnsiColors) -> Self {
+        Self { pattern, color }
+    }
+
+    /// Highlight numbers, such as `42`, `-1.5` and `0x1f`
+    pub fn numbers(color: AnsiColors) -> Self {
+        let pattern = Regex::new(r"\b0x[0-9a-fA-F]+\b|-?\b\d+(\.\d+)?\b").expect("valid pattern");
+        Self::new(pattern, color)
+    }
+
+    /// Highlight URLs, such as `https://example.com/`
+    pub fn urls(color: AnsiColors) -> Self {
+        let pattern =
+            Regex::new(r#"\b[a-zA-Z][a-zA-Z0-9+.-]*://[^\s<>"']+"#).expect("valid pattern");
+        Self::new(pattern, color)
+    }
+}
+
+// Patterns are compared by their source, since compiled ones can't be
+impl PartialEq for Highlight {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str() && self.color == other.color
+    }
+}
+
+/// Displays the escape sequence that starts a foreground color
+struct Foreground(AnsiColors);
+
+impl fmt::Display for Foreground {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_ansi_fg(f)
+    }
+}
+
+/// Color the matches of `highlights` in the formatted text of part of a line.
+/// Escape sequences, such as the colors of `show_tabs` markers, are left out of
+/// the matching, and so is the text they color, which a match would otherwise
+/// end the color of.
+pub(crate) fn highlight(text: &[u8], highlights: &[Highlight]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(text.len());
+    let mut colored = false;
+    let mut rest = text;
+    loop {
+        let plain = rest.iter().position(|c| *c == 0x1b).unwrap_or(rest.len());
+        if colored {
+            output.extend_from_slice(&rest[..plain]);
+        } else {
+            highlight_plain(&rest[..plain], highlights, &mut output);
+        }
+        rest = &rest[plain..];
+        if rest.is_empty() {
+            return output;
+        }
+        let escape = &rest[..escape_len(rest)];
+        if let Some(sgr) = escape
+            .strip_prefix(b"\x1b[")
+            .and_then(|sgr| sgr.strip_suffix(b"m"))
+        {
+            colored = !matches!(sgr, b"" | b"0");
+        }
+        output.extend_from_slice(escape);
+        rest = &rest[escape.len()..];
+    }
+}
+
+/// The length of the escape sequence at the start of `text`: a CSI sequence up
+/// to its final byte, or ESC and the byte after it otherwise. One cut off by the
+/// end of `text` takes the rest of it.
+fn escape_len(text: &[u8]) -> usize {
+    if text.get(1) != Some(&b'[') {
+        return text.len().min(2);
+    }
+    text[2..]
+        .iter()
+        .position(|c| !(0x20..=0x3f).contains(c))
+        .map_or(text.len(), |i| {
+            // A byte that can't end the sequence isn't part of it
+            2 + i + usize::from((0x40..=0x7e).contains(&text[2 + i]))
+        })
+}
+
+/// Color the matches of `highlights` in `text`, which holds no escape
+/// sequences, appending it to `output`. Where matches overlap, the one that
+/// starts first wins, then the one whose highlight comes first.
+fn highlight_plain(text: &[u8], highlights: &[Highlight], output: &mut Vec<u8>) {
+    let mut matches = Vec::new();
+    for highlight in highlights {
+        for found in highlight.pattern.find_iter(text) {
+            if !found.is_empty() {
+                matches.push((found.start(), found.end(), highlight.color));
+            }
+        }
+    }
+    // Stable, so highlights keep their order among matches at the same place
+    matches.sort_by_key(|(start, _, _)| *start);
+
+    let mut pos = 0;
+    for (start, end, color) in matches {
+        if start < pos {
+            continue;
+        }
+        output.extend_from_slice(&text[pos..start]);
+        output.extend_from_slice(Foreground(color).to_string().as_bytes());
+        output.extend_from_slice(&text[start..end]);
+        output.extend_from_slice(RESET_COLOR.as_bytes());
+        pos = end;
+    }
+    output.extend_from_slice(&text[pos..]);
+}
@@ -0,0 +1,92 @@
+use std::io::Write;
+
+use crate::CatResult;
+use crate::FlushMode;
+use crate::NumberingMode;
+use crate::Options;
+use crate::State;
+
+/// Write one whole input line as a JSON object, for `json`: `text` is the line
+/// without its line feed, `n` its line number if numbering is on, and `offset`
+/// its offset in the input with `offsets`, always in decimal.
+/// `line_range` and `squeeze_blank` drop lines as they would otherwise.
+pub(crate) fn format_json_line<W: Write>(
+    line: &[u8],
+    output: &mut W,
+    options: &Options,
+    state: &mut State,
+) -> CatResult<()> {
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    let blank = content.is_empty();
+    let numbered = match options.number {
+        NumberingMode::All => true,
+        NumberingMode::NonEmpty => !blank,
+        NumberingMode::None => false,
+    };
+    let input_line = state.input_line;
+    state.input_line += 1;
+    if !options.in_line_range(input_line) {
+        if numbered {
+            state.line_number += 1;
+        }
+        return Ok(());
+    }
+    if blank {
+        if options.squeeze_blank && state.blank_run >= options.max_blank {
+            state.blanks_squeezed += 1;
+            return Ok(());
+        }
+        state.blank_run += 1;
+    } else {
+        state.blank_run = 0;
+    }
+
+    output.write_all(b"{")?;
+    if options.offsets.is_some() {
+        write!(output, "\"offset\": {}, ", state.line_offset)?;
+    }
+    if numbered {
+        write!(output, "\"n\": {}, ", state.line_number)?;
+        state.line_number += 1;
+        state.numbered += 1;
+    }
+    output.write_all(b"\"text\": ")?;
+    write_json_string(output, &String::from_utf8_lossy(content))?;
+    output.write_all(b"}")?;
+    output.write_all(options.newline().as_bytes())?;
+    state.lines += 1;
+    if options.flush == FlushMode::Line {
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Write `text` as a quoted JSON string
+fn write_json_string<W: Write>(output: &mut W, text: &str) -> CatResult<()> {
+    output.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        let escape = match c {
+            '"' => Some("\\\""),
+            '\\' => Some("\\\\"),
+            '\n' => Some("\\n"),
+            '\r' => Some("\\r"),
+            '\t' => Some("\\t"),
+            '\u{8}' => Some("\\b"),
+            '\u{c}' => Some("\\f"),
+            '\0'..='\u{1f}' => None,
+            _ => continue,
+        };
+        output.write_all(&text.as_bytes()[start..i])?;
+        start = i + 1;
+        match escape {
+            Some(escape) => output.write_all(escape.as_bytes())?,
+            None => write!(output, "\\u{:04x}", c as u32)?,
+        }
+    }
+    output.write_all(&text.as_bytes()[start..])?;
+    output.write_all(b"\"")?;
+
+    Ok(())
+}
@@ -1,12 +1,99 @@
+mod args;
+#[cfg(feature = "async")]
+mod async_cat;
+mod checksum;
+mod cr;
+mod decode;
+#[cfg(feature = "encoding")]
+mod encode;
+mod follow;
+#[cfg(feature = "gzip")]
+mod gzip;
+#[cfg(feature = "regex")]
+mod highlight;
+mod json;
+mod limit;
+mod lines;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod nonprint;
 mod options;
+mod parallel;
+mod prefix;
+mod progress;
+mod resume;
+mod split;
+mod stats;
+mod tee;
+mod writer;
 
-use std::borrow::Borrow;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::IoSlice;
 use std::io::Read;
 use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
 
+pub use args::parse_args;
+pub use args::Args;
+pub use args::ParseError;
+#[cfg(feature = "async")]
+pub use async_cat::cat_async;
+pub use checksum::Checksum;
+pub use checksum::ChecksumWriter;
+use cr::CrReader;
+use decode::DecodeReader;
+#[cfg(feature = "encoding")]
+use encode::EncodeWriter;
+pub use follow::stop_following;
+#[cfg(feature = "regex")]
+pub use highlight::Highlight;
+use limit::LimitReader;
+use limit::LimitWriter;
+pub use lines::CatLines;
+use nonprint::NonprintTable;
+pub use options::Align;
+pub use options::ColorChoice;
+#[cfg(feature = "gzip")]
+pub use options::Compression;
+pub use options::Encoding;
+pub use options::FlushMode;
+pub use options::InvalidUtf8;
+pub use options::NulMode;
 pub use options::NumberingMode;
+pub use options::OffsetFormat;
 pub use options::Options;
+pub use options::OptionsError;
+#[cfg(feature = "encoding")]
+pub use options::OutputEncoding;
+pub use options::OutputLineEnding;
+pub use options::ShowLf;
+#[cfg(feature = "encoding")]
+pub use options::Unmappable;
+use options::MARKER_COLOR;
+use options::NUMBER_COLOR;
+use options::RESET_COLOR;
+pub use parallel::cat_files_parallel;
+pub use parallel::cat_files_parallel_to;
+use prefix::PrefixWriter;
+use progress::ProgressReader;
+pub use resume::cat_with_state;
+pub use resume::CatState;
+pub use split::cat_files_split;
+pub use split::SplitWriter;
+pub use stats::CatStats;
+use stats::CountingWriter;
+use stats::LineEndWriter;
+pub use tee::cat_files_tee;
+pub use tee::TeeWriter;
 use thiserror::Error;
+pub use writer::CatWriter;
 
 #[derive(Error, Debug)]
 pub enum CatError {
@@ -16,6 +103,19 @@ pub enum CatError {
 
 pub type CatResult<T> = Result<T, CatError>;
 
+/// Default read buffer size when copying input unchanged
+const FAST_BUFFER_SIZE: usize = 1024 * 64;
+
+/// The number of bytes at the start of a file looked at by `binary_check`
+const BINARY_CHECK_SIZE: usize = 1024 * 8;
+
+/// Default read buffer size when formatting input line by line
+const LINES_BUFFER_SIZE: usize = 1024 * 31;
+
+/// The largest file kept in memory by `cat_files` to write it again when it is
+/// named several times in a row
+const REPEAT_CACHE_SIZE: u64 = 1024 * 1024 * 16;
+
 struct State {
     /// The current line number
     line_number: usize,
@@ -26,20 +126,138 @@ struct State {
     /// Whether we skipped a \r, which still needs to be printed
     skipped_carriage_return: bool,
 
-    /// Whether we have already printed a blank line    
-    one_blank_kept: bool,
+    /// The number of consecutive blank lines printed so far
+    blank_run: usize,
+
+    /// The number of line terminators written so far
+    lines: usize,
+
+    /// The number of lines given a line number so far
+    numbered: usize,
+
+    /// The display column of the output cursor within the current line
+    column: usize,
+
+    /// The display column within the current line for `wrap`, which restarts
+    /// on continuation lines
+    wrap_column: usize,
+
+    /// Whether the current line has only had spaces and tabs so far, for
+    /// `expand_leading`
+    indenting: bool,
+
+    /// The start of a UTF-8 sequence cut off by the end of the last buffer
+    utf8_partial: Vec<u8>,
+
+    /// What `show_nonprinting` writes for each byte, built on first use
+    nonprint: Option<NonprintTable>,
+
+    /// The current line of the input
+    input_line: usize,
+
+    /// The number of bytes in the whole input lines taken so far, for `offsets`
+    input_offset: usize,
+
+    /// The offset in the input of the line being written, for `offsets`
+    line_offset: usize,
+
+    /// Whether the line being skipped for `line_range` has any content
+    skipped_content: bool,
+
+    /// The number of blank lines dropped by `squeeze_blank`
+    blanks_squeezed: usize,
+
+    /// How far into an ANSI escape sequence the end of the last buffer was
+    ansi: AnsiState,
+
+    /// The start of a backslash escape cut off by the end of the last buffer
+    escape_partial: Vec<u8>,
+
+    /// The start of an input line cut off by the end of the last buffer, held
+    /// back until it can be checked against `filter` or highlighted whole
+    filter_partial: Vec<u8>,
+
+    /// The last input line written, or held back for `uniq_count`, for `uniq`
+    /// and `squeeze_repeats`
+    uniq_line: Option<Vec<u8>>,
+
+    /// The number of times `uniq_line` was repeated, for `uniq_count`
+    uniq_count: usize,
+
+    /// The offset in the input of `uniq_line`, for `uniq_count`
+    uniq_offset: usize,
 }
 
-fn cat_fast<R: Read, W: Write>(input: &mut R, output: &mut W, _options: &Options) -> CatResult<()> {
-    let mut buf = [0; 1024 * 64];
-    while let Ok(n) = input.read(&mut buf) {
-        if n == 0 {
-            break;
+/// Progress through an ANSI escape sequence, for `strip_ansi`
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum AnsiState {
+    /// Not in an escape sequence
+    Text,
+    /// After an ESC
+    Escape,
+    /// After `ESC [`, waiting for the final byte
+    Csi,
+}
+
+impl State {
+    fn new(options: &Options) -> Self {
+        Self {
+            line_number: options.number_start,
+            at_line_start: true,
+            skipped_carriage_return: false,
+            blank_run: 0,
+            lines: 0,
+            numbered: 0,
+            column: 0,
+            wrap_column: 0,
+            indenting: true,
+            utf8_partial: Vec::new(),
+            nonprint: None,
+            input_line: 1,
+            input_offset: 0,
+            line_offset: 0,
+            skipped_content: false,
+            blanks_squeezed: 0,
+            ansi: AnsiState::Text,
+            escape_partial: Vec::new(),
+            filter_partial: Vec::new(),
+            uniq_line: None,
+            uniq_count: 0,
+            uniq_offset: 0,
         }
-        output.write(&buf[..n])?;
     }
+}
 
-    Ok(())
+fn cat_fast<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    let mut stats = CatStats::default();
+    let mut ends_with_newline = true;
+    let mut buf = vec![0; options.buffer_size_or(FAST_BUFFER_SIZE)];
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        output.write_all(&buf[..n])?;
+        stats.bytes_in += n;
+        stats.lines += buf[..n].iter().filter(|c| **c == b'\n').count();
+        ends_with_newline = buf[n - 1] == b'\n';
+    }
+    if !ends_with_newline {
+        stats.lines += 1;
+    }
+    stats.bytes_out = stats.bytes_in;
+    if !ends_with_newline && options.ensure_trailing_newline {
+        output.write_all(b"\n")?;
+        stats.bytes_out += 1;
+    }
+
+    Ok(stats)
 }
 
 fn cat_lines<R: Read, W: Write>(
@@ -47,260 +265,4206 @@ fn cat_lines<R: Read, W: Write>(
     output: &mut W,
     options: &Options,
     mut state: State,
-) -> CatResult<()> {
-    let mut inbuf = [0; 1024 * 31];
-    while let Ok(n) = input.read(&mut inbuf) {
+) -> CatResult<CatStats> {
+    if options.head.is_some() || options.tail.is_some() {
+        return cat_limited(input, output, options, state);
+    }
+
+    let mut stats = CatStats::default();
+    let mut inbuf = vec![0; options.buffer_size_or(LINES_BUFFER_SIZE)];
+    loop {
+        let n = match input.read(&mut inbuf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        cat_buffer(&inbuf[..n], output, options, &mut state)?;
+        stats.bytes_in += n;
+        if options.past_line_range(state.input_line) {
+            break;
+        }
+    }
+    finish(output, options, &mut state)?;
+    stats.lines = state.lines;
+    stats.blanks_squeezed = state.blanks_squeezed;
+    stats.numbered = state.numbered;
+    if !state.at_line_start {
+        stats.lines += 1;
+    }
+
+    Ok(stats)
+}
+
+/// Write the input as a hex dump in the style of `hexdump -C`: each row shows
+/// the offset, 16 bytes in hex split into two halves, and the printable ASCII
+/// characters. Other formatting options are ignored.
+fn cat_hex<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    const ROW: usize = 16;
+    let mut stats = CatStats::default();
+    let mut inbuf = vec![0; options.buffer_size_or(LINES_BUFFER_SIZE).max(ROW)];
+    let mut filled = 0;
+    loop {
+        let n = match input.read(&mut inbuf[filled..]) {
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        filled += n;
+        let complete = if n == 0 {
+            filled
+        } else {
+            filled - filled % ROW
+        };
+        for row in inbuf[..complete].chunks(ROW) {
+            write_hex_row(output, stats.bytes_in, row)?;
+            stats.bytes_in += row.len();
+            stats.lines += 1;
+        }
+        inbuf.copy_within(complete..filled, 0);
+        filled -= complete;
         if n == 0 {
             break;
         }
+    }
+    if stats.bytes_in > 0 {
+        writeln!(output, "{:08x}", stats.bytes_in)?;
+        stats.lines += 1;
+    }
 
-        let inbuf = &inbuf[..n];
-        let mut pos = 0;
-        while pos < n {
-            // skip empty line_number, enumerating them if needed
-            if inbuf[pos] == b'\n' {
-                write_new_line(output, options, &mut state)?;
-                state.at_line_start = true;
-                pos += 1;
-                continue;
+    Ok(stats)
+}
+
+fn write_hex_row<W: Write>(output: &mut W, offset: usize, row: &[u8]) -> CatResult<()> {
+    write!(output, "{offset:08x} ")?;
+    for i in 0..16 {
+        if i % 8 == 0 {
+            output.write_all(b" ")?;
+        }
+        match row.get(i) {
+            Some(byte) => write!(output, "{byte:02x} ")?,
+            None => output.write_all(b"   ")?,
+        }
+    }
+    output.write_all(b" |")?;
+    for byte in row {
+        let c = if (32..=126).contains(byte) {
+            *byte
+        } else {
+            b'.'
+        };
+        output.write_all(&[c])?;
+    }
+    output.write_all(b"|\n")?;
+
+    Ok(())
+}
+
+/// Buffer the whole input and format its lines in reverse order.
+///
+/// Like `tac`, each line keeps its own terminator, so a final line without a
+/// trailing newline is written first and runs into the line that follows it.
+fn cat_reverse<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    let mut inbuf = Vec::new();
+    input.read_to_end(&mut inbuf)?;
+
+    let mut reversed = Vec::with_capacity(inbuf.len());
+    for line in inbuf.split_inclusive(|c| *c == b'\n').rev() {
+        reversed.extend_from_slice(line);
+    }
+
+    cat_lines(
+        &mut std::io::Cursor::new(reversed),
+        output,
+        options,
+        State::new(options),
+    )
+}
+
+/// Format the input, writing only the first `head` and/or last `tail` lines of
+/// the output. When both are set, `tail` applies to the lines left by `head`.
+fn cat_limited<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+    state: State,
+) -> CatResult<CatStats> {
+    let mut stats = CatStats::default();
+    let mut lines = CatLines::new(input, options, state);
+    let head = options.head.unwrap_or(usize::MAX);
+    match options.tail {
+        Some(tail) => {
+            let mut last = VecDeque::with_capacity(tail.min(1024));
+            for line in lines.by_ref().take(head) {
+                if last.len() == tail {
+                    last.pop_front();
+                }
+                if tail > 0 {
+                    last.push_back(line?);
+                }
             }
-            if state.skipped_carriage_return {
-                output.write_all(b"\r")?;
-                state.skipped_carriage_return = false;
-                state.at_line_start = false;
+            for line in last {
+                output.write_all(&line)?;
+                stats.lines += 1;
             }
-            state.one_blank_kept = false;
-            if state.at_line_start && options.number != NumberingMode::None {
-                write!(output, "{0:6}\t", state.line_number)?;
-                state.line_number += 1;
+        }
+        None => {
+            for line in lines.by_ref().take(head) {
+                output.write_all(&line?)?;
+                stats.lines += 1;
             }
+        }
+    }
+    stats.bytes_in = lines.bytes_in;
+    stats.blanks_squeezed = lines.state.blanks_squeezed;
+    stats.numbered = lines.state.numbered;
 
-            // print to end of line or end of buffer
-            let offset = write_end(output, &inbuf[pos..], options);
+    Ok(stats)
+}
 
-            // end of buffer?
-            if offset + pos == inbuf.len() {
-                state.at_line_start = false;
-                break;
-            }
+/// Format a single chunk of input, carrying line state over to the next chunk
+fn cat_buffer<W: Write>(
+    inbuf: &[u8],
+    output: &mut W,
+    options: &Options,
+    state: &mut State,
+) -> CatResult<()> {
+    if !options.splits_lines() {
+        return format_buffer(inbuf, output, options, state);
+    }
 
-            if inbuf[pos + offset] == b'\r' {
-                state.skipped_carriage_return = true;
-            } else {
-                debug_assert_eq!(inbuf[pos + offset], b'\n');
-                // print suitable end of line
-                write_end_of_line(output, options.end_of_line().as_bytes())?;
-                state.at_line_start = true;
-            }
-            pos += offset + 1;
+    let mut lines = inbuf.split_inclusive(|c| *c == b'\n').peekable();
+    while let Some(line) = lines.next() {
+        if lines.peek().is_none() && !line.ends_with(b"\n") {
+            state.filter_partial.extend_from_slice(line);
+            break;
+        }
+        if state.filter_partial.is_empty() {
+            format_line(line, output, options, state)?;
+        } else {
+            let mut joined = std::mem::take(&mut state.filter_partial);
+            joined.extend_from_slice(line);
+            format_line(&joined, output, options, state)?;
         }
     }
 
     Ok(())
 }
 
-fn write_new_line<W: Write>(output: &mut W, options: &Options, state: &mut State) -> CatResult<()> {
-    if state.skipped_carriage_return && options.show_ends {
-        output.write_all(b"^M")?;
-        state.skipped_carriage_return = false;
+/// Format one whole input line, unless `filter`, `uniq` or `squeeze_repeats`
+/// drops it
+fn format_line<W: Write>(
+    line: &[u8],
+    output: &mut W,
+    options: &Options,
+    state: &mut State,
+) -> CatResult<()> {
+    // Offsets are of the line as read, before it is trimmed
+    state.line_offset = state.input_offset;
+    state.input_offset += line.len();
+    let trimmed;
+    let line = if options.trim_trailing {
+        trimmed = trim_trailing(line);
+        &trimmed
+    } else {
+        line
+    };
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    if !options.matches_filter(content) {
+        skip_line(content, options, state);
+        return Ok(());
     }
-
-    if !state.at_line_start || !options.squeeze_blank || !state.one_blank_kept {
-        state.one_blank_kept = true;
-        if state.at_line_start && options.number == NumberingMode::All {
-            write!(output, "{0:6}\t", state.line_number)?;
-            state.line_number += 1;
+    let repeated = state
+        .uniq_line
+        .as_deref()
+        .is_some_and(|last| last.strip_suffix(b"\n").unwrap_or(last) == content);
+    if options.squeeze_repeats {
+        if repeated && !(options.squeeze_blank && content.is_empty()) {
+            // Dropped as output, so it takes no line number
+            state.input_line += 1;
+            return Ok(());
         }
-        output.write_all(options.end_of_line().as_bytes())?;
-        output.flush()?;
+        state.uniq_line = Some(line.to_vec());
+        return format_kept_line(line, output, options, state);
+    }
+    if !options.uniq {
+        return format_kept_line(line, output, options, state);
     }
 
-    Ok(())
+    if repeated {
+        if options.uniq_count {
+            state.uniq_count += 1;
+        } else {
+            skip_line(content, options, state);
+        }
+        return Ok(());
+    }
+    if options.uniq_count {
+        write_uniq_run(output, options, state)?;
+        state.uniq_line = Some(line.to_vec());
+        state.uniq_count = 1;
+        state.uniq_offset = state.line_offset;
+        return Ok(());
+    }
+    state.uniq_line = Some(line.to_vec());
+    format_kept_line(line, output, options, state)
 }
 
-fn write_end<W: Write>(output: &mut W, inbuf: &[u8], options: &Options) -> usize {
-    if options.show_nonprinting {
-        write_nonprint_to_end(inbuf, output, options.tab().as_bytes())
-    } else if options.show_tabs {
-        write_tab_to_end(inbuf, output)
+/// Format a whole line that was not dropped by `format_line`
+fn format_kept_line<W: Write>(
+    line: &[u8],
+    output: &mut W,
+    options: &Options,
+    state: &mut State,
+) -> CatResult<()> {
+    if options.json {
+        json::format_json_line(line, output, options, state)
     } else {
-        write_to_end(inbuf, output)
+        format_buffer(line, output, options, state)
     }
 }
 
-// write***_to_end methods
-// Write all symbols till \n or \r or end of buffer is reached
-// We need to stop at \r because it may be written as ^M depending on the byte after and settings;
-// however, write_nonprint_to_end doesn't need to stop at \r because it will always write \r as ^M.
-// Return the number of written symbols
-fn write_to_end<W: Write>(inbuf: &[u8], output: &mut W) -> usize {
-    match inbuf.iter().position(|c| *c == b'\n' || *c == b'\r') {
-        Some(p) => {
-            output.write_all(&inbuf[..p]).unwrap();
-            p
-        }
-        None => {
-            output.write_all(inbuf).unwrap();
-            inbuf.len()
-        }
+/// Drop the spaces and tabs at the end of `line`, before its line ending
+fn trim_trailing(line: &[u8]) -> Cow<'_, [u8]> {
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    let content = content.strip_suffix(b"\r").unwrap_or(content);
+    let end = content
+        .iter()
+        .rposition(|c| *c != b' ' && *c != b'\t')
+        .map_or(0, |p| p + 1);
+    if end == content.len() {
+        return Cow::Borrowed(line);
     }
+    let mut trimmed = line[..end].to_vec();
+    trimmed.extend_from_slice(&line[content.len()..]);
+    Cow::Owned(trimmed)
 }
 
-fn write_tab_to_end<W: Write>(mut inbuf: &[u8], output: &mut W) -> usize {
-    let mut count = 0;
-    loop {
-        match inbuf
-            .iter()
-            .position(|c| *c == b'\n' || *c == b'\t' || *c == b'\r')
-        {
-            Some(p) => {
-                output.write_all(&inbuf[..p]).unwrap();
-                if inbuf[p] == b'\t' {
-                    output.write_all(b"^I").unwrap();
-                    inbuf = &inbuf[p + 1..];
-                    count += p + 1;
-                } else {
-                    // b'\n' or b'\r'
-                    return count + p;
-                }
-            }
-            None => {
-                output.write_all(inbuf).unwrap();
-                return inbuf.len();
-            }
-        };
+/// Count a dropped input line towards line numbering
+fn skip_line(content: &[u8], options: &Options, state: &mut State) {
+    if options.number == NumberingMode::All
+        || (options.number == NumberingMode::NonEmpty && !content.is_empty())
+    {
+        state.line_number += 1;
     }
+    state.input_line += 1;
+}
+
+/// Write the line held back for `uniq_count`, prefixed with its run length
+fn write_uniq_run<W: Write>(output: &mut W, options: &Options, state: &mut State) -> CatResult<()> {
+    if let Some(last) = state.uniq_line.take() {
+        let mut line = format!("{:7} ", state.uniq_count).into_bytes();
+        line.extend_from_slice(&last);
+        // Written once the next different line is read, but at its own offset
+        let offset = std::mem::replace(&mut state.line_offset, state.uniq_offset);
+        format_kept_line(&line, output, options, state)?;
+        state.line_offset = offset;
+    }
+
+    Ok(())
 }
 
-fn write_nonprint_to_end<W: Write>(inbuf: &[u8], output: &mut W, tab: &[u8]) -> usize {
-    let mut count = 0;
+/// Format a chunk of input that has passed `filter` and `uniq`
+fn format_buffer<W: Write>(
+    inbuf: &[u8],
+    output: &mut W,
+    options: &Options,
+    state: &mut State,
+) -> CatResult<()> {
+    let end_of_line = options.end_of_line();
+    let n = inbuf.len();
+    let mut pos = 0;
+    while pos < n {
+        // skip lines outside of line_range, still counting them for numbering
+        if !options.in_line_range(state.input_line) {
+            let Some(p) = inbuf[pos..].iter().position(|c| *c == b'\n') else {
+                state.skipped_content = true;
+                break;
+            };
+            let blank = p == 0 && !state.skipped_content;
+            if options.number == NumberingMode::All
+                || (options.number == NumberingMode::NonEmpty && !blank)
+            {
+                state.line_number += 1;
+            }
+            state.skipped_content = false;
+            state.input_line += 1;
+            pos += p + 1;
+            continue;
+        }
+
+        // skip empty line_number, enumerating them if needed
+        if inbuf[pos] == b'\n' {
+            write_utf8_partial(output, options, state)?;
+            write_ansi_partial(output, &mut state.ansi)?;
+            write_escape_partial(output, &mut state.escape_partial)?;
+            write_new_line(output, options, state, end_of_line.as_bytes())?;
+            state.at_line_start = true;
+            state.column = 0;
+            state.wrap_column = 0;
+            state.indenting = true;
+            state.input_line += 1;
+            pos += 1;
+            continue;
+        }
+        if state.skipped_carriage_return {
+            write_carriage_return(output, options, state, false, false)?;
+        }
+        if inbuf[pos] == b'\r'
+            && state.at_line_start
+            && !options.show_nonprinting
+            && !options.strip_nonprinting
+        {
+            // hold off numbering, since a line that is only \r counts as blank
+            state.skipped_carriage_return = true;
+            pos += 1;
+            continue;
+        }
+        state.blank_run = 0;
+        if state.at_line_start
+            && (options.number != NumberingMode::None || options.offsets.is_some())
+        {
+            write_gutter(output, options, state)?;
+        }
+        state.at_line_start = false;
+
+        // print to end of line or end of buffer
+        let offset = write_end(output, &inbuf[pos..], options, state)?;
 
-    for byte in inbuf.iter().copied() {
-        if byte == b'\n' {
+        // end of buffer?
+        if offset + pos == inbuf.len() {
             break;
         }
-        match byte {
-            9 => output.write_all(tab),
-            0..=8 | 10..=31 => output.write_all(&[b'^', byte + 64]),
-            32..=126 => output.write_all(&[byte]),
-            127 => output.write_all(&[b'^', b'?']),
-            128..=159 => output.write_all(&[b'M', b'-', b'^', byte - 64]),
-            160..=254 => output.write_all(&[b'M', b'-', byte - 128]),
-            _ => output.write_all(&[b'M', b'-', b'^', b'?']),
+
+        if inbuf[pos + offset] == b'\r' {
+            state.skipped_carriage_return = true;
+        } else {
+            debug_assert_eq!(inbuf[pos + offset], b'\n');
+            // print suitable end of line
+            write_end_of_line(output, end_of_line.as_bytes())?;
+            if options.flush == FlushMode::Line {
+                output.flush()?;
+            }
+            state.at_line_start = true;
+            state.column = 0;
+            state.wrap_column = 0;
+            state.indenting = true;
+            state.lines += 1;
+            state.input_line += 1;
         }
-        .unwrap();
-        count += 1;
+        pos += offset + 1;
     }
-    count
+
+    Ok(())
 }
 
-fn write_end_of_line<W: Write>(writer: &mut W, end_of_line: &[u8]) -> CatResult<()> {
-    writer.write_all(end_of_line)?;
-    writer.flush()?;
+/// Write out anything still held back once the input is exhausted
+fn finish<W: Write>(output: &mut W, options: &Options, state: &mut State) -> CatResult<()> {
+    if !state.filter_partial.is_empty() {
+        let line = std::mem::take(&mut state.filter_partial);
+        format_line(&line, output, options, state)?;
+    }
+    if options.uniq_count {
+        write_uniq_run(output, options, state)?;
+    }
+    write_utf8_partial(output, options, state)?;
+    write_ansi_partial(output, &mut state.ansi)?;
+    write_escape_partial(output, &mut state.escape_partial)?;
+    if state.skipped_carriage_return {
+        write_carriage_return(output, options, state, true, false)?;
+    }
+    if options.ensure_trailing_newline && !state.at_line_start {
+        write_end_of_line(output, options.end_of_line().as_bytes())?;
+        state.lines += 1;
+        state.at_line_start = true;
+    }
+
     Ok(())
 }
 
-pub fn cat<R: Read, W: Write>(input: &mut R, output: &mut W, options: &Options) -> CatResult<()> {
-    if options.can_write_fast() {
-        cat_fast(input, output, options)
+/// Write a carriage return that was held back to see whether it ends the line,
+/// as `^M` if `marker` is set. When the line is only the carriage return, it is
+/// numbered as a `blank` line or not.
+fn write_carriage_return<W: Write>(
+    output: &mut W,
+    options: &Options,
+    state: &mut State,
+    blank: bool,
+    marker: bool,
+) -> CatResult<()> {
+    let numbered = match options.number {
+        NumberingMode::All => true,
+        NumberingMode::NonEmpty => !blank,
+        NumberingMode::None => options.offsets.is_some(),
+    };
+    if state.at_line_start && numbered {
+        write_gutter(output, options, state)?;
+    }
+    if marker {
+        write_marker(output, b"^M", options.use_color())?;
     } else {
-        cat_lines(
-            input,
-            output,
-            options,
-            State {
-                line_number: 0,
-                at_line_start: true,
-                skipped_carriage_return: false,
-                one_blank_kept: false,
-            },
-        )
+        output.write_all(b"\r")?;
     }
-}
+    state.skipped_carriage_return = false;
+    state.at_line_start = false;
+    state.blank_run = 0;
 
-#[derive(Error, Debug)]
-pub enum CatFilesError {
-    #[error("file not found")]
-    NotFound(String),
-    #[error("io error")]
-    Io(#[from] std::io::Error),
+    Ok(())
 }
 
-pub fn cat_files<T: Borrow<String>>(files: &[T], options: &Options) -> Result<(), CatFilesError> {
-    let mut stdout = std::io::stdout();
-    for file in files {
-        let mut file = std::fs::File::open(file.borrow()).map_err(|e| match e.kind() {
-            std::io::ErrorKind::NotFound => CatFilesError::NotFound(file.borrow().to_string()),
-            _ => CatFilesError::Io(e),
-        })?;
-        cat(&mut file, &mut stdout, options).map_err(|e| match e {
-            CatError::Io(e) => CatFilesError::Io(e),
-        })?;
+/// Write the bytes of a UTF-8 sequence that turned out to be incomplete, as
+/// `invalid_utf8` says
+fn write_utf8_partial<W: Write>(
+    output: &mut W,
+    options: &Options,
+    state: &mut State,
+) -> CatResult<()> {
+    if !state.utf8_partial.is_empty() {
+        let table = nonprint_table(&mut state.nonprint, options);
+        write_invalid_utf8(&state.utf8_partial, output, table, options.invalid_utf8)?;
+        state.utf8_partial.clear();
     }
 
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    // write all the tests
-    use super::*;
-
-    #[test]
-    fn test_write_to_end() {
-        let mut output = Vec::new();
-        let input = b"Hello, world!";
-        let n = write_to_end(input, &mut output);
-        assert_eq!(n, input.len());
-        assert_eq!(output, input);
+fn write_new_line<W: Write>(
+    output: &mut W,
+    options: &Options,
+    state: &mut State,
+    end_of_line: &[u8],
+) -> CatResult<()> {
+    let crlf_end_of_line;
+    let mut end_of_line = end_of_line;
+    if state.skipped_carriage_return && options.normalize_crlf {
+        // The \r belongs to the line ending, so the line may still be blank
+        state.skipped_carriage_return = false;
+        crlf_end_of_line = options.crlf_end_of_line();
+        end_of_line = crlf_end_of_line.as_bytes();
+    } else if state.skipped_carriage_return {
+        write_carriage_return(output, options, state, true, options.show_ends)?;
     }
 
-    #[test]
-    fn test_write_tab_to_end() {
-        let mut output = Vec::new();
-        let input = b"Hello, world!";
-        let n = write_tab_to_end(input, &mut output);
-        assert_eq!(n, input.len());
-        assert_eq!(output, input);
+    if !state.at_line_start || !options.squeeze_blank || state.blank_run < options.max_blank {
+        if state.at_line_start {
+            state.blank_run += 1;
+        }
+        if state.at_line_start
+            && (options.number == NumberingMode::All || options.offsets.is_some())
+        {
+            write_gutter(output, options, state)?;
+        }
+        output.write_all(end_of_line)?;
+        state.lines += 1;
+        if options.flush == FlushMode::Line {
+            output.flush()?;
+        }
+    } else {
+        state.blanks_squeezed += 1;
     }
 
-    #[test]
-    fn test_write_nonprint_to_end() {
-        let mut output = Vec::new();
-        let input = b"Hello, world!";
-        let tab = b"    ";
-        let n = write_nonprint_to_end(input, &mut output, tab);
-        assert_eq!(n, input.len());
-        assert_eq!(output, input);
-    }
+    Ok(())
+}
 
-    #[test]
-    fn test_write_end_of_line() {
-        let mut output = Vec::new();
-        let input = b"\n";
-        write_end_of_line(&mut output, input).unwrap();
-        assert_eq!(output, input);
+/// Start a line with its number, or with its offset in the input for `offsets`
+fn write_gutter<W: Write>(output: &mut W, options: &Options, state: &mut State) -> CatResult<()> {
+    match options.offsets {
+        Some(OffsetFormat::Decimal) => write_line_number(output, options, state.line_offset),
+        Some(OffsetFormat::Hex) => {
+            write_line_number(output, options, format!("{:x}", state.line_offset))
+        }
+        None => {
+            write_line_number(output, options, state.line_number)?;
+            state.line_number += 1;
+            state.numbered += 1;
+            Ok(())
+        }
     }
+}
 
-    // Copilot: test cat stuff with unicode, nonprinting, an assorted set of options
+/// Write a line number and its separator, in the format set by the options and
+/// colored if enabled
+fn write_line_number<W: Write>(
+    output: &mut W,
+    options: &Options,
+    number: impl Display,
+) -> CatResult<()> {
+    let width = options.number_width;
+    let separator = &options.number_sep;
+    let (color, reset) = if options.use_color() {
+        (NUMBER_COLOR, RESET_COLOR)
+    } else {
+        ("", "")
+    };
+    match options.number_align {
+        Align::Left => write!(output, "{color}{number:<width$}{reset}{separator}")?,
+        Align::Right => write!(output, "{color}{number:>width$}{reset}{separator}")?,
+    }
+    Ok(())
+}
 
-    #[test]
-    fn test_cat_files_not_found() {
-        let options = Options::new();
-        let files = vec!["nonexistent_file".to_string()];
-        let result = cat_files(&files, &options);
-        assert!(result.is_err());
-        assert!(matches!(result.unwrap_err(), CatFilesError::NotFound(_)));
+/// Write a marker such as `^M`, colored if `color` is set
+fn write_marker<W: Write>(output: &mut W, marker: &[u8], color: bool) -> CatResult<()> {
+    if color {
+        output.write_all(MARKER_COLOR.as_bytes())?;
+        output.write_all(marker)?;
+        output.write_all(RESET_COLOR.as_bytes())?;
+    } else {
+        output.write_all(marker)?;
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_cat_fast() {
-        let options = Options::new();
+fn write_end<W: Write>(
+    output: &mut W,
+    inbuf: &[u8],
+    options: &Options,
+    state: &mut State,
+) -> CatResult<usize> {
+    let nul = options.nul_repr();
+    if options.wrap.is_none() && !options.highlighting() && nul.is_none() {
+        return write_unwrapped_end(output, inbuf, options, state);
+    }
+    let mut formatted = Vec::new();
+    let end = write_unwrapped_end(&mut formatted, inbuf, options, state)?;
+    if let Some(nul) = nul.filter(|_| formatted.contains(&0)) {
+        formatted = formatted
+            .split(|c| *c == 0)
+            .collect::<Vec<_>>()
+            .join(nul.as_bytes());
+    }
+    // Whole lines are formatted at once when highlighting
+    #[cfg(feature = "regex")]
+    if options.highlighting() {
+        formatted = highlight::highlight(&formatted, &options.highlight);
+    }
+    match options.wrap {
+        Some(wrap) => write_wrapped(output, &formatted, options, wrap.max(1), state)?,
+        None => output.write_all(&formatted)?,
+    }
+    Ok(end)
+}
+
+fn write_unwrapped_end<W: Write>(
+    output: &mut W,
+    inbuf: &[u8],
+    options: &Options,
+    state: &mut State,
+) -> CatResult<usize> {
+    if options.interpret_escapes {
+        write_interpret_to_end(
+            inbuf,
+            output,
+            options.tab().as_bytes(),
+            &mut state.escape_partial,
+        )
+    } else if options.strip_ansi {
+        write_strip_ansi_to_end(inbuf, output, options.tab().as_bytes(), &mut state.ansi)
+    } else if options.strip_nonprinting {
+        write_strip_to_end(inbuf, output, options.tab().as_bytes())
+    } else if options.show_nonprinting && options.unicode_aware {
+        let table = nonprint_table(&mut state.nonprint, options);
+        write_utf8_nonprint_to_end(
+            inbuf,
+            output,
+            table,
+            options.invalid_utf8,
+            &mut state.utf8_partial,
+        )
+    } else if options.show_nonprinting {
+        let table = nonprint_table(&mut state.nonprint, options);
+        write_nonprint_to_end(inbuf, output, table)
+    } else if let Some(tab_width) = options.expand_tabs {
+        write_expand_to_end(inbuf, output, tab_width.max(1), &mut state.column)
+    } else if let Some(tab_width) = options.expand_leading {
+        write_expand_leading_to_end(
+            inbuf,
+            output,
+            tab_width.max(1),
+            options.tab().as_bytes(),
+            state,
+        )
+    } else if options.show_tabs {
+        write_tab_to_end(inbuf, output, options.tab().as_bytes())
+    } else {
+        write_to_end(inbuf, output)
+    }
+}
+
+/// Write formatted output, breaking the line before any character that would
+/// go past column `wrap`. Escape sequences take no columns.
+fn write_wrapped<W: Write>(
+    output: &mut W,
+    formatted: &[u8],
+    options: &Options,
+    wrap: usize,
+    state: &mut State,
+) -> CatResult<()> {
+    let mut start = 0;
+    let mut i = 0;
+    while i < formatted.len() {
+        let width = match formatted[i] {
+            0x1b => {
+                // Skip to the final byte of the sequence
+                let len = formatted[i + 1..]
+                    .iter()
+                    .position(|c| (0x40..=0x7e).contains(c) && *c != b'[')
+                    .map_or(formatted.len() - i, |p| p + 2);
+                i += len;
+                continue;
+            }
+            b'\t' => 8 - state.wrap_column % 8,
+            // UTF-8 continuation bytes don't start a new column
+            0x80..=0xbf => 0,
+            _ => 1,
+        };
+        if width > 0 && state.wrap_column > 0 && state.wrap_column + width > wrap {
+            output.write_all(&formatted[start..i])?;
+            start = i;
+            output.write_all(options.newline().as_bytes())?;
+            if options.number != NumberingMode::None || options.offsets.is_some() {
+                write!(
+                    output,
+                    "{:1$}{2}",
+                    "", options.number_width, options.number_sep
+                )?;
+            }
+            state.wrap_column = 0;
+            // Measure this character again, since a tab depends on the column
+            continue;
+        }
+        state.wrap_column += width;
+        i += 1;
+    }
+    output.write_all(&formatted[start..])?;
+
+    Ok(())
+}
+
+// write***_to_end methods
+// Write all symbols till \n or \r or end of buffer is reached
+// We need to stop at \r because it may be written as ^M depending on the byte after and settings;
+// however, write_nonprint_to_end doesn't need to stop at \r because it will always write \r as ^M.
+// That is also where -E puts its $ after a \r with -v, so `-vE` and `-E` agree on CRLF input, as
+// in GNU cat.
+// Return the number of written symbols
+fn write_to_end<W: Write>(inbuf: &[u8], output: &mut W) -> CatResult<usize> {
+    match inbuf.iter().position(|c| *c == b'\n' || *c == b'\r') {
+        Some(p) => {
+            output.write_all(&inbuf[..p])?;
+            Ok(p)
+        }
+        None => {
+            output.write_all(inbuf)?;
+            Ok(inbuf.len())
+        }
+    }
+}
+
+fn write_tab_to_end<W: Write>(inbuf: &[u8], output: &mut W, tab: &[u8]) -> CatResult<usize> {
+    const MAX_SLICES: usize = 64;
+    let end = inbuf
+        .iter()
+        .position(|c| *c == b'\n' || *c == b'\r')
+        .unwrap_or(inbuf.len());
+
+    // Hand the segments between tabs and the tab markers to the writer together
+    let mut slices = [IoSlice::new(&[]); MAX_SLICES];
+    let mut len = 0;
+    let mut vectored = true;
+    for (i, segment) in inbuf[..end].split(|c| *c == b'\t').enumerate() {
+        if !vectored {
+            // Not worth gathering the slices for a writer that takes one at a time
+            if i > 0 {
+                output.write_all(tab)?;
+            }
+            output.write_all(segment)?;
+            continue;
+        }
+        if len + 2 > MAX_SLICES {
+            write_all_vectored(output, &mut slices[..len], &mut vectored)?;
+            len = 0;
+        }
+        if i > 0 {
+            slices[len] = IoSlice::new(tab);
+            len += 1;
+        }
+        slices[len] = IoSlice::new(segment);
+        len += 1;
+    }
+    write_all_vectored(output, &mut slices[..len], &mut vectored)?;
+
+    Ok(end)
+}
+
+/// Write all of `slices`, like the unstable `Write::write_all_vectored`.
+///
+/// `Write::is_write_vectored` is unstable too, so a writer that takes no more
+/// than the first slice of a vectored write, as the default `write_vectored`
+/// does, is taken not to support them: `vectored` is cleared and the rest of
+/// the slices are written one at a time.
+fn write_all_vectored<W: Write>(
+    output: &mut W,
+    mut slices: &mut [IoSlice<'_>],
+    vectored: &mut bool,
+) -> CatResult<()> {
+    IoSlice::advance_slices(&mut slices, 0);
+    while *vectored && !slices.is_empty() {
+        let first = slices[0].len();
+        match output.write_vectored(slices) {
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into()),
+            Ok(n) => {
+                *vectored = n > first || slices.len() == 1;
+                IoSlice::advance_slices(&mut slices, n);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    for slice in slices.iter() {
+        output.write_all(slice)?;
+    }
+
+    Ok(())
+}
+
+/// Like `write_tab_to_end`, but replace each tab with enough spaces to reach
+/// the next multiple of `tab_width`, keeping track of the current `column`
+fn write_expand_to_end<W: Write>(
+    inbuf: &[u8],
+    output: &mut W,
+    tab_width: usize,
+    column: &mut usize,
+) -> CatResult<usize> {
+    const SPACES: [u8; 64] = [b' '; 64];
+    let mut start = 0;
+    let mut end = inbuf.len();
+    for (i, byte) in inbuf.iter().copied().enumerate() {
+        match byte {
+            b'\n' | b'\r' => {
+                end = i;
+                break;
+            }
+            b'\t' => {
+                output.write_all(&inbuf[start..i])?;
+                start = i + 1;
+                let mut spaces = tab_width - *column % tab_width;
+                *column += spaces;
+                while spaces > 0 {
+                    let n = spaces.min(SPACES.len());
+                    output.write_all(&SPACES[..n])?;
+                    spaces -= n;
+                }
+            }
+            // UTF-8 continuation bytes don't start a new column
+            0x80..=0xbf => {}
+            _ => *column += 1,
+        }
+    }
+    output.write_all(&inbuf[start..end])?;
+    Ok(end)
+}
+
+/// Like `write_expand_to_end` for the spaces and tabs that start a line, then
+/// like `write_tab_to_end` for the rest of it
+fn write_expand_leading_to_end<W: Write>(
+    inbuf: &[u8],
+    output: &mut W,
+    tab_width: usize,
+    tab: &[u8],
+    state: &mut State,
+) -> CatResult<usize> {
+    if !state.indenting {
+        return write_tab_to_end(inbuf, output, tab);
+    }
+    let indent = inbuf
+        .iter()
+        .position(|c| *c != b' ' && *c != b'\t')
+        .unwrap_or(inbuf.len());
+    write_expand_to_end(&inbuf[..indent], output, tab_width, &mut state.column)?;
+    if indent == inbuf.len() || matches!(inbuf[indent], b'\n' | b'\r') {
+        return Ok(indent);
+    }
+    state.indenting = false;
+    Ok(indent + write_tab_to_end(&inbuf[indent..], output, tab)?)
+}
+
+/// The `show_nonprinting` output for each byte, building it if this is the
+/// first time it's needed
+fn nonprint_table<'a>(
+    table: &'a mut Option<NonprintTable>,
+    options: &Options,
+) -> &'a NonprintTable {
+    table.get_or_insert_with(|| {
+        let nul = options.nul.unwrap_or(NulMode::Caret);
+        NonprintTable::new(options.tab().as_bytes(), nul, options.use_color())
+    })
+}
+
+fn write_nonprint_to_end<W: Write>(
+    inbuf: &[u8],
+    output: &mut W,
+    table: &NonprintTable,
+) -> CatResult<usize> {
+    let end = inbuf
+        .iter()
+        .position(|c| *c == b'\n')
+        .unwrap_or(inbuf.len());
+    let mut batch = Batch::new(output);
+    let mut start = 0;
+    for (i, byte) in inbuf[..end].iter().copied().enumerate() {
+        if (32..=126).contains(&byte) {
+            continue;
+        }
+        // Copy the run of printable bytes before this one in a single piece
+        if start < i {
+            batch.push(&inbuf[start..i])?;
+        }
+        start = i + 1;
+        batch.push(table.get(byte))?;
+    }
+    batch.push(&inbuf[start..end])?;
+    batch.flush()?;
+
+    Ok(end)
+}
+
+/// Write the input up to the next \n, dropping the bytes that
+/// `write_nonprint_to_end` would escape
+fn write_strip_to_end<W: Write>(inbuf: &[u8], output: &mut W, tab: &[u8]) -> CatResult<usize> {
+    let end = inbuf
+        .iter()
+        .position(|c| *c == b'\n')
+        .unwrap_or(inbuf.len());
+    let mut batch = Batch::new(output);
+    let mut start = 0;
+    for (i, byte) in inbuf[..end].iter().copied().enumerate() {
+        if (32..=126).contains(&byte) {
+            continue;
+        }
+        batch.push(&inbuf[start..i])?;
+        start = i + 1;
+        if byte == 9 {
+            batch.push(tab)?;
+        }
+    }
+    batch.push(&inbuf[start..end])?;
+    batch.flush()?;
+
+    Ok(end)
+}
+
+/// Write the input up to the next \n or \r, dropping CSI escape sequences
+/// such as color codes. A sequence cut off by the end of the buffer is carried
+/// over in `ansi`; one cut off by the end of the line is dropped.
+fn write_strip_ansi_to_end<W: Write>(
+    inbuf: &[u8],
+    output: &mut W,
+    tab: &[u8],
+    ansi: &mut AnsiState,
+) -> CatResult<usize> {
+    let end = inbuf
+        .iter()
+        .position(|c| *c == b'\n' || *c == b'\r')
+        .unwrap_or(inbuf.len());
+    let mut batch = Batch::new(output);
+    let mut start = 0;
+    for (i, byte) in inbuf[..end].iter().copied().enumerate() {
+        match *ansi {
+            AnsiState::Text => {}
+            AnsiState::Escape if byte == b'[' => {
+                *ansi = AnsiState::Csi;
+                continue;
+            }
+            AnsiState::Escape => {
+                // Not a CSI sequence, so keep the ESC
+                batch.push(b"\x1b")?;
+                *ansi = AnsiState::Text;
+                start = i;
+            }
+            AnsiState::Csi => match byte {
+                0x20..=0x3f => continue,
+                0x40..=0x7e => {
+                    *ansi = AnsiState::Text;
+                    start = i + 1;
+                    continue;
+                }
+                // A malformed sequence ends before this byte
+                _ => {
+                    *ansi = AnsiState::Text;
+                    start = i;
+                }
+            },
+        }
+        if byte == 0x1b {
+            batch.push(&inbuf[start..i])?;
+            *ansi = AnsiState::Escape;
+        } else if byte == b'\t' {
+            batch.push(&inbuf[start..i])?;
+            batch.push(tab)?;
+            start = i + 1;
+        }
+    }
+    if *ansi == AnsiState::Text {
+        batch.push(&inbuf[start..end])?;
+    }
+    batch.flush()?;
+    if end < inbuf.len() {
+        write_ansi_partial(output, ansi)?;
+    }
+
+    Ok(end)
+}
+
+/// End an escape sequence cut off by the end of a line or of the input,
+/// keeping a lone ESC
+fn write_ansi_partial<W: Write>(output: &mut W, ansi: &mut AnsiState) -> CatResult<()> {
+    if *ansi == AnsiState::Escape {
+        output.write_all(b"\x1b")?;
+    }
+    *ansi = AnsiState::Text;
+
+    Ok(())
+}
+
+/// Write the input up to the next \n or \r, turning backslash escapes into
+/// the bytes they stand for. An escape cut off by the end of the buffer is
+/// carried over in `partial`; one cut off by the end of the line is ended
+/// there.
+fn write_interpret_to_end<W: Write>(
+    inbuf: &[u8],
+    output: &mut W,
+    tab: &[u8],
+    partial: &mut Vec<u8>,
+) -> CatResult<usize> {
+    let end = inbuf
+        .iter()
+        .position(|c| *c == b'\n' || *c == b'\r')
+        .unwrap_or(inbuf.len());
+    let mut batch = Batch::new(output);
+    let mut start = 0;
+    for (i, byte) in inbuf[..end].iter().copied().enumerate() {
+        if !partial.is_empty() {
+            partial.push(byte);
+            start = i + 1;
+            match partial[..] {
+                [b'\\', b'x'] => {}
+                [b'\\', b'x', digit] if digit.is_ascii_hexdigit() => {}
+                [b'\\', b'x', high, low] if low.is_ascii_hexdigit() => {
+                    batch.push(&[(hex_value(high) << 4) | hex_value(low)])?;
+                    partial.clear();
+                }
+                [b'\\', b'x', ..] => {
+                    // The escape ended before this byte
+                    partial.pop();
+                    push_escape_partial(&mut batch, partial)?;
+                    start = i;
+                }
+                [b'\\', escape] => {
+                    match escape_value(escape) {
+                        Some(value) => batch.push(&[value])?,
+                        None => batch.push(partial)?,
+                    }
+                    partial.clear();
+                }
+                _ => unreachable!(),
+            }
+            if !partial.is_empty() || start > i {
+                continue;
+            }
+        }
+        if byte == b'\\' {
+            batch.push(&inbuf[start..i])?;
+            partial.push(byte);
+        } else if byte == b'\t' {
+            batch.push(&inbuf[start..i])?;
+            batch.push(tab)?;
+            start = i + 1;
+        }
+    }
+    if partial.is_empty() {
+        batch.push(&inbuf[start..end])?;
+    }
+    if end < inbuf.len() {
+        push_escape_partial(&mut batch, partial)?;
+    }
+    batch.flush()?;
+
+    Ok(end)
+}
+
+/// The byte that a one-character escape such as `\n` stands for
+fn escape_value(escape: u8) -> Option<u8> {
+    match escape {
+        b'\\' => Some(b'\\'),
+        b'0' => Some(0),
+        b'a' => Some(0x07),
+        b'b' => Some(0x08),
+        b'e' => Some(0x1b),
+        b'f' => Some(0x0c),
+        b'n' => Some(b'\n'),
+        b'r' => Some(b'\r'),
+        b't' => Some(b'\t'),
+        b'v' => Some(0x0b),
+        _ => None,
+    }
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+/// End a backslash escape cut off by the end of a line or of the input. A
+/// `\x` with one digit stands for that digit's value; anything shorter is
+/// written as is.
+fn write_escape_partial<W: Write>(output: &mut W, partial: &mut Vec<u8>) -> CatResult<()> {
+    let mut batch = Batch::new(output);
+    push_escape_partial(&mut batch, partial)?;
+    batch.flush()
+}
+
+fn push_escape_partial<W: Write>(batch: &mut Batch<'_, W>, partial: &mut Vec<u8>) -> CatResult<()> {
+    match partial[..] {
+        [b'\\', b'x', digit] => batch.push(&[hex_value(digit)])?,
+        _ => batch.push(partial)?,
+    }
+    partial.clear();
+
+    Ok(())
+}
+
+/// Collects small writes into a fixed buffer so that they reach the
+/// underlying writer in larger pieces
+struct Batch<'a, W: Write> {
+    output: &'a mut W,
+    buf: [u8; 1024],
+    len: usize,
+}
+
+impl<'a, W: Write> Batch<'a, W> {
+    fn new(output: &'a mut W) -> Self {
+        Self {
+            output,
+            buf: [0; 1024],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> CatResult<()> {
+        if self.len + bytes.len() > self.buf.len() {
+            self.flush()?;
+            if bytes.len() > self.buf.len() {
+                self.output.write_all(bytes)?;
+                return Ok(());
+            }
+        }
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> CatResult<()> {
+        self.output.write_all(&self.buf[..self.len])?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// Like `write_nonprint_to_end`, but pass valid UTF-8 through unchanged and
+/// only escape control characters, writing invalid bytes as `invalid` says. A
+/// sequence cut off by the end of `inbuf` is moved into `partial` to be
+/// completed by the next buffer.
+fn write_utf8_nonprint_to_end<W: Write>(
+    inbuf: &[u8],
+    output: &mut W,
+    table: &NonprintTable,
+    invalid: InvalidUtf8,
+    partial: &mut Vec<u8>,
+) -> CatResult<usize> {
+    let end = inbuf
+        .iter()
+        .position(|c| *c == b'\n')
+        .unwrap_or(inbuf.len());
+    let joined;
+    let line = if partial.is_empty() {
+        &inbuf[..end]
+    } else {
+        partial.extend_from_slice(&inbuf[..end]);
+        joined = std::mem::take(partial);
+        &joined[..]
+    };
+
+    let mut chunks = line.utf8_chunks().peekable();
+    while let Some(chunk) = chunks.next() {
+        let valid = chunk.valid();
+        let mut start = 0;
+        for (i, c) in valid.char_indices() {
+            if c.is_control() {
+                output.write_all(&valid.as_bytes()[start..i])?;
+                // C0 and C1 controls are escaped like the bytes of the same value
+                write_nonprint_to_end(&[c as u8], output, table)?;
+                start = i + c.len_utf8();
+            }
+        }
+        output.write_all(&valid.as_bytes()[start..])?;
+
+        let bytes = chunk.invalid();
+        let incomplete = chunks.peek().is_none()
+            && end == inbuf.len()
+            && std::str::from_utf8(bytes).is_err_and(|e| e.error_len().is_none());
+        if incomplete {
+            partial.extend_from_slice(bytes);
+        } else {
+            write_invalid_utf8(bytes, output, table, invalid)?;
+        }
+    }
+
+    Ok(end)
+}
+
+/// Write `bytes`, one invalid UTF-8 sequence or none, as `invalid` says
+fn write_invalid_utf8<W: Write>(
+    bytes: &[u8],
+    output: &mut W,
+    table: &NonprintTable,
+    invalid: InvalidUtf8,
+) -> CatResult<()> {
+    match invalid {
+        InvalidUtf8::Escape => {
+            write_nonprint_to_end(bytes, output, table)?;
+        }
+        InvalidUtf8::Replace if !bytes.is_empty() => {
+            output.write_all("\u{fffd}".as_bytes())?;
+        }
+        InvalidUtf8::Replace | InvalidUtf8::Skip => {}
+    }
+
+    Ok(())
+}
+
+fn write_end_of_line<W: Write>(writer: &mut W, end_of_line: &[u8]) -> CatResult<()> {
+    writer.write_all(end_of_line)?;
+    Ok(())
+}
+
+pub fn cat<R: Read, W: Write>(input: &mut R, output: &mut W, options: &Options) -> CatResult<()> {
+    cat_counted(input, output, options).map(|_| ())
+}
+
+/// Like [`cat`], but format `input` in memory and return the output
+pub fn cat_bytes(input: &[u8], options: &Options) -> CatResult<Vec<u8>> {
+    let mut output = Vec::new();
+    cat(&mut std::io::Cursor::new(input), &mut output, options)?;
+    Ok(output)
+}
+
+/// Like [`cat_bytes`], but for text. Any invalid UTF-8 in the output is
+/// replaced with U+FFFD.
+pub fn cat_str(input: &str, options: &Options) -> CatResult<String> {
+    let output = cat_bytes(input.as_bytes(), options)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Like [`cat`], but also report how many bytes and lines were processed
+pub fn cat_counted<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    if options.skip_bytes > 0 {
+        // `take` keeps reading until it has all the bytes or the input ends
+        let skipped = std::io::copy(
+            &mut input.by_ref().take(options.skip_bytes as u64),
+            &mut std::io::sink(),
+        )?;
+        let mut stats = cat_unskipped(input, output, options)?;
+        stats.bytes_in += skipped as usize;
+        return Ok(stats);
+    }
+    cat_unskipped(input, output, options)
+}
+
+/// Format input that has had `skip_bytes` discarded
+fn cat_unskipped<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    #[cfg(feature = "gzip")]
+    if options.decompress != Compression::None {
+        return gzip::cat_compressed(input, output, options);
+    }
+    cat_uncompressed(input, output, options)
+}
+
+/// Decode and format input that has already been decompressed
+fn cat_uncompressed<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    if options.encoding != Encoding::Utf8 || options.strip_bom {
+        let mut input = DecodeReader::new(input, options.encoding, options.strip_bom);
+        return cat_decoded(&mut input, output, options);
+    }
+    cat_decoded(input, output, options)
+}
+
+/// Format input that is already UTF-8 (or raw bytes)
+fn cat_decoded<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    // A hex dump shows the input as it is
+    let strip_crlf = options.line_ending != OutputLineEnding::Keep;
+    if (options.cr_is_eol || strip_crlf) && !options.hex_dump {
+        let mut input = CrReader::new(input, options.cr_is_eol, strip_crlf);
+        return cat_translated(&mut input, output, options);
+    }
+    cat_translated(input, output, options)
+}
+
+/// Format input that has been decoded and had its line endings translated
+fn cat_translated<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    let mut output = CountingWriter::new(output);
+    let mut stats = match options.max_bytes {
+        Some(max_bytes) => {
+            let full = Cell::new(false);
+            let mut limited = LimitWriter::new(&mut output, max_bytes, options.line_end(), &full);
+            let mut input = LimitReader {
+                inner: input,
+                full: &full,
+            };
+            let mut stats = cat_encoded(&mut input, &mut limited, options)?;
+            stats.truncated = limited.truncated;
+            limited.finish()?;
+            stats
+        }
+        None => cat_encoded(input, &mut output, options)?,
+    };
+    stats.bytes_out = output.count;
+
+    Ok(stats)
+}
+
+/// Format the input and encode the output as set by `output_encoding`
+fn cat_encoded<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    #[cfg(feature = "encoding")]
+    if options.output_encoding != OutputEncoding::Utf8 {
+        let mut encoder = EncodeWriter::new(output, options.output_encoding, options.unmappable);
+        let stats = cat_formatted(input, &mut encoder, options)?;
+        encoder.finish()?;
+        return Ok(stats);
+    }
+    cat_formatted(input, output, options)
+}
+
+/// Apply the formatting for the options, whichever way it needs to be done
+fn cat_formatted<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+) -> CatResult<CatStats> {
+    if options.hex_dump {
+        cat_hex(input, output, options)
+    } else if options.reverse_lines {
+        cat_reverse(input, output, options)
+    } else if options.can_write_fast() {
+        cat_fast(input, output, options)
+    } else {
+        cat_lines(input, output, options, State::new(options))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CatFilesError {
+    #[error("file not found")]
+    NotFound(String),
+    #[error("is a directory")]
+    IsDirectory(String),
+    #[error("permission denied")]
+    PermissionDenied(String),
+    #[error("binary file")]
+    Binary(String),
+    /// A list of files read from standard input named `-`, which can't be read
+    /// as well
+    #[error("file name '-' not allowed when reading names from stdin")]
+    StdinInList,
+    /// An error opening or reading the file at `path`
+    #[error("io error")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// An error writing the output
+    #[error("write error")]
+    Output(#[from] std::io::Error),
+    #[error("{} file(s) could not be read", .errors.len())]
+    Failed {
+        /// The errors for each file that could not be read, in order
+        errors: Vec<CatFilesError>,
+        /// Statistics for the files that were read
+        stats: CatStats,
+    },
+}
+
+/// Concatenate `files` to standard output. A file named `-` is read from
+/// standard input.
+///
+/// Files may be named by anything that converts to a [`Path`], such as
+/// `String`, `&str` or `PathBuf`.
+pub fn cat_files<T: AsRef<Path>>(
+    files: &[T],
+    options: &Options,
+) -> Result<CatStats, CatFilesError> {
+    let mut stdout = BufWriter::new(std::io::stdout().lock());
+    // Flush even if some files failed, so their output precedes the errors
+    let result = cat_files_to(files, &mut stdout, options);
+    stdout.flush()?;
+
+    result
+}
+
+/// Concatenate `files` to `output`. A file named `-` is read from standard
+/// input.
+///
+/// The files are formatted as if they were one input, like GNU `cat`: line
+/// numbering carries on from one file to the next, unless
+/// [`number_reset_per_file`](Options::number_reset_per_file) is set, blank
+/// lines are squeezed across file boundaries, and a last line without a line
+/// feed runs into the first line of the next file, unless `headers` or
+/// `with_filename` has to start that file on a line of its own. Options that
+/// need all of the input at once, such as `head` or `skip_bytes`, apply to
+/// each file on its own, as do line ranges and offsets.
+///
+/// A file that cannot be opened or read does not stop the remaining files from
+/// being written; all such errors are collected into [`CatFilesError::Failed`].
+/// Errors writing to `output` are returned immediately.
+pub fn cat_files_to<W: Write, T: AsRef<Path>>(
+    files: &[T],
+    output: &mut W,
+    options: &Options,
+) -> Result<CatStats, CatFilesError> {
+    let full = Cell::new(false);
+    match options.max_bytes {
+        Some(max_bytes) => {
+            let mut limited = LimitWriter::new(output, max_bytes, options.line_end(), &full);
+            let result = cat_each_file(files, &mut limited, options, &full);
+            finish_limited(result, limited)
+        }
+        None => cat_each_file(files, output, options, &full),
+    }
+}
+
+/// Concatenate `files` to `output`, stopping once `full` is set
+fn cat_each_file<W: Write, T: AsRef<Path>>(
+    files: &[T],
+    output: &mut W,
+    options: &Options,
+    full: &Cell<bool>,
+) -> Result<CatStats, CatFilesError> {
+    let mut stats = CatStats::default();
+    let mut errors = Vec::new();
+    let mut carry = Carry::new(options, full);
+    for (i, file) in files.iter().enumerate() {
+        if full.get() {
+            break;
+        }
+        let file = file.as_ref();
+        carry.cache.again = files.get(i + 1).is_some_and(|next| next.as_ref() == file);
+        if options.follow && i + 1 == files.len() && !is_stdin(file) {
+            carry.end_line(output, options, &mut stats)?;
+            if options.headers {
+                stats.bytes_out += write_header(output, file, i == 0)?;
+            }
+            let mid_line = carry.mid_line && carry.format.is_none();
+            let format = carry.format.take().map(|mut format| {
+                format.start_file(options);
+                format
+            });
+            let options = match format {
+                Some(_) => Cow::Borrowed(options),
+                None => next_file_options(options, &stats),
+            };
+            match follow::follow_path(file, output, &options, format) {
+                Ok(file_stats) => stats += carry.joined(file_stats, mid_line),
+                Err(e @ (CatFilesError::Output(_) | CatFilesError::Failed { .. })) => {
+                    return Err(e)
+                }
+                Err(e) => errors.push(e),
+            }
+            break;
+        }
+        cat_path(
+            file,
+            i == 0,
+            output,
+            options,
+            &mut stats,
+            &mut errors,
+            &mut carry,
+        )?;
+    }
+    carry.finish(output, options, &mut stats)?;
+
+    if errors.is_empty() {
+        Ok(stats)
+    } else {
+        Err(CatFilesError::Failed { errors, stats })
+    }
+}
+
+/// Like [`cat_files`], but read the names of the files from the file `list`
+/// (`-` for standard input). Each name ends with `separator`, such as `\n` or
+/// `\0`, and empty names are skipped. When the list is standard input, a name
+/// of `-` fails with [`CatFilesError::StdinInList`].
+pub fn cat_file_list(
+    list: impl AsRef<Path>,
+    separator: u8,
+    options: &Options,
+) -> Result<CatStats, CatFilesError> {
+    let mut stdout = BufWriter::new(std::io::stdout().lock());
+    let result = cat_file_list_to(list, separator, &mut stdout, options);
+    stdout.flush()?;
+
+    result
+}
+
+/// Like [`cat_files_to`], but read the names of the files from the file
+/// `list`, as in [`cat_file_list`]. Names are read as they are needed, so the
+/// list may be of any length.
+pub fn cat_file_list_to<W: Write>(
+    list: impl AsRef<Path>,
+    separator: u8,
+    output: &mut W,
+    options: &Options,
+) -> Result<CatStats, CatFilesError> {
+    let full = Cell::new(false);
+    match options.max_bytes {
+        Some(max_bytes) => {
+            let mut limited = LimitWriter::new(output, max_bytes, options.line_end(), &full);
+            let result = cat_listed_files(list.as_ref(), separator, &mut limited, options, &full);
+            finish_limited(result, limited)
+        }
+        None => cat_listed_files(list.as_ref(), separator, output, options, &full),
+    }
+}
+
+/// Concatenate the files named in `list` to `output`, stopping once `full` is
+/// set
+fn cat_listed_files<W: Write>(
+    list: &Path,
+    separator: u8,
+    output: &mut W,
+    options: &Options,
+    full: &Cell<bool>,
+) -> Result<CatStats, CatFilesError> {
+    let mut reader: Box<dyn BufRead> = if is_stdin(list) {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(open_file(list)?))
+    };
+    let mut stats = CatStats::default();
+    let mut errors = Vec::new();
+    let mut carry = Carry::new(options, full);
+    let mut name = Vec::new();
+    // Read a name ahead, to know whether the file is named again next
+    let mut next = Vec::new();
+    let mut more = read_listed_name(&mut reader, separator, list, &mut next)?;
+    let mut first = true;
+    while more && !full.get() {
+        std::mem::swap(&mut name, &mut next);
+        more = read_listed_name(&mut reader, separator, list, &mut next)?;
+        carry.cache.again = more && next == name;
+        if name == b"-" && is_stdin(list) {
+            errors.push(CatFilesError::StdinInList);
+        } else {
+            cat_path(
+                &listed_path(&name),
+                first,
+                output,
+                options,
+                &mut stats,
+                &mut errors,
+                &mut carry,
+            )?;
+            first = false;
+        }
+    }
+    carry.finish(output, options, &mut stats)?;
+
+    if errors.is_empty() {
+        Ok(stats)
+    } else {
+        Err(CatFilesError::Failed { errors, stats })
+    }
+}
+
+/// Read the next name from the file `list` into `name`, skipping empty names.
+/// Returns whether there was one.
+fn read_listed_name(
+    reader: &mut impl BufRead,
+    separator: u8,
+    list: &Path,
+    name: &mut Vec<u8>,
+) -> Result<bool, CatFilesError> {
+    loop {
+        name.clear();
+        let n = reader
+            .read_until(separator, name)
+            .map_err(|source| CatFilesError::Io {
+                path: list.to_string_lossy().into_owned(),
+                source,
+            })?;
+        if n == 0 {
+            return Ok(false);
+        }
+        if name.last() == Some(&separator) {
+            name.pop();
+        }
+        if !name.is_empty() {
+            return Ok(true);
+        }
+    }
+}
+
+/// The path of a file named in a list, which may be any bytes on unix
+fn listed_path(name: &[u8]) -> Cow<'_, Path> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(Path::new(std::ffi::OsStr::from_bytes(name)))
+    }
+    #[cfg(not(unix))]
+    {
+        Cow::Owned(PathBuf::from(String::from_utf8_lossy(name).into_owned()))
+    }
+}
+
+/// Format the file at `path` (`-` for standard input), adding to `stats`.
+/// Errors that don't stop the remaining files are added to `errors`.
+fn cat_path<W: Write>(
+    path: &Path,
+    first: bool,
+    output: &mut W,
+    options: &Options,
+    stats: &mut CatStats,
+    errors: &mut Vec<CatFilesError>,
+    carry: &mut Carry,
+) -> Result<(), CatFilesError> {
+    let options = match &mut carry.format {
+        Some(format) => {
+            format.start_file(options);
+            Cow::Borrowed(options)
+        }
+        None => next_file_options(options, stats),
+    };
+    let options = &*options;
+    let mut opened = None;
+    let kept = carry.cache.kept.take().filter(|(kept, _)| kept == path);
+    if kept.is_none() && !is_stdin(path) {
+        match open_file(path) {
+            Ok(file) => opened = Some(file),
+            Err(e) => {
+                errors.push(e);
+                return Ok(());
+            }
+        }
+    }
+    if options.check {
+        return Ok(());
+    }
+    carry.end_line(output, options, stats)?;
+    if options.headers {
+        stats.bytes_out += write_header(output, path, first)?;
+    }
+    let full = carry.full;
+    let format = carry.format.as_mut().map(|format| Carried { format, full });
+    let cache = &mut carry.cache;
+    let mut tracked = LineEndWriter::new(output, options.line_end(), carry.mid_line);
+    let content = &mut tracked;
+    let result = match (kept, &mut opened) {
+        (Some((kept, data)), _) => {
+            let size = Some(data.len() as u64);
+            let result = cat_checked(path, &mut &data[..], content, options, size, format);
+            if cache.again {
+                cache.kept = Some((kept, data));
+            }
+            result
+        }
+        (None, Some(file)) if cache.again => {
+            cat_file_to_repeat(path, file, content, options, cache, format)
+        }
+        (None, Some(file)) => cat_file(path, file, content, options, format),
+        (None, None) => cat_checked(
+            Path::new("-"),
+            &mut std::io::stdin().lock(),
+            content,
+            options,
+            None,
+            format,
+        ),
+    };
+    let mid_line = std::mem::replace(&mut carry.mid_line, tracked.mid_line);
+    match result {
+        Ok(file_stats) => *stats += carry.joined(file_stats, mid_line),
+        Err(e @ (CatFilesError::Binary(_) | CatFilesError::Io { .. })) => errors.push(e),
+        Err(e) => return Err(e),
+    }
+    // The file's last line is ended here, instead of running into the next
+    if let Some(format) = carry
+        .format
+        .as_mut()
+        .filter(|_| options.ensure_trailing_newline)
+    {
+        *stats += format.finish_counted(output, options)?;
+    }
+
+    Ok(())
+}
+
+/// The options to format the next of several files with on its own, given the
+/// `stats` of the files before it: unless `number_reset_per_file` is set, its
+/// numbering carries on from theirs
+fn next_file_options<'a>(options: &'a Options, stats: &CatStats) -> Cow<'a, Options> {
+    if options.number_reset_per_file || stats.numbered == 0 {
+        Cow::Borrowed(options)
+    } else {
+        let start = options.number_start + stats.numbered;
+        Cow::Owned(options.clone().number_start(start))
+    }
+}
+
+/// What carries over from one of several files to the next
+struct Carry<'a> {
+    /// The formatting, which carries on from file to file as if they were one
+    /// input, unless the options need each file whole or leave it unformatted
+    format: Option<CatState>,
+
+    /// The contents of a file named again next
+    cache: RepeatCache,
+
+    /// Set once the output is full for `max_bytes`, to stop reading
+    full: &'a Cell<bool>,
+
+    /// Whether the output so far ends in the middle of a line, for files
+    /// formatted each on their own
+    mid_line: bool,
+}
+
+impl<'a> Carry<'a> {
+    fn new(options: &Options, full: &'a Cell<bool>) -> Self {
+        let carries = options.can_stream_decompressed() && !options.can_write_fast();
+        Self {
+            format: carries.then(|| CatState::for_files(options)),
+            cache: RepeatCache::default(),
+            full,
+            mid_line: false,
+        }
+    }
+
+    /// The `stats` of a file formatted on its own, which counted its first
+    /// line even if it carries on a line left without a line feed by the
+    /// files before it
+    fn joined(&self, mut stats: CatStats, mid_line: bool) -> CatStats {
+        if self.format.is_none() && mid_line && stats.bytes_out > 0 {
+            stats.lines = stats.lines.saturating_sub(1);
+        }
+        stats
+    }
+
+    /// End a line the files before left without a line feed, before the next
+    /// file's header or name prefix, adding it to `stats`
+    fn end_line<W: Write>(
+        &mut self,
+        output: &mut W,
+        options: &Options,
+        stats: &mut CatStats,
+    ) -> Result<(), CatFilesError> {
+        if !options.headers && !options.with_filename {
+            return Ok(());
+        }
+        match &mut self.format {
+            Some(format) => *stats += format.end_line(output, options)?,
+            None if self.mid_line => {
+                let end_of_line = options.end_of_line();
+                write_end_of_line(output, end_of_line.as_bytes())?;
+                stats.bytes_out += end_of_line.len();
+                self.mid_line = false;
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// Write out what the formatting still holds back after the last file,
+    /// adding it to `stats`
+    fn finish<W: Write>(
+        &mut self,
+        output: &mut W,
+        options: &Options,
+        stats: &mut CatStats,
+    ) -> Result<(), CatFilesError> {
+        if let Some(mut format) = self.format.take() {
+            *stats += format.finish_counted(output, options)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The formatting a file carries on from the files before it
+struct Carried<'a> {
+    format: &'a mut CatState,
+
+    /// Set once the output is full for `max_bytes`, to stop reading
+    full: &'a Cell<bool>,
+}
+
+/// The contents of a file named several times in a row, kept to write it again
+/// without reading it again
+#[derive(Default)]
+struct RepeatCache {
+    /// Whether the file being written is named again next
+    again: bool,
+
+    /// The last file written, and all of its contents, if they were kept
+    kept: Option<(PathBuf, Vec<u8>)>,
+}
+
+/// Like `cat_file`, but keep the contents of the file in `cache` if they are
+/// small enough and were all read
+fn cat_file_to_repeat<W: Write>(
+    path: &Path,
+    file: &mut std::fs::File,
+    output: &mut W,
+    options: &Options,
+    cache: &mut RepeatCache,
+    format: Option<Carried<'_>>,
+) -> Result<CatStats, CatFilesError> {
+    let size = file
+        .metadata()
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.len());
+    let Some(size) = size.filter(|size| *size <= REPEAT_CACHE_SIZE) else {
+        return cat_file(path, file, output, options, format);
+    };
+    let mut data = Vec::with_capacity(size as usize);
+    let mut input = Recorder {
+        inner: file,
+        copy: &mut data,
+    };
+    let result = cat_checked(path, &mut input, output, options, Some(size), format);
+    // `head` or `max_bytes` may have stopped reading early
+    if result.is_ok() && data.len() as u64 == size {
+        cache.kept = Some((path.to_path_buf(), data));
+    }
+    result
+}
+
+/// A reader that keeps a copy of everything read through it
+struct Recorder<'a, R> {
+    inner: R,
+    copy: &'a mut Vec<u8>,
+}
+
+impl<R: Read> Read for Recorder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.copy.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Write out the rest of the output limited by `max_bytes` for all the files,
+/// and count only the bytes that got through
+fn finish_limited<W: Write>(
+    mut result: Result<CatStats, CatFilesError>,
+    limited: LimitWriter<'_, W>,
+) -> Result<CatStats, CatFilesError> {
+    let truncated = limited.truncated;
+    let written = limited.finish()?;
+    if let Ok(stats) | Err(CatFilesError::Failed { stats, .. }) = &mut result {
+        stats.bytes_out = written;
+        stats.truncated |= truncated;
+    }
+
+    result
+}
+
+/// Write the `headers` line for `path`, returning its length
+fn write_header<W: Write>(output: &mut W, path: &Path, first: bool) -> std::io::Result<usize> {
+    let name = if is_stdin(path) {
+        Cow::Borrowed("standard input")
+    } else {
+        path.to_string_lossy()
+    };
+    let header = format!("{}==> {name} <==\n", if first { "" } else { "\n" });
+    output.write_all(header.as_bytes())?;
+    Ok(header.len())
+}
+
+/// Format the opened file at `path`, memory-mapping it if enabled and
+/// worthwhile
+fn cat_file<W: Write>(
+    path: &Path,
+    file: &mut std::fs::File,
+    output: &mut W,
+    options: &Options,
+    format: Option<Carried<'_>>,
+) -> Result<CatStats, CatFilesError> {
+    let size = if options.progress {
+        file.metadata()
+            .ok()
+            .filter(|m| m.is_file())
+            .map(|m| m.len())
+    } else {
+        None
+    };
+    #[cfg(feature = "mmap")]
+    if options.mmap {
+        if let Some(map) = mmap::map_file(file) {
+            let mut input = std::io::Cursor::new(&map[..]);
+            return cat_checked(path, &mut input, output, options, size, format);
+        }
+    }
+    cat_checked(path, file, output, options, size, format)
+}
+
+/// Format the input read from `path`, unless `binary_check` is set and the
+/// start of the input looks binary. `size` is the size of the input for
+/// `progress`, if known. The formatting carries on from `format` if given.
+fn cat_checked<R: Read, W: Write>(
+    path: &Path,
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+    size: Option<u64>,
+    format: Option<Carried<'_>>,
+) -> Result<CatStats, CatFilesError> {
+    let mut progress;
+    let input: &mut dyn Read = if options.progress {
+        let name = if is_stdin(path) {
+            "standard input".to_string()
+        } else {
+            path.to_string_lossy().into_owned()
+        };
+        progress = ProgressReader::new(input, std::io::stderr(), name, size);
+        &mut progress
+    } else {
+        input
+    };
+    let mut input = ReadErrors {
+        inner: input,
+        failed: false,
+    };
+    let result = if options.with_filename {
+        let name = if is_stdin(path) {
+            Cow::Borrowed("(standard input)")
+        } else {
+            path.to_string_lossy()
+        };
+        let prefix = format!("{name}:");
+        let mut output = PrefixWriter::new(output, prefix.as_bytes(), options.line_end());
+        cat_checked_input(path, &mut input, &mut output, options, format).map(|mut stats| {
+            stats.bytes_out += output.count;
+            stats
+        })
+    } else {
+        cat_checked_input(path, &mut input, output, options, format)
+    };
+    result.map_err(|e| match e {
+        CatFilesError::Output(source) if input.failed => CatFilesError::Io {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        },
+        e => e,
+    })
+}
+
+fn cat_checked_input<R: Read, W: Write>(
+    path: &Path,
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+    format: Option<Carried<'_>>,
+) -> Result<CatStats, CatFilesError> {
+    // UTF-16 text is full of NUL bytes, so there is nothing to check
+    if !options.binary_check || options.show_nonprinting || options.encoding != Encoding::Utf8 {
+        return Ok(cat_carried(input, output, options, format)?);
+    }
+
+    let mut head = Vec::new();
+    input
+        .by_ref()
+        .take(BINARY_CHECK_SIZE as u64)
+        .read_to_end(&mut head)?;
+    #[cfg(feature = "gzip")]
+    let compressed = options.decompress != Compression::None && head.starts_with(&[0x1f, 0x8b]);
+    #[cfg(not(feature = "gzip"))]
+    let compressed = false;
+    if !compressed && looks_binary(&head) {
+        return Err(CatFilesError::Binary(path.to_string_lossy().into_owned()));
+    }
+    Ok(cat_carried(
+        &mut std::io::Cursor::new(head).chain(input),
+        output,
+        options,
+        format,
+    )?)
+}
+
+/// Format one of several files, carrying on from `format` if given
+fn cat_carried<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+    format: Option<Carried<'_>>,
+) -> CatResult<CatStats> {
+    match format {
+        Some(Carried { format, full }) => {
+            // The output for all the files is limited at once, which only
+            // stops the input through the shared flag
+            let mut input = LimitReader { inner: input, full };
+            cat_with_state(&mut input, output, options, format)
+        }
+        None => cat_counted(input, output, options),
+    }
+}
+
+/// Whether `head` contains a NUL byte, or enough control characters that are
+/// rare in text to make it unlikely to be text
+fn looks_binary(head: &[u8]) -> bool {
+    let control = head
+        .iter()
+        .filter(|c| matches!(c, 0..=6 | 14..=26 | 28..=31 | 127))
+        .count();
+    head.contains(&0) || control * 10 > head.len()
+}
+
+/// A reader that remembers whether reading failed, to tell errors reading the
+/// input from errors writing the output
+struct ReadErrors<R> {
+    inner: R,
+    failed: bool,
+}
+
+impl<R: Read> Read for ReadErrors<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let result = self.inner.read(buf);
+        if result
+            .as_ref()
+            .is_err_and(|e| e.kind() != std::io::ErrorKind::Interrupted)
+        {
+            self.failed = true;
+        }
+        result
+    }
+}
+
+impl From<CatError> for CatFilesError {
+    fn from(error: CatError) -> Self {
+        match error {
+            CatError::Io(e) => CatFilesError::Output(e),
+        }
+    }
+}
+
+/// Whether `path` is `-`, which stands for standard input
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Open `path` for reading, classifying the common failures. Errors carry the
+/// path lossily converted to a string.
+fn open_file(path: &Path) -> Result<std::fs::File, CatFilesError> {
+    let name = || path.to_string_lossy().into_owned();
+    let io_error = |source| CatFilesError::Io {
+        path: name(),
+        source,
+    };
+    let file = std::fs::File::open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => CatFilesError::NotFound(name()),
+        std::io::ErrorKind::PermissionDenied => CatFilesError::PermissionDenied(name()),
+        _ => io_error(e),
+    })?;
+    if file.metadata().map_err(io_error)?.is_dir() {
+        return Err(CatFilesError::IsDirectory(name()));
+    }
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    // write all the tests
+    use super::*;
+
+    #[test]
+    fn test_write_to_end() {
+        let mut output = Vec::new();
+        let input = b"Hello, world!";
+        let n = write_to_end(input, &mut output).unwrap();
+        assert_eq!(n, input.len());
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_write_tab_to_end() {
+        let mut output = Vec::new();
+        let input = b"Hello, world!";
+        let n = write_tab_to_end(input, &mut output, b"^I").unwrap();
+        assert_eq!(n, input.len());
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_write_tab_to_end_counts_tabs() {
+        let mut output = Vec::new();
+        let input = b"a\tb\tc";
+        let n = write_tab_to_end(input, &mut output, b"^I").unwrap();
+        assert_eq!(n, input.len());
+        assert_eq!(output, b"a^Ib^Ic");
+    }
+
+    #[test]
+    fn test_write_tab_to_end_many_tabs() {
+        // More segments than fit in one vectored write
+        let input = b"a\tbc\t\td".repeat(50);
+        let expected = b"a^Ibc^I^Id".repeat(50);
+        let mut output = Vec::new();
+        let n = write_tab_to_end(&input, &mut output, b"^I").unwrap();
+        assert_eq!(n, input.len());
+        assert_eq!(output, expected);
+
+        // Partial writes resume in the middle of a slice
+        let mut writer = ShortWriter(Vec::new());
+        let n = write_tab_to_end(b"abcdefghij\tk\t\r\n", &mut writer, b"<TAB>").unwrap();
+        assert_eq!(n, 13);
+        assert_eq!(writer.0, b"abcdefghij<TAB>k<TAB>");
+    }
+
+    #[test]
+    fn test_write_all_vectored() {
+        let segments: [&[u8]; 4] = [b"abcdefghij", b"^I", b"", b"k"];
+        let mut slices = segments.map(IoSlice::new);
+        let mut vectored = true;
+        let mut output = Vec::new();
+        write_all_vectored(&mut output, &mut slices, &mut vectored).unwrap();
+        assert_eq!(output, b"abcdefghij^Ik");
+        assert!(vectored);
+
+        // The default `write_vectored` only writes the first slice, so the
+        // rest are written one at a time
+        let mut slices = segments.map(IoSlice::new);
+        let mut writer = ShortWriter(Vec::new());
+        write_all_vectored(&mut writer, &mut slices, &mut vectored).unwrap();
+        assert_eq!(writer.0, b"abcdefghij^Ik");
+        assert!(!vectored);
+    }
+
+    #[test]
+    fn test_cat_expand_tabs() {
+        let options = Options::new().expand_tabs(Some(8));
+        let mut input = std::io::Cursor::new(b"a\tb\n\tc\nabcdefgh\ti\n");
+        let mut output = Vec::new();
+        cat(&mut input, &mut output, &options).unwrap();
+        assert_eq!(output, b"a       b\n        c\nabcdefgh        i\n");
+    }
+
+    #[test]
+    fn test_cat_expand_tabs_across_buffers() {
+        let options = Options::new()
+            .expand_tabs(Some(4))
+            .number(NumberingMode::All)
+            .buffer_size(2);
+        let mut input = std::io::Cursor::new("ab\tc\t\n\u{e9}\tx\n".as_bytes());
+        let mut output = Vec::new();
+        cat(&mut input, &mut output, &options).unwrap();
+        assert_eq!(output, "     1\tab  c   \n     2\t\u{e9}   x\n".as_bytes());
+    }
+
+    #[test]
+    fn test_cat_expand_leading() {
+        let input = b"\t\tcode\tvalue\n  \tx\t\n\t\n";
+        let options = Options::new().expand_leading(Some(4));
+        let expected = b"        code\tvalue\n    x\t\n    \n";
+        assert_eq!(cat_bytes(input, &options).unwrap(), expected);
+
+        // Indentation split across reads
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(output, expected);
+
+        // Tabs after the indentation are still shown with `show_tabs`
+        let options = options.show_tabs(true);
+        assert_eq!(cat_bytes(b"\ta\tb\n", &options).unwrap(), b"    a^Ib\n");
+    }
+
+    #[test]
+    fn test_cat_reverse() {
+        let options = Options::new().reverse_lines(true);
+        let mut output = Vec::new();
+        cat(
+            &mut std::io::Cursor::new(b"a\nb\nc\n"),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(output, b"c\nb\na\n");
+
+        let options = options.number(NumberingMode::All).show_ends(true);
+        let mut output = Vec::new();
+        cat(
+            &mut std::io::Cursor::new(b"a\n\nb\n"),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(output, b"     1\tb$\n     2\t$\n     3\ta$\n");
+    }
+
+    #[test]
+    fn test_cat_reverse_no_trailing_newline() {
+        let options = Options::new().reverse_lines(true);
+        let mut output = Vec::new();
+        cat(&mut std::io::Cursor::new(b"a\nb\nc"), &mut output, &options).unwrap();
+        assert_eq!(output, b"cb\na\n");
+
+        let mut output = Vec::new();
+        cat(&mut std::io::Cursor::new(b""), &mut output, &options).unwrap();
+        assert_eq!(output, b"");
+    }
+
+    /// A reader that returns one byte per `read` call
+    struct OneByteReader<'a>(&'a [u8]);
+
+    impl Read for OneByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((byte, rest)), Some(first)) => {
+                    *first = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    /// A reader that is interrupted before every `read` that would succeed
+    struct InterruptedReader<R: Read> {
+        inner: R,
+        interrupted: bool,
+    }
+
+    impl<R: Read> Read for InterruptedReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.interrupted = !self.interrupted;
+            if self.interrupted {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    fn utf16(s: &str, bom: &[u8], to_bytes: fn(u16) -> [u8; 2]) -> Vec<u8> {
+        let mut bytes = bom.to_vec();
+        bytes.extend(s.encode_utf16().flat_map(to_bytes));
+        bytes
+    }
+
+    #[test]
+    fn test_cat_utf16_auto() {
+        let text = "a\u{e9}\n\u{1f600}b\n";
+        let options = Options::new()
+            .encoding(Encoding::Auto)
+            .number(NumberingMode::All);
+        let expected = "     1\ta\u{e9}\n     2\t\u{1f600}b\n".as_bytes();
+        for input in [
+            utf16(text, &[0xff, 0xfe], u16::to_le_bytes),
+            utf16(text, &[0xfe, 0xff], u16::to_be_bytes),
+            [&[0xef, 0xbb, 0xbf], text.as_bytes()].concat(),
+            text.as_bytes().to_vec(),
+        ] {
+            let mut output = Vec::new();
+            cat(&mut std::io::Cursor::new(&input), &mut output, &options).unwrap();
+            assert_eq!(output, expected);
+
+            // Surrogate pairs and byte order marks split across reads
+            let mut output = Vec::new();
+            cat(&mut OneByteReader(&input), &mut output, &options).unwrap();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_cat_utf16_explicit() {
+        let options = Options::new().encoding(Encoding::Utf16Be);
+        let mut output = Vec::new();
+        let input = utf16("x\ty", &[], u16::to_be_bytes);
+        cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"x\ty");
+
+        // An unpaired surrogate and a dangling odd byte
+        let options = Options::new().encoding(Encoding::Utf16Le);
+        let mut output = Vec::new();
+        let input = [0xff, 0xfe, b'a', 0, 0x00, 0xd8, b'b', 0, b'c'];
+        cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, "a\u{fffd}b\u{fffd}".as_bytes());
+    }
+
+    #[test]
+    fn test_cat_strip_bom() {
+        let input = "\u{feff}a\n\u{feff}b\n".as_bytes();
+        let options = Options::new().show_nonprinting(true);
+        let mut output = Vec::new();
+        cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"M-oM-;M-?a\nM-oM-;M-?b\n");
+
+        // Only the mark at the very start is removed, even across reads
+        let options = options.strip_bom(true);
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"a\nM-oM-;M-?b\n");
+
+        // A partial mark is not a mark
+        let options = Options::new().strip_bom(true);
+        for input in [&b"\xef\xbbA"[..], b"\xef", b""] {
+            let mut output = Vec::new();
+            cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+            assert_eq!(output, input);
+        }
+    }
+
+    #[test]
+    fn test_cat_strip_nonprinting() {
+        let input = b"a\x01b\tc\x1b[0m\r\n\x7f\xc3\xa9\xff\n";
+        let options = Options::new().strip_nonprinting(true);
+        assert_eq!(cat_bytes(input, &options).unwrap(), b"ab\tc[0m\n\n");
+
+        // Stripping wins over escaping, and tabs are still shown with -T
+        let options = options
+            .show_nonprinting(true)
+            .show_tabs(true)
+            .number(NumberingMode::All);
+        assert_eq!(
+            cat_bytes(input, &options).unwrap(),
+            b"     1\tab^Ic[0m\n     2\t\n"
+        );
+    }
+
+    #[test]
+    fn test_cat_strip_ansi() {
+        let input = b"\x1b[1;31merror\x1b[0m: \x1b[38;5;208mfailed\x1b[m\tnow\n\x1b(B\x1b[2K\x1b\n";
+        let expected = b"error: failed\tnow\n\x1b(B\x1b\n";
+        let options = Options::new().strip_ansi(true);
+        assert_eq!(cat_bytes(input, &options).unwrap(), expected);
+
+        // Sequences split across reads
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(output, expected);
+
+        // A sequence cut off by the end of the line is dropped
+        let options = options.show_ends(true).show_tabs(true);
+        let output = cat_bytes(b"a\x1b[3\r\nb\x1b", &options).unwrap();
+        assert_eq!(output, b"a^M$\nb\x1b");
+    }
+
+    #[test]
+    fn test_cat_interpret_escapes() {
+        let input = b"a\\tb\\x41\\x4g\\n\\q\\\\\\e[0m\\x\n\\x4\r\n";
+        let expected = b"a\tbA\x04g\n\\q\\\x1b[0m\\x\n\x04\r\n";
+        let options = Options::new().interpret_escapes(true);
+        assert_eq!(cat_bytes(input, &options).unwrap(), expected);
+
+        // Escapes split across reads
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(output, expected);
+
+        // A partial escape at the end of the input
+        let options = options.show_tabs(true);
+        assert_eq!(cat_bytes(b"\t\\t\\x4", &options).unwrap(), b"^I\t\x04");
+        assert_eq!(cat_bytes(br"\x", &options).unwrap(), br"\x");
+    }
+
+    #[test]
+    fn test_cat_trim_trailing() {
+        let input = b"a  \nb\t\t\n \t \nc\td \t\r\n\n\ne \t";
+        let options = Options::new().trim_trailing(true);
+        let expected = b"a\nb\n\nc\td\r\n\n\ne";
+        assert_eq!(cat_bytes(input, &options).unwrap(), expected);
+
+        // Lines split across reads
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(output, expected);
+
+        // `show_ends` marks the trimmed end, `show_tabs` the tabs that are left
+        let options = options.show_ends(true).show_tabs(true).squeeze_blank(true);
+        assert_eq!(
+            cat_bytes(input, &options).unwrap(),
+            b"a$\nb$\n$\nc^Id^M$\n$\ne"
+        );
+    }
+
+    #[test]
+    fn test_cat_unicode_aware() {
+        let input = "caf\u{e9} \u{1f600}\x01\u{85}\x7f\t\n".as_bytes();
+        let options = Options::new().show_nonprinting(true);
+        let mut output = Vec::new();
+        cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"cafM-CM-) M-pM-^_M-^XM-^@^AM-BM-^E^?\t\n");
+
+        let options = options.unicode_aware(true);
+        let expected = "caf\u{e9} \u{1f600}^AM-^E^?\t\n".as_bytes();
+        let mut output = Vec::new();
+        cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, expected);
+
+        // Multibyte characters split across reads
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_cat_unicode_aware_invalid() {
+        let options = Options::new().show_nonprinting(true).unicode_aware(true);
+        // A stray continuation byte, a truncated sequence before a newline, and
+        // a truncated sequence at the end of the input
+        let input = b"a\x80b\xe2\x82\n\xc3\xa9\xf0\x9f";
+        let expected = "aM-^@bM-bM-^B\n\u{e9}M-pM-^_".as_bytes();
+        let mut output = Vec::new();
+        cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, expected);
+
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_cat_invalid_utf8() {
+        let options = Options::new().show_nonprinting(true).unicode_aware(true);
+        // A lone continuation byte, a truncated sequence followed by more text,
+        // and truncated sequences before a newline and at the end of the input
+        let input = b"a\x80b\xe2\x82c\xe2\x82\n\xc3\xa9\xf0\x9f";
+        let cases = [
+            (InvalidUtf8::Escape, "aM-^@bM-bM-^BcM-bM-^B\n\u{e9}M-pM-^_"),
+            (
+                InvalidUtf8::Replace,
+                "a\u{fffd}b\u{fffd}c\u{fffd}\n\u{e9}\u{fffd}",
+            ),
+            (InvalidUtf8::Skip, "abc\n\u{e9}"),
+        ];
+        for (invalid, expected) in cases {
+            let options = options.clone().invalid_utf8(invalid);
+            for buffer_size in 1..=4 {
+                let options = options.clone().buffer_size(buffer_size);
+                assert_eq!(cat_bytes(input, &options).unwrap(), expected.as_bytes());
+            }
+            let mut output = Vec::new();
+            cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+            assert_eq!(output, expected.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_cat_hex_dump() {
+        let options = Options::new().hex_dump(true).number(NumberingMode::All);
+        let input = b"Hello, world!\n\x00\x01\xff0123456789abcdefXYZ";
+        let expected = "\
+00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|
+00000010  ff 30 31 32 33 34 35 36  37 38 39 61 62 63 64 65  |.0123456789abcde|
+00000020  66 58 59 5a                                       |fXYZ|
+00000024
+";
+        let mut output = Vec::new();
+        cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        // Rows don't depend on how the input is split into reads
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        // Or on reads interrupted by a signal
+        let mut reader = InterruptedReader {
+            inner: OneByteReader(input),
+            interrupted: false,
+        };
+        let mut output = Vec::new();
+        cat(&mut reader, &mut output, &options).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        let mut output = Vec::new();
+        cat(&mut std::io::Cursor::new(b""), &mut output, &options).unwrap();
+        assert_eq!(output, b"");
+    }
+
+    #[test]
+    fn test_cat_head_tail() {
+        let input = b"1\n2\n\n\n\n3\n4\n5";
+        let limited = |options: Options| {
+            let mut output = Vec::new();
+            cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(limited(Options::new().head(Some(2))), "1\n2\n");
+        assert_eq!(limited(Options::new().head(Some(0))), "");
+        assert_eq!(
+            limited(Options::new().head(Some(100))),
+            "1\n2\n\n\n\n3\n4\n5"
+        );
+        assert_eq!(limited(Options::new().tail(Some(2))), "4\n5");
+        assert_eq!(limited(Options::new().tail(Some(0))), "");
+        assert_eq!(limited(Options::new().head(Some(6)).tail(Some(2))), "\n3\n");
+
+        // Limits count output lines, and numbers are those of the full output
+        assert_eq!(
+            limited(
+                Options::new()
+                    .squeeze_blank(true)
+                    .number(NumberingMode::NonEmpty)
+                    .tail(Some(3))
+            ),
+            "     3\t3\n     4\t4\n     5\t5"
+        );
+        assert_eq!(
+            limited(
+                Options::new()
+                    .squeeze_blank(true)
+                    .number(NumberingMode::All)
+                    .head(Some(4))
+            ),
+            "     1\t1\n     2\t2\n     3\t\n     4\t3\n"
+        );
+    }
+
+    #[test]
+    fn test_cat_counted_head() {
+        let options = Options::new().head(Some(1)).buffer_size(2);
+        let mut output = Vec::new();
+        let stats = cat_counted(
+            &mut std::io::Cursor::new(b"ab\ncd\nef\n"),
+            &mut output,
+            &options,
+        );
+        assert_eq!(output, b"ab\n");
+        // Reading stops once enough lines have been formatted
+        assert_eq!(
+            stats.unwrap(),
+            CatStats {
+                bytes_in: 4,
+                bytes_out: 3,
+                lines: 1,
+                blanks_squeezed: 0,
+                numbered: 0,
+                truncated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_cat_line_range() {
+        let input = b"1\n2\n\n4\n5\n6";
+        let selected = |options: Options| {
+            let mut output = Vec::new();
+            cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(
+            selected(Options::new().line_range(Some((Some(2), Some(4))))),
+            "2\n\n4\n"
+        );
+        assert_eq!(
+            selected(Options::new().line_range(Some((None, Some(2))))),
+            "1\n2\n"
+        );
+        assert_eq!(
+            selected(Options::new().line_range(Some((Some(5), None)))),
+            "5\n6"
+        );
+        assert_eq!(
+            selected(Options::new().line_range(Some((Some(9), None)))),
+            ""
+        );
+        assert_eq!(
+            selected(Options::new().line_range(Some((None, None)))),
+            "1\n2\n\n4\n5\n6"
+        );
+
+        // Numbers are those of the original lines
+        assert_eq!(
+            selected(
+                Options::new()
+                    .number(NumberingMode::All)
+                    .line_range(Some((Some(3), Some(4))))
+            ),
+            "     3\t\n     4\t4\n"
+        );
+        assert_eq!(
+            selected(
+                Options::new()
+                    .number(NumberingMode::NonEmpty)
+                    .line_range(Some((Some(4), Some(5))))
+            ),
+            "     3\t4\n     4\t5\n"
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_cat_gzip() {
+        let compressed = gzip(b"a\nb\n");
+        let options = Options::new().decompress(Compression::Auto).show_ends(true);
+        let mut output = Vec::new();
+        cat(
+            &mut std::io::Cursor::new(&compressed),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(output, b"a$\nb$\n");
+
+        // Concatenated members are decompressed in turn, like zcat
+        let options = Options::new().decompress(Compression::Gzip);
+        let concatenated = [compressed.clone(), gzip(b"c\n")].concat();
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(&concatenated), &mut output, &options).unwrap();
+        assert_eq!(output, b"a\nb\nc\n");
+
+        let options = Options::new().decompress(Compression::None);
+        let mut output = Vec::new();
+        cat(
+            &mut std::io::Cursor::new(&compressed),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(output, compressed);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_cat_gzip_auto_plain() {
+        let options = Options::new().decompress(Compression::Auto);
+        for input in [&b"plain text\n"[..], b"\x1f", b""] {
+            let mut output = Vec::new();
+            cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+            assert_eq!(output, input);
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_cat_async() {
+        let input = b"a\tb\n\n\n\nc";
+        for options in [
+            Options::new(),
+            Options::new()
+                .number(NumberingMode::All)
+                .show_tabs(true)
+                .squeeze_blank(true)
+                .buffer_size(2),
+            Options::new().reverse_lines(true),
+            Options::new().max_bytes(Some(6)),
+            Options::new().max_bytes(Some(2)).buffer_size(2),
+            Options::new()
+                .number(NumberingMode::All)
+                .max_bytes(Some(30))
+                .buffer_size(2),
+        ] {
+            let mut expected = Vec::new();
+            cat(&mut &input[..], &mut expected, &options).unwrap();
+            let mut output = Vec::new();
+            cat_async(&mut &input[..], &mut output, &options)
+                .await
+                .unwrap();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_cat_tab_repr() {
+        let options = Options::new().show_tabs(true).tab_repr("    ");
+        let mut input = std::io::Cursor::new(b"a\tb\n\tc\n");
+        let mut output = Vec::new();
+        cat(&mut input, &mut output, &options).unwrap();
+        assert_eq!(output, b"a    b\n    c\n");
+
+        let options = options.show_nonprinting(true).tab_repr("\u{2192}");
+        let mut input = std::io::Cursor::new(b"a\tb\x01\n");
+        let mut output = Vec::new();
+        cat(&mut input, &mut output, &options).unwrap();
+        assert_eq!(output, "a\u{2192}b^A\n".as_bytes());
+    }
+
+    #[test]
+    fn test_write_nonprint_to_end() {
+        let mut output = Vec::new();
+        let input = b"Hello, world!";
+        let tab = b"    ";
+        let table = NonprintTable::new(tab, NulMode::Caret, false);
+        let n = write_nonprint_to_end(input, &mut output, &table).unwrap();
+        assert_eq!(n, input.len());
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_write_nonprint_to_end_all_bytes() {
+        // Every byte but \n, repeated to span several internal batches
+        let input: Vec<u8> = (0..=255u8)
+            .filter(|b| *b != b'\n')
+            .cycle()
+            .take(5000)
+            .collect();
+        for color in [false, true] {
+            let mut expected = Vec::new();
+            for byte in input.iter().copied() {
+                let escape = match byte {
+                    9 => b"^I".to_vec(),
+                    32..=126 => {
+                        expected.push(byte);
+                        continue;
+                    }
+                    0..=31 => vec![b'^', byte + 64],
+                    127 => b"^?".to_vec(),
+                    128..=159 => vec![b'M', b'-', b'^', byte - 64],
+                    160..=254 => vec![b'M', b'-', byte - 128],
+                    255 => b"M-^?".to_vec(),
+                };
+                if color && byte != 9 {
+                    expected.extend_from_slice(MARKER_COLOR.as_bytes());
+                    expected.extend_from_slice(&escape);
+                    expected.extend_from_slice(RESET_COLOR.as_bytes());
+                } else {
+                    expected.extend_from_slice(&escape);
+                }
+            }
+            let mut output = Vec::new();
+            let table = NonprintTable::new(b"^I", NulMode::Caret, color);
+            let n = write_nonprint_to_end(&input, &mut output, &table).unwrap();
+            assert_eq!(n, input.len());
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_write_end_of_line() {
+        let mut output = Vec::new();
+        let input = b"\n";
+        write_end_of_line(&mut output, input).unwrap();
+        assert_eq!(output, input);
+    }
+
+    // Copilot: test cat stuff with unicode, nonprinting, an assorted set of options
+
+    #[test]
+    fn test_cat_files_not_found() {
+        let options = Options::new();
+        let files = vec!["nonexistent_file".to_string()];
+        let result = cat_files(&files, &options);
+        assert!(result.is_err());
+        let CatFilesError::Failed { errors, .. } = result.unwrap_err() else {
+            panic!("expected CatFilesError::Failed");
+        };
+        assert!(matches!(errors[..], [CatFilesError::NotFound(_)]));
+
+        // Names that aren't UTF-8 are reported lossily
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let files = [Path::new(std::ffi::OsStr::from_bytes(b"nonexistent_\xff"))];
+            let Err(CatFilesError::Failed { errors, .. }) = cat_files(&files, &options) else {
+                panic!("expected CatFilesError::Failed");
+            };
+            assert!(
+                matches!(&errors[..], [CatFilesError::NotFound(name)] if name == "nonexistent_\u{fffd}")
+            );
+        }
+    }
+
+    #[test]
+    fn test_cat_files_to_continues_after_error() {
+        let file = temp_file("continue-after-error", b"exists\n");
+        let options = Options::new();
+        let mut output = Vec::new();
+        let result = cat_files_to(
+            &["nonexistent_file", ".", &file, "nonexistent_file"].map(String::from),
+            &mut output,
+            &options,
+        );
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(output, b"exists\n");
+        let Err(CatFilesError::Failed { errors, stats }) = result else {
+            panic!("expected CatFilesError::Failed");
+        };
+        assert!(matches!(
+            errors[..],
+            [
+                CatFilesError::NotFound(_),
+                CatFilesError::IsDirectory(_),
+                CatFilesError::NotFound(_),
+            ]
+        ));
+        assert_eq!(stats.lines, 1);
+    }
+
+    #[test]
+    fn test_cat_file_list_to() {
+        let first = temp_file("file-list-1", b"a\n");
+        let second = temp_file("file-list-2", b"b\n");
+        for separator in [b'\n', b'\0'] {
+            let mut names = Vec::new();
+            for name in [&first, &second, "nonexistent_file", "", &first] {
+                names.extend_from_slice(name.as_bytes());
+                names.push(separator);
+            }
+            let list = temp_file("file-list", &names);
+            let mut output = Vec::new();
+            let result = cat_file_list_to(&list, separator, &mut output, &Options::new());
+            std::fs::remove_file(list).unwrap();
+            assert_eq!(output, b"a\nb\na\n");
+            assert!(matches!(
+                result,
+                Err(CatFilesError::Failed { ref errors, .. })
+                    if matches!(errors[..], [CatFilesError::NotFound(_)])
+            ));
+        }
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+
+        let result = cat_file_list_to("nonexistent_list", b'\n', &mut Vec::new(), &Options::new());
+        assert!(matches!(result, Err(CatFilesError::NotFound(_))));
+    }
+
+    /// Write `contents` to a fresh file in the temp directory and return its path
+    fn temp_file(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("ccc-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_cat_files_to() {
+        let first = temp_file("cat-files-to-1", b"a\n");
+        let second = temp_file("cat-files-to-2", b"b\n");
+        let options = Options::new().show_ends(true);
+        let mut output = Vec::new();
+        let result = cat_files_to(&[&first, &second, &first], &mut output, &options);
+        assert!(result.is_ok());
+        assert_eq!(output, b"a$\nb$\na$\n");
+
+        // Any kind of path will do
+        let paths = [std::path::PathBuf::from(&first)];
+        let mut output = Vec::new();
+        cat_files_to(&paths, &mut output, &options).unwrap();
+        let mut output_str = Vec::new();
+        cat_files_to(&[second.as_str()], &mut output_str, &options).unwrap();
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+        assert_eq!((&output[..], &output_str[..]), (&b"a$\n"[..], &b"b$\n"[..]));
+    }
+
+    #[test]
+    fn test_cat_files_ensure_trailing_newline() {
+        let first = temp_file("trailing-newline-1", b"a\nb");
+        let second = temp_file("trailing-newline-2", b"c\n");
+        let files = [&first, &second, &first];
+        let options = Options::new().ensure_trailing_newline(true);
+        let mut output = Vec::new();
+        let stats = cat_files_to(&files, &mut output, &options).unwrap();
+        assert_eq!(output, b"a\nb\nc\na\nb\n");
+        assert_eq!((stats.lines, stats.bytes_out), (5, 10));
+
+        let options = options.show_ends(true).number(NumberingMode::All);
+        let mut output = Vec::new();
+        let result = cat_files_to(&files, &mut output, &options);
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+        assert_eq!(result.unwrap().lines, 5);
+        assert_eq!(
+            output,
+            b"     1\ta$\n     2\tb$\n     3\tc$\n     4\ta$\n     5\tb$\n"
+        );
+    }
+
+    #[test]
+    fn test_cat_files_number_reset_per_file() {
+        let first = temp_file("number-reset-1", b"a\n\nb\n");
+        let second = temp_file("number-reset-2", b"c\n");
+        let files = [&first, &second];
+        let numbered = |options: Options| {
+            let mut output = Vec::new();
+            cat_files_to(&files, &mut output, &options).unwrap();
+            let mut parallel = Vec::new();
+            cat_files_parallel_to(&files, &mut parallel, &options, 2).unwrap();
+            assert_eq!(parallel, output);
+            String::from_utf8(output).unwrap()
+        };
+
+        let options = Options::new().number(NumberingMode::NonEmpty);
+        assert_eq!(
+            numbered(options.clone()),
+            "     1\ta\n\n     2\tb\n     3\tc\n"
+        );
+        assert_eq!(
+            numbered(options.clone().number_start(10)),
+            "    10\ta\n\n    11\tb\n    12\tc\n"
+        );
+        let result = numbered(options.number_reset_per_file(true));
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+        assert_eq!(result, "     1\ta\n\n     2\tb\n     1\tc\n");
+    }
+
+    #[test]
+    fn test_cat_files_with_filename() {
+        let first = temp_file("with-filename-1", b"a\nb");
+        let second = temp_file("with-filename-2", b"c\n");
+        let files = [&first, &second];
+        let options = Options::new()
+            .number(NumberingMode::All)
+            .with_filename(true);
+        let mut output = Vec::new();
+        let stats = cat_files_to(&files, &mut output, &options).unwrap();
+        // The first file's last line is ended before the second file's name
+        let expected = format!("{first}:     1\ta\n{first}:     2\tb\n{second}:     3\tc\n");
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!((stats.bytes_out, stats.lines), (expected.len(), 3));
+
+        // And before the second file's header
+        let options = Options::new().number(NumberingMode::All).headers(true);
+        let mut output = Vec::new();
+        let stats = cat_files_to(&files, &mut output, &options).unwrap();
+        let expected =
+            format!("==> {first} <==\n     1\ta\n     2\tb\n\n==> {second} <==\n     3\tc\n");
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!((stats.bytes_out, stats.lines), (expected.len(), 3));
+
+        let options = Options::new().headers(true);
+        let mut output = Vec::new();
+        let stats = cat_files_parallel_to(&files, &mut output, &options, 2);
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+        let expected = format!("==> {first} <==\na\nb\n==> {second} <==\nc\n");
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!(stats.unwrap().bytes_out, expected.len());
+    }
+
+    /// Follow `files` with `options` while appending `appended` to the last
+    fn follow_appending(files: &[&str], appended: &[u8], options: &Options) -> (Vec<u8>, CatStats) {
+        let appender = std::thread::spawn({
+            let last = files[files.len() - 1].to_string();
+            let appended = appended.to_vec();
+            move || {
+                std::thread::sleep(std::time::Duration::from_millis(300));
+                let mut file = std::fs::OpenOptions::new().append(true).open(last).unwrap();
+                file.write_all(&appended).unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(600));
+                assert!(stop_following());
+            }
+        });
+        let mut output = Vec::new();
+        let stats = cat_files_to(files, &mut output, options).unwrap();
+        appender.join().unwrap();
+        (output, stats)
+    }
+
+    #[test]
+    fn test_cat_files_follow() {
+        let first = temp_file("follow-1", b"a\n");
+        let second = temp_file("follow-2", b"b\n");
+        let options = Options::new().number(NumberingMode::All).follow(true);
+        let (output, stats) = follow_appending(&[&first, &second], b"c\nd", &options);
+        assert_eq!(output, b"     1\ta\n     2\tb\n     3\tc\n     4\td");
+        assert_eq!(stats.lines, 4);
+        // Nothing is being followed any more
+        assert!(!stop_following());
+
+        // Stopping one follow doesn't stop the next
+        std::fs::write(&second, b"b\n").unwrap();
+        let options = Options::new().with_filename(true).follow(true);
+        let (output, _) = follow_appending(&[&first, &second], b"c\n", &options);
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+        let expected = format!("{first}:a\n{second}:b\n{second}:c\n");
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_cat_trailing_carriage_return() {
+        let options = Options::new().show_ends(true);
+        let mut output = Vec::new();
+        cat(&mut std::io::Cursor::new(b"a\r"), &mut output, &options).unwrap();
+        assert_eq!(output, b"a\r");
+    }
+
+    #[test]
+    fn test_cat_files_to_carriage_return_boundary() {
+        let first = temp_file("cr-boundary-1", b"a\r");
+        let second = temp_file("cr-boundary-2", b"\nb\n");
+        let options = Options::new().show_ends(true).squeeze_blank(true);
+        let mut output = Vec::new();
+        let result = cat_files_to(&[&first, &second], &mut output, &options);
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+        assert!(result.is_ok());
+        // The first file's \r is held back, and pairs up with the second
+        // file's leading \n as if they were one input
+        assert_eq!(output, b"a^M$\nb$\n");
+    }
+
+    #[test]
+    fn test_cat_files_as_one_input() {
+        let first = temp_file("one-input-1", b"a\nb");
+        let second = temp_file("one-input-2", b"c\n\n");
+        let third = temp_file("one-input-3", b"\n\nd\n");
+        let list = temp_file("one-input-list", format!("{first}\n{second}\n").as_bytes());
+
+        // A last line without a line feed runs into the next file
+        let options = Options::new().number(NumberingMode::All);
+        let mut output = Vec::new();
+        let stats = cat_files_to(&[&first, &second], &mut output, &options).unwrap();
+        assert_eq!(output, b"     1\ta\n     2\tbc\n     3\t\n");
+        assert_eq!((stats.lines, stats.numbered), (3, 3));
+        let mut listed = Vec::new();
+        cat_file_list_to(&list, b'\n', &mut listed, &options).unwrap();
+        assert_eq!(listed, output);
+        // Even when the first is decompressed
+        #[cfg(feature = "gzip")]
+        {
+            let compressed = temp_file("one-input-gz", &gzip(b"a\nb"));
+            let options = options.clone().decompress(Compression::Auto);
+            let mut decompressed = Vec::new();
+            cat_files_to(&[&compressed, &second], &mut decompressed, &options).unwrap();
+            std::fs::remove_file(compressed).unwrap();
+            assert_eq!(decompressed, output);
+        }
+        // Lines are counted the same way when nothing needs formatting
+        let stats = cat_files_to(&[&first, &second], &mut Vec::new(), &Options::new()).unwrap();
+        assert_eq!(stats.lines, 3);
+        let partial = temp_file("one-input-partial", b"x");
+        for options in [Options::new(), Options::new().show_ends(true)] {
+            let files = [&partial, &partial, &partial];
+            let stats = cat_files_to(&files, &mut Vec::new(), &options).unwrap();
+            assert_eq!(stats.lines, 1);
+        }
+        // And a header still ends the line before it
+        let options = Options::new().headers(true);
+        let mut output = Vec::new();
+        let stats = cat_files_to(&[&partial, &partial], &mut output, &options).unwrap();
+        std::fs::remove_file(&partial).unwrap();
+        let header = format!("==> {partial} <==\n");
+        assert_eq!(output, format!("{header}x\n\n{header}x").into_bytes());
+        assert_eq!(stats.lines, 2);
+
+        // A run of blank lines is squeezed across files
+        let options = Options::new().squeeze_blank(true);
+        let mut output = Vec::new();
+        let stats = cat_files_to(&[&second, &third], &mut output, &options).unwrap();
+        for file in [first, second, third, list] {
+            std::fs::remove_file(file).unwrap();
+        }
+        assert_eq!(output, b"c\n\nd\n");
+        assert_eq!(stats.blanks_squeezed, 2);
+    }
+
+    #[test]
+    fn test_cat_files_parallel_to() {
+        let files: Vec<String> = (0..8)
+            .map(|i| {
+                temp_file(
+                    &format!("parallel-{i}"),
+                    format!("{i}\n").repeat(i).as_bytes(),
+                )
+            })
+            .chain(["missing-parallel".to_string()])
+            .collect();
+        let options = Options::new().number(NumberingMode::All);
+        let mut expected = Vec::new();
+        let sequential = cat_files_to(&files, &mut expected, &options);
+        let mut output = Vec::new();
+        let parallel = cat_files_parallel_to(&files, &mut output, &options, 3);
+        // The files share one max_bytes
+        let mut limited = Vec::new();
+        let options = Options::new().max_bytes(Some(8));
+        cat_files_parallel_to(&files[3..5], &mut limited, &options, 2).unwrap();
+        assert_eq!(limited, b"3\n3\n3\n4\n");
+        for file in &files[..8] {
+            std::fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!(output, expected);
+        match (sequential, parallel) {
+            (
+                Err(CatFilesError::Failed { errors, stats }),
+                Err(CatFilesError::Failed {
+                    errors: parallel_errors,
+                    stats: parallel_stats,
+                }),
+            ) => {
+                assert!(matches!(errors[..], [CatFilesError::NotFound(_)]));
+                assert!(matches!(parallel_errors[..], [CatFilesError::NotFound(_)]));
+                assert_eq!(parallel_stats, stats);
+            }
+            result => panic!("unexpected results: {result:?}"),
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_cat_files_to_mmap() {
+        let large = b"line\tof text\n".repeat(100_000);
+        let files = [
+            temp_file("mmap-large", &large),
+            temp_file("mmap-small", b"a\tb"),
+        ];
+        let options = Options::new().show_tabs(true);
+        let mut expected = Vec::new();
+        cat_files_to(&files, &mut expected, &options).unwrap();
+        let mut output = Vec::new();
+        let stats = cat_files_to(&files, &mut output, &options.mmap(true)).unwrap();
+        for file in &files {
+            std::fs::remove_file(file).unwrap();
+        }
+        assert_eq!(output, expected);
+        assert_eq!(stats.bytes_in, large.len() + 3);
+    }
+
+    #[test]
+    fn test_cat_files_directory() {
+        let options = Options::new();
+        let files = vec![".".to_string()];
+        let result = cat_files(&files, &options);
+        let Err(CatFilesError::Failed { errors, .. }) = result else {
+            panic!("expected CatFilesError::Failed");
+        };
+        assert!(matches!(&errors[..], [CatFilesError::IsDirectory(path)] if path == "."));
+    }
+
+    #[test]
+    fn test_cat_files_split() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("ccc-split-input-{}", std::process::id()));
+        std::fs::write(&input, "a\nb\nc\nd\ne\n").unwrap();
+        let prefix = dir.join(format!("ccc-split-{}-", std::process::id()));
+        let chunk = |n: usize| format!("{}{n:02}", prefix.display());
+        let options = Options::new().number(NumberingMode::All);
+        let result = cat_files_split(&[&input], &prefix, 2, &options);
+        std::fs::remove_file(&input).unwrap();
+        assert_eq!(result.unwrap().lines, 5);
+
+        // Numbering carries on across the chunks, and no empty chunk is left
+        let chunks: Vec<String> = (0..3)
+            .map(|n| std::fs::read_to_string(chunk(n)).unwrap())
+            .collect();
+        let exists = Path::new(&chunk(3)).exists();
+        for n in 0..3 {
+            std::fs::remove_file(chunk(n)).unwrap();
+        }
+        assert_eq!(
+            chunks,
+            [
+                "     1\ta\n     2\tb\n",
+                "     3\tc\n     4\td\n",
+                "     5\te\n",
+            ]
+        );
+        assert!(!exists);
+
+        let suffixes = [0, 89, 90, 989, 990].map(split::chunk_suffix);
+        assert_eq!(suffixes, ["00", "89", "9000", "9899", "990000"]);
+    }
+
+    #[test]
+    fn test_tee_writer() {
+        let options = Options::new().number(NumberingMode::All).show_ends(true);
+        let mut output = TeeWriter::new(Vec::new(), Vec::new());
+        let mut input = std::io::Cursor::new("a\n\nb");
+        cat(&mut input, &mut output, &options).unwrap();
+        let (stdout, copy) = output.into_inner();
+        assert_eq!(stdout, b"     1\ta$\n     2\t$\n     3\tb");
+        assert_eq!(copy, stdout);
+    }
+
+    #[test]
+    fn test_cat_files_check() {
+        let file = std::env::temp_dir().join(format!("ccc-check-{}", std::process::id()));
+        std::fs::write(&file, "a\n").unwrap();
+        let options = Options::new().check(true).headers(true);
+        let mut output = Vec::new();
+        let result = cat_files_to(&[file.as_path()], &mut output, &options);
+        assert_eq!(result.unwrap(), CatStats::default());
+
+        let files = [
+            file.as_path(),
+            Path::new("nonexistent_file"),
+            Path::new("."),
+        ];
+        let result = cat_files_to(&files, &mut output, &options);
+        std::fs::remove_file(&file).unwrap();
+        let Err(CatFilesError::Failed { errors, .. }) = result else {
+            panic!("expected CatFilesError::Failed");
+        };
+        assert!(matches!(
+            errors[..],
+            [CatFilesError::NotFound(_), CatFilesError::IsDirectory(_)]
+        ));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_cat_files_repeated() {
+        let file = std::env::temp_dir().join(format!("ccc-repeat-{}", std::process::id()));
+        let other = std::env::temp_dir().join(format!("ccc-repeat-other-{}", std::process::id()));
+        std::fs::write(&file, "a\nb\n").unwrap();
+        std::fs::write(&other, "c\n").unwrap();
+        let files = [&file, &file, &other, &file, &file];
+        // The same names in a list, with an empty name between two of them
+        let list = std::env::temp_dir().join(format!("ccc-repeat-list-{}", std::process::id()));
+        let names = files.map(|file| file.display().to_string());
+        std::fs::write(&list, names.join("\n").replacen("\n", "\n\n", 1)).unwrap();
+        let repeated = |options: &Options| {
+            let mut output = Vec::new();
+            cat_files_to(&files, &mut output, options).unwrap();
+            let mut listed = Vec::new();
+            cat_file_list_to(&list, b'\n', &mut listed, options).unwrap();
+            assert_eq!(listed, output);
+            String::from_utf8(output).unwrap()
+        };
+
+        let options = Options::new().number(NumberingMode::All);
+        let expected = (1..=9)
+            .zip(["a", "b", "a", "b", "c", "a", "b", "a", "b"])
+            .map(|(n, line)| format!("{n:>6}\t{line}\n"))
+            .collect::<String>();
+        assert_eq!(repeated(&options), expected);
+        // Only the first line is read, so the rest must not be taken from it
+        assert_eq!(repeated(&Options::new().head(Some(1))), "a\na\nc\na\na\n");
+        std::fs::remove_file(&file).unwrap();
+        std::fs::remove_file(&other).unwrap();
+        std::fs::remove_file(&list).unwrap();
+    }
+
+    #[test]
+    fn test_cat_fast() {
+        let options = Options::new();
         let mut input = std::io::Cursor::new(b"Hello, world!");
         let mut output = Vec::new();
-        let result = cat_fast(&mut input, &mut output, &options);
-        assert!(result.is_ok());
-        assert_eq!(output, b"Hello, world!");
+        let result = cat_fast(&mut input, &mut output, &options);
+        assert!(result.is_ok());
+        assert_eq!(output, b"Hello, world!");
+    }
+
+    /// A writer that accepts at most 7 bytes per `write` call
+    struct ShortWriter(Vec<u8>);
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(7);
+            self.0.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cat_fast_partial_writes() {
+        let options = Options::new();
+        let data = b"The quick brown fox jumps over the lazy dog".repeat(100);
+        let mut input = std::io::Cursor::new(&data);
+        let mut output = ShortWriter(Vec::new());
+        let result = cat_fast(&mut input, &mut output, &options);
+        assert!(result.is_ok());
+        assert_eq!(output.0, data);
+    }
+
+    /// A writer whose reader has gone away
+    struct BrokenPipeWriter;
+
+    impl Write for BrokenPipeWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A reader that fails after its first `len` bytes
+    struct FailingReader {
+        len: usize,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.len == 0 {
+                return Err(std::io::Error::other("disk on fire"));
+            }
+            let n = self.len.min(buf.len());
+            buf[..n].fill(b'x');
+            self.len -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_cat_read_error() {
+        for options in [Options::new(), Options::new().number(NumberingMode::All)] {
+            let mut output = Vec::new();
+            let result = cat(&mut FailingReader { len: 5 }, &mut output, &options);
+            assert!(matches!(result, Err(CatError::Io(_))));
+
+            let result = cat_checked(
+                Path::new("flaky"),
+                &mut FailingReader { len: 5 },
+                &mut output,
+                &options,
+                None,
+                None,
+            );
+            let Err(CatFilesError::Io { path, source }) = result else {
+                panic!("expected CatFilesError::Io");
+            };
+            assert_eq!(path, "flaky");
+            assert_eq!(source.to_string(), "disk on fire");
+        }
+
+        // Errors writing the output are not blamed on the input
+        let result = cat_checked(
+            Path::new("flaky"),
+            &mut FailingReader { len: 5 },
+            &mut BrokenPipeWriter,
+            &Options::new(),
+            None,
+            None,
+        );
+        assert!(matches!(result, Err(CatFilesError::Output(_))));
+    }
+
+    #[test]
+    fn test_progress() {
+        let input = vec![b'a'; 10 << 20];
+        let mut status = Vec::new();
+        let mut progress =
+            ProgressReader::new(&input[..], &mut status, "big".to_string(), Some(10 << 20));
+        let mut output = Vec::new();
+        std::io::copy(&mut progress, &mut output).unwrap();
+        drop(progress);
+        assert_eq!(output, input);
+        let status = String::from_utf8(status).unwrap();
+        assert!(status.starts_with("\rbig: 4194304 bytes (40%)\rbig: 8388608 bytes (80%)"));
+        assert!(status.ends_with("\r\x1b[K"));
+
+        // Small inputs never show a line, so there is nothing to clear
+        let mut status = Vec::new();
+        let mut progress = ProgressReader::new(&b"a\n"[..], &mut status, "small".to_string(), None);
+        std::io::copy(&mut progress, &mut std::io::sink()).unwrap();
+        drop(progress);
+        assert!(status.is_empty());
+    }
+
+    /// A writer that records how much had been written at each flush
+    #[derive(Default)]
+    struct FlushRecorder {
+        written: Vec<u8>,
+        flushes: Vec<usize>,
+    }
+
+    impl Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushes.push(self.written.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cat_flush() {
+        let options = Options::new().flush(FlushMode::Line);
+        let mut output = FlushRecorder::default();
+        cat(
+            &mut std::io::Cursor::new(b"a\n\nbc\nd"),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(output.written, b"a\n\nbc\nd");
+        assert_eq!(output.flushes, [2, 3, 6]);
+
+        let file = temp_file("flush", b"a\n");
+        let files = [&file, &file];
+        for (flush, flushes) in [
+            (FlushMode::Line, &[2, 4][..]),
+            (FlushMode::Block, &[]),
+            (FlushMode::Never, &[]),
+        ] {
+            let mut output = FlushRecorder::default();
+            cat_files_to(&files, &mut output, &Options::new().flush(flush)).unwrap();
+            assert_eq!(output.flushes, flushes);
+        }
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_cat_broken_pipe() {
+        for options in [
+            Options::new().number(NumberingMode::All),
+            Options::new().show_tabs(true),
+            Options::new().show_nonprinting(true),
+        ] {
+            let mut input = std::io::Cursor::new(b"Hello,\tworld!\n");
+            let result = cat(&mut input, &mut BrokenPipeWriter, &options);
+            assert!(matches!(result, Err(CatError::Io(_))));
+        }
+    }
+
+    #[test]
+    fn test_cat_small_buffer_size() {
+        let data = b"a\tb\nc\x01d\n".repeat(10);
+        let formatted = |options: Options| {
+            let options = options
+                .number(NumberingMode::All)
+                .show_nonprinting(true)
+                .show_tabs(true)
+                .show_ends(true);
+            let mut output = Vec::new();
+            cat(&mut std::io::Cursor::new(&data), &mut output, &options).unwrap();
+            output
+        };
+        let expected = formatted(Options::new());
+        for size in [1, 2, 3, 7] {
+            assert_eq!(formatted(Options::new().buffer_size(size)), expected);
+        }
+    }
+
+    #[test]
+    fn test_cat_zero_buffer_size() {
+        let options = Options::new().buffer_size(0).number(NumberingMode::All);
+        let mut input = std::io::Cursor::new(b"a\nb\n");
+        let mut output = Vec::new();
+        cat(&mut input, &mut output, &options).unwrap();
+        assert_eq!(output, b"     1\ta\n     2\tb\n");
+    }
+
+    #[test]
+    fn test_options_lines() {
+        let options = Options::new()
+            .number(NumberingMode::All)
+            .show_tabs(true)
+            .show_ends(true)
+            .buffer_size(3);
+        let input = std::io::Cursor::new(b"a\tb\n\nlast");
+        let lines = options.lines(input).collect::<CatResult<Vec<_>>>().unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                b"     1\ta^Ib$\n".to_vec(),
+                b"     2\t$\n".to_vec(),
+                b"     3\tlast".to_vec(),
+            ]
+        );
+
+        // Only the lines that fit within max_bytes
+        let options = options.max_bytes(Some(20));
+        let input = std::io::Cursor::new(b"a\tb\n\nlast");
+        let lines = options.lines(input).collect::<CatResult<Vec<_>>>().unwrap();
+        assert_eq!(lines, vec![b"     1\ta^Ib$\n".to_vec()]);
+        let options = options.max_bytes(Some(3));
+        let input = std::io::Cursor::new(b"a\tb\n\nlast");
+        let lines = options.lines(input).collect::<CatResult<Vec<_>>>().unwrap();
+        assert_eq!(lines, vec![b"   ".to_vec()]);
+    }
+
+    #[test]
+    fn test_cat_wrap() {
+        let input = "abcdefghij\nab\tcd\n\néééé\n";
+        for buffer_size in 1..=6 {
+            let options = Options::new().wrap(Some(4)).buffer_size(buffer_size);
+            let output = cat_str(input, &options).unwrap();
+            // A tab wider than the space left moves to the next line
+            assert_eq!(output, "abcd\nefgh\nij\nab\n\t\ncd\n\néééé\n");
+
+            // Columns are counted as shown, and continuation lines line up
+            let options = options.show_tabs(true).number(NumberingMode::All);
+            let output = cat_str(input, &options).unwrap();
+            assert_eq!(
+                output,
+                "     1\tabcd\n      \tefgh\n      \tij\n     2\tab^I\n      \tcd\n     3\t\n     4\téééé\n"
+            );
+        }
+
+        // So do they after offsets
+        let options = Options::new()
+            .wrap(Some(6))
+            .offsets(Some(OffsetFormat::Decimal));
+        let output = cat_str("0123456789abcdef", &options).unwrap();
+        assert_eq!(output, "     0\t012345\n      \t6789ab\n      \tcdef");
+
+        // Color codes take no columns
+        let options = Options::new()
+            .wrap(Some(3))
+            .show_nonprinting(true)
+            .color(ColorChoice::Always);
+        let output = cat_bytes(b"a\x01b\n", &options).unwrap();
+        assert_eq!(output, b"a\x1b[36m^A\x1b[0m\nb\n");
+    }
+
+    #[test]
+    fn test_cat_filter() {
+        let input = b"error: a\nok\n\nerror: b\nlast error";
+        for buffer_size in 1..=6 {
+            let options = Options::new()
+                .filter(Some("error".to_string()))
+                .number(NumberingMode::All)
+                .buffer_size(buffer_size);
+            let output = cat_bytes(input, &options).unwrap();
+            assert_eq!(
+                output,
+                b"     1\terror: a\n     4\terror: b\n     5\tlast error"
+            );
+
+            let options = options.invert_filter(true).number(NumberingMode::NonEmpty);
+            let output = cat_bytes(input, &options).unwrap();
+            assert_eq!(output, b"     2\tok\n\n");
+        }
+    }
+
+    #[test]
+    fn test_cat_uniq() {
+        let input = b"a\na\nb\na\n";
+        for buffer_size in 1..=6 {
+            let options = Options::new().uniq(true).buffer_size(buffer_size);
+            assert_eq!(cat_bytes(input, &options).unwrap(), b"a\nb\na\n");
+
+            // Numbers follow the input lines
+            let options = options.number(NumberingMode::All);
+            assert_eq!(
+                cat_bytes(input, &options).unwrap(),
+                b"     1\ta\n     3\tb\n     4\ta\n"
+            );
+
+            // With counts, numbers follow the output lines
+            let options = options.uniq_count(true);
+            assert_eq!(
+                cat_bytes(input, &options).unwrap(),
+                b"     1\t      2 a\n     2\t      1 b\n     3\t      1 a\n"
+            );
+        }
+
+        let options = Options::new().uniq(true).squeeze_blank(true);
+        let output = cat_bytes(b"a\n\n\n\nb\nb", &options).unwrap();
+        assert_eq!(output, b"a\n\nb\n");
+    }
+
+    #[test]
+    fn test_cat_squeeze_repeats() {
+        let input = b"a\na\nb\n\n\n\na\nb\nb";
+        for buffer_size in 1..=6 {
+            let options = Options::new()
+                .squeeze_repeats(true)
+                .buffer_size(buffer_size);
+            assert_eq!(cat_bytes(input, &options).unwrap(), b"a\nb\n\na\nb\n");
+
+            // The kept line gets one number
+            let options = options.number(NumberingMode::All);
+            assert_eq!(
+                cat_bytes(input, &options).unwrap(),
+                b"     1\ta\n     2\tb\n     3\t\n     4\ta\n     5\tb\n"
+            );
+        }
+
+        // Blank lines are left to `squeeze_blank`
+        let options = Options::new()
+            .squeeze_repeats(true)
+            .squeeze_blank(true)
+            .max_blank(2)
+            .number(NumberingMode::NonEmpty);
+        assert_eq!(
+            cat_bytes(input, &options).unwrap(),
+            b"     1\ta\n     2\tb\n\n\n     3\ta\n     4\tb\n"
+        );
+    }
+
+    #[test]
+    fn test_cat_show_lf() {
+        let input = b"a\n\nb";
+        let options = Options::new().show_lf(Some(ShowLf::Break));
+        assert_eq!(cat_bytes(input, &options).unwrap(), b"a\\n\n\\n\nb");
+
+        // After the `show_ends` marker
+        let options = options.show_ends(true);
+        assert_eq!(cat_bytes(input, &options).unwrap(), b"a$\\n\n$\\n\nb");
+
+        // Without the line ends, lines are still numbered
+        let options = Options::new()
+            .show_lf(Some(ShowLf::Inline))
+            .number(NumberingMode::All);
+        assert_eq!(
+            cat_bytes(input, &options).unwrap(),
+            b"     1\ta\\n     2\t\\n     3\tb"
+        );
+        let options = options
+            .number(NumberingMode::None)
+            .color(ColorChoice::Always);
+        let expected = format!("a{MARKER_COLOR}\\n{RESET_COLOR}b");
+        assert_eq!(cat_bytes(b"a\nb", &options).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_cat_nul() {
+        let input = b"a\0b\x01\n";
+        for (nul, plain, nonprinting) in [
+            (None, &b"a\0b\x01\n"[..], &b"a^@b^A\n"[..]),
+            (Some(NulMode::Keep), b"a\0b\x01\n", b"a\0b^A\n"),
+            (Some(NulMode::Caret), b"a^@b\x01\n", b"a^@b^A\n"),
+            (Some(NulMode::Replace('.')), b"a.b\x01\n", b"a.b^A\n"),
+            (
+                Some(NulMode::Replace('\u{2400}')),
+                "a\u{2400}b\x01\n".as_bytes(),
+                "a\u{2400}b^A\n".as_bytes(),
+            ),
+        ] {
+            let options = Options::new().nul(nul);
+            assert_eq!(cat_bytes(input, &options).unwrap(), plain, "{nul:?}");
+            let options = options.show_nonprinting(true);
+            assert_eq!(
+                cat_bytes(input, &options).unwrap(),
+                nonprinting,
+                "{nul:?} with -v"
+            );
+            let options = options.unicode_aware(true);
+            assert_eq!(
+                cat_bytes(input, &options).unwrap(),
+                nonprinting,
+                "{nul:?} with -v, unicode"
+            );
+        }
+
+        // Along with other formatting, and colored like the other markers
+        let options = Options::new()
+            .nul(Some(NulMode::Caret))
+            .number(NumberingMode::All)
+            .color(ColorChoice::Always);
+        let expected =
+            format!("{NUMBER_COLOR}     1{RESET_COLOR}\ta{MARKER_COLOR}^@{RESET_COLOR}\n");
+        assert_eq!(cat_bytes(b"a\0\n", &options).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_cat_offsets() {
+        let input = "ab\n\ncd\r\n\rx  \n".repeat(2) + "y";
+        for buffer_size in 1..=6 {
+            let options = Options::new()
+                .offsets(Some(OffsetFormat::Decimal))
+                .buffer_size(buffer_size);
+            let expected = [
+                "     0\tab",
+                "     3\t",
+                "     4\tcd\r",
+                "     8\t\rx  ",
+                "    13\tab",
+                "    16\t",
+                "    17\tcd\r",
+                "    21\t\rx  ",
+                "    26\ty",
+            ];
+            let output = cat_bytes(input.as_bytes(), &options).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected.join("\n"));
+
+            // Lines keep the offsets they were read at when others are dropped
+            // or held back, or they are trimmed
+            let options = options
+                .offsets(Some(OffsetFormat::Hex))
+                .number_width(2)
+                .number_sep(": ")
+                .filter(Some("x".to_string()))
+                .trim_trailing(true)
+                .uniq(true)
+                .uniq_count(true);
+            let expected = " 8:       2 \rx\n";
+            assert_eq!(cat_str(&input, &options).unwrap(), expected);
+        }
+
+        let options = Options::new().offsets(Some(OffsetFormat::Hex)).json(true);
+        let output = cat_str("a\n\nb\n", &options).unwrap();
+        let expected = [
+            r#"{"offset": 0, "text": "a"}"#,
+            r#"{"offset": 2, "text": ""}"#,
+            r#"{"offset": 3, "text": "b"}"#,
+            "",
+        ];
+        assert_eq!(output, expected.join("\n"));
+    }
+
+    #[test]
+    fn test_cat_json() {
+        let input = "say \"hi\"\n\n\n\nC:\\dir\tx\x01\nna\u{ef}ve \u{1f600}";
+        for buffer_size in 1..=6 {
+            let options = Options::new().json(true).buffer_size(buffer_size);
+            let expected = [
+                r#"{"text": "say \"hi\""}"#,
+                r#"{"text": ""}"#,
+                r#"{"text": ""}"#,
+                r#"{"text": ""}"#,
+                r#"{"text": "C:\\dir\tx\u0001"}"#,
+                "{\"text\": \"na\u{ef}ve \u{1f600}\"}",
+                "",
+            ];
+            let output = cat_bytes(input.as_bytes(), &options).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected.join("\n"));
+
+            // -v doesn't change the text, and numbering follows -b and -s
+            let options = options
+                .show_nonprinting(true)
+                .number(NumberingMode::NonEmpty)
+                .squeeze_blank(true);
+            let expected = [
+                r#"{"n": 1, "text": "say \"hi\""}"#,
+                r#"{"text": ""}"#,
+                r#"{"n": 2, "text": "C:\\dir\tx\u0001"}"#,
+                "{\"n\": 3, \"text\": \"na\u{ef}ve \u{1f600}\"}",
+                "",
+            ];
+            let output = cat_bytes(input.as_bytes(), &options).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected.join("\n"));
+        }
+
+        // Only the lines that pass the filter
+        let options = Options::new()
+            .json(true)
+            .number(NumberingMode::All)
+            .filter(Some("dir".to_string()));
+        let output = cat_bytes(input.as_bytes(), &options).unwrap();
+        assert_eq!(output, b"{\"n\": 5, \"text\": \"C:\\\\dir\\tx\\u0001\"}\n");
+    }
+
+    #[test]
+    fn test_cat_line_terminator() {
+        let options = Options::new().line_terminator("\0");
+        assert_eq!(cat_bytes(b"a\nb\n", &options).unwrap(), b"a\0b\0");
+
+        // The end marker still comes first
+        let options = options.show_ends(true);
+        assert_eq!(cat_bytes(b"a\n\nb", &options).unwrap(), b"a$\0$\0b");
+
+        let options = options.tail(Some(2));
+        assert_eq!(cat_bytes(b"a\nb\nc\n", &options).unwrap(), b"b$\0c$\0");
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn test_cat_output_encoding() {
+        let input = "caf\u{e9} \u{2192} \u{1f600}\n".as_bytes();
+        let options = Options::new().output_encoding(OutputEncoding::Latin1);
+        assert_eq!(cat_bytes(input, &options).unwrap(), b"caf\xe9 ? ?\n");
+        let options = options
+            .unmappable(Unmappable::Drop)
+            .number(NumberingMode::All);
+        assert_eq!(cat_bytes(input, &options).unwrap(), b"     1\tcaf\xe9  \n");
+
+        // Characters split across reads, and invalid bytes
+        let options = Options::new().output_encoding(OutputEncoding::Utf16Le);
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(b"\xc3\xa9\xff\n"), &mut output, &options).unwrap();
+        assert_eq!(output, b"\xe9\x00\xfd\xff\n\x00");
+        let stats = cat_counted(&mut OneByteReader(b"a\xf0\x9f"), &mut Vec::new(), &options);
+        assert_eq!(stats.unwrap().bytes_out, 4);
+    }
+
+    #[test]
+    fn test_checksum_writer() {
+        let mut output = ChecksumWriter::new(ShortWriter(Vec::new()), Checksum::Crc32);
+        cat(&mut "123456789".as_bytes(), &mut output, &Options::new()).unwrap();
+        assert_eq!(output.digest(), "cbf43926");
+        assert_eq!(output.into_inner().0, b"123456789");
+
+        #[cfg(feature = "sha256")]
+        {
+            let mut output = ChecksumWriter::new(Vec::new(), Checksum::Sha256);
+            output.write_all(b"abc").unwrap();
+            assert_eq!(
+                output.digest(),
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            );
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_cat_highlight() {
+        use owo_colors::AnsiColors;
+
+        let input = b"see http://a.io/1 for 404\nx-2\n";
+        let highlight = vec![
+            Highlight::urls(AnsiColors::Blue),
+            Highlight::numbers(AnsiColors::Yellow),
+        ];
+        let options = Options::new().highlight(highlight);
+        assert_eq!(cat_bytes(input, &options).unwrap(), input);
+
+        let options = options.color(ColorChoice::Always).show_ends(true);
+        let expected = b"see \x1b[34mhttp://a.io/1\x1b[0m for \x1b[33m404\x1b[0m\x1b[36m$\x1b[0m\n\
+            x\x1b[33m-2\x1b[0m\x1b[36m$\x1b[0m\n";
+        assert_eq!(cat_bytes(input, &options).unwrap(), expected);
+
+        // Lines split across reads are matched whole
+        let mut output = Vec::new();
+        cat(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(output, expected);
+
+        // The colors of markers and the text they color are never matched
+        let highlight = vec![Highlight::numbers(AnsiColors::Yellow)];
+        let options = Options::new()
+            .highlight(highlight)
+            .color(ColorChoice::Always)
+            .show_tabs(true)
+            .show_nonprinting(true);
+        assert_eq!(
+            cat_bytes(b"a\tb 7\x01\n", &options).unwrap(),
+            b"a\x1b[36m^I\x1b[0mb \x1b[33m7\x1b[0m\x1b[36m^A\x1b[0m\n"
+        );
+        let options = options.tab_repr("<1>");
+        assert_eq!(
+            cat_bytes(b"\t2\n", &options).unwrap(),
+            b"\x1b[36m<1>\x1b[0m\x1b[33m2\x1b[0m\n"
+        );
+    }
+
+    /// Each option that changes how input is formatted, set on its own. The
+    /// struct literal has no `..`, so a new field won't compile until it is
+    /// sorted into one group or the other.
+    fn formatting_options() -> Vec<(&'static str, Options)> {
+        let defaults = Options::new();
+        let _ = Options {
+            // Formatting options, listed below
+            number: defaults.number,
+            offsets: defaults.offsets,
+            show_ends: defaults.show_ends,
+            show_lf: defaults.show_lf,
+            squeeze_blank: defaults.squeeze_blank,
+            squeeze_repeats: defaults.squeeze_repeats,
+            show_tabs: defaults.show_tabs,
+            expand_tabs: defaults.expand_tabs,
+            expand_leading: defaults.expand_leading,
+            wrap: defaults.wrap,
+            show_nonprinting: defaults.show_nonprinting,
+            nul: defaults.nul,
+            strip_nonprinting: defaults.strip_nonprinting,
+            strip_ansi: defaults.strip_ansi,
+            trim_trailing: defaults.trim_trailing,
+            interpret_escapes: defaults.interpret_escapes,
+            line_ending: defaults.line_ending,
+            line_terminator: defaults.line_terminator,
+            reverse_lines: defaults.reverse_lines,
+            line_range: defaults.line_range,
+            filter: defaults.filter,
+            uniq: defaults.uniq,
+            head: defaults.head,
+            tail: defaults.tail,
+            hex_dump: defaults.hex_dump,
+            json: defaults.json,
+            #[cfg(feature = "regex")]
+            highlight: defaults.highlight,
+            flush: defaults.flush,
+            // Only used along with one of the options above
+            number_start: defaults.number_start,
+            number_reset_per_file: defaults.number_reset_per_file,
+            number_width: defaults.number_width,
+            number_align: defaults.number_align,
+            number_sep: defaults.number_sep,
+            end_marker: defaults.end_marker,
+            normalize_crlf: defaults.normalize_crlf,
+            max_blank: defaults.max_blank,
+            tab_repr: defaults.tab_repr,
+            unicode_aware: defaults.unicode_aware,
+            invalid_utf8: defaults.invalid_utf8,
+            invert_filter: defaults.invert_filter,
+            uniq_count: defaults.uniq_count,
+            color: defaults.color,
+            // Applied to the input or output around the formatting
+            cr_is_eol: defaults.cr_is_eol,
+            ensure_trailing_newline: defaults.ensure_trailing_newline,
+            #[cfg(feature = "gzip")]
+            decompress: defaults.decompress,
+            encoding: defaults.encoding,
+            #[cfg(feature = "encoding")]
+            output_encoding: defaults.output_encoding,
+            #[cfg(feature = "encoding")]
+            unmappable: defaults.unmappable,
+            strip_bom: defaults.strip_bom,
+            skip_bytes: defaults.skip_bytes,
+            max_bytes: defaults.max_bytes,
+            with_filename: defaults.with_filename,
+            headers: defaults.headers,
+            follow: defaults.follow,
+            binary_check: defaults.binary_check,
+            progress: defaults.progress,
+            check: defaults.check,
+            #[cfg(feature = "mmap")]
+            mmap: defaults.mmap,
+            buffer_size: defaults.buffer_size,
+        };
+
+        let new = Options::new;
+        #[allow(unused_mut)]
+        let mut options = vec![
+            ("number", new().number(NumberingMode::All)),
+            ("number nonblank", new().number(NumberingMode::NonEmpty)),
+            ("offsets", new().offsets(Some(OffsetFormat::Decimal))),
+            ("show_ends", new().show_ends(true)),
+            ("show_lf", new().show_lf(Some(ShowLf::Break))),
+            ("squeeze_blank", new().squeeze_blank(true)),
+            ("squeeze_repeats", new().squeeze_repeats(true)),
+            ("show_tabs", new().show_tabs(true)),
+            ("expand_tabs", new().expand_tabs(Some(8))),
+            ("expand_leading", new().expand_leading(Some(8))),
+            ("wrap", new().wrap(Some(80))),
+            ("show_nonprinting", new().show_nonprinting(true)),
+            ("nul", new().nul(Some(NulMode::Caret))),
+            ("strip_nonprinting", new().strip_nonprinting(true)),
+            ("strip_ansi", new().strip_ansi(true)),
+            ("trim_trailing", new().trim_trailing(true)),
+            ("interpret_escapes", new().interpret_escapes(true)),
+            ("line_ending", new().line_ending(OutputLineEnding::CrLf)),
+            ("line_terminator", new().line_terminator("\0")),
+            ("reverse_lines", new().reverse_lines(true)),
+            ("line_range", new().line_range(Some((Some(2), None)))),
+            ("filter", new().filter(Some("a".to_string()))),
+            ("uniq", new().uniq(true)),
+            ("head", new().head(Some(10))),
+            ("tail", new().tail(Some(10))),
+            ("hex_dump", new().hex_dump(true)),
+            ("json", new().json(true)),
+            ("flush", new().flush(FlushMode::Line)),
+        ];
+        #[cfg(feature = "regex")]
+        options.push((
+            "highlight",
+            new()
+                .highlight(vec![Highlight::numbers(owo_colors::AnsiColors::Red)])
+                .color(ColorChoice::Always),
+        ));
+        options
+    }
+
+    #[test]
+    fn test_can_write_fast() {
+        assert!(Options::new().can_write_fast());
+        for (name, options) in formatting_options() {
+            assert!(!options.can_write_fast(), "{name} kept the fast path");
+        }
+    }
+
+    #[test]
+    fn test_options_validate() {
+        assert_eq!(Options::new().validate(), Ok(()));
+        let options = Options::new().hex_dump(true);
+        assert_eq!(options.validate(), Ok(()));
+        assert_eq!(
+            options.number(NumberingMode::NonEmpty).validate(),
+            Err(OptionsError::Conflict("hex_dump", "number"))
+        );
+        let options = Options::new().follow(true).tail(Some(10));
+        assert_eq!(
+            options.validate().unwrap_err().to_string(),
+            "`follow` can't be combined with `tail`"
+        );
+        assert_eq!(
+            Options::new().follow(true).head(Some(1)).validate(),
+            Err(OptionsError::Conflict("follow", "head"))
+        );
+        assert_eq!(
+            Options::new().follow(true).skip_bytes(2).validate(),
+            Err(OptionsError::Conflict("follow", "skip_bytes"))
+        );
+        assert_eq!(
+            Options::new().follow(true).max_bytes(Some(4)).validate(),
+            Err(OptionsError::Conflict("follow", "max_bytes"))
+        );
+        assert_eq!(
+            Options::new()
+                .offsets(Some(OffsetFormat::Hex))
+                .number(NumberingMode::All)
+                .validate(),
+            Err(OptionsError::Conflict("offsets", "number"))
+        );
+        assert_eq!(
+            Options::new().invalid_utf8(InvalidUtf8::Skip).validate(),
+            Err(OptionsError::Requires("invalid_utf8", "unicode_aware"))
+        );
+        assert_eq!(
+            Options::new().invert_filter(true).validate(),
+            Err(OptionsError::Requires("invert_filter", "filter"))
+        );
+    }
+
+    #[test]
+    fn test_cat_max_bytes() {
+        let input = b"one\ntwo\nthree\n";
+        let options = Options::new().max_bytes(Some(10));
+        let mut output = Vec::new();
+        let stats = cat_counted(&mut OneByteReader(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"one\ntwo\n");
+        assert_eq!((stats.bytes_out, stats.truncated), (8, true));
+        // The rest of the input is not read
+        assert!(stats.bytes_in < input.len());
+
+        // A first line that doesn't fit is cut short
+        let options = options.max_bytes(Some(6)).number(NumberingMode::All);
+        assert_eq!(cat_bytes(input, &options).unwrap(), b"     1");
+        let options = options.max_bytes(Some(100));
+        let stats = cat_counted(&mut &b"a\nb"[..], &mut Vec::new(), &options).unwrap();
+        assert_eq!((stats.bytes_out, stats.truncated), (17, false));
+
+        // The limit is for all the files together
+        let file = temp_file("max-bytes", b"abc\n");
+        let options = Options::new().max_bytes(Some(10));
+        let mut output = Vec::new();
+        let stats = cat_files_to(&[&file, &file, &file], &mut output, &options).unwrap();
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(output, b"abc\nabc\n");
+        assert!(stats.truncated);
+
+        // Formatted files stop being read too
+        let input = b"line\n".repeat(100_000);
+        let file = temp_file("max-bytes-formatted", &input);
+        let options = options.number(NumberingMode::All);
+        let mut output = Vec::new();
+        let stats = cat_files_to(&[&file], &mut output, &options).unwrap();
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(output, b"     1\tlin");
+        assert!(stats.bytes_in < input.len());
+    }
+
+    #[test]
+    fn test_cat_skip_bytes() {
+        let options = Options::new().skip_bytes(3);
+        assert_eq!(cat_bytes(b"xxxhello\n", &options).unwrap(), b"hello\n");
+
+        // Reads that return fewer bytes than are skipped
+        let options = options.number(NumberingMode::All);
+        let mut output = Vec::new();
+        let stats = cat_counted(&mut OneByteReader(b"xxxhello\n"), &mut output, &options);
+        assert_eq!(output, b"     1\thello\n");
+        assert_eq!(stats.unwrap().bytes_in, 9);
+        assert_eq!(cat_bytes(b"xx", &options).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_cat_with_state() {
+        let options = Options::new()
+            .number(NumberingMode::All)
+            .squeeze_blank(true)
+            .show_ends(true);
+        let mut state = CatState::new(&options);
+        let mut chunk = |input: &[u8]| {
+            let mut output = Vec::new();
+            let stats = cat_with_state(&mut &input[..], &mut output, &options, &mut state);
+            (String::from_utf8(output).unwrap(), stats.unwrap())
+        };
+
+        let (output, stats) = chunk(b"a\n\n\n");
+        assert_eq!(output, "     1\ta$\n     2\t$\n");
+        assert_eq!(
+            (stats.lines, stats.numbered, stats.blanks_squeezed),
+            (2, 2, 1)
+        );
+        // The blank line carries on the run, and the \r is held back to see
+        // whether it ends the line
+        let (output, stats) = chunk(b"\nb\r");
+        assert_eq!(output, "     3\tb");
+        assert_eq!(
+            (stats.lines, stats.numbered, stats.blanks_squeezed),
+            (0, 1, 1)
+        );
+        let (output, _) = chunk(b"\nc");
+        assert_eq!(output, "^M$\n     4\tc");
+        assert_eq!(state.line_number(), 5);
+
+        // A line cut off by the end of an input is only filtered once whole
+        let options = Options::new().filter(Some("ab".to_string()));
+        let mut state = CatState::new(&options);
+        let mut output = Vec::new();
+        for input in [&b"x\na"[..], b"b\n", b"a", b"b"] {
+            cat_with_state(&mut &input[..], &mut output, &options, &mut state).unwrap();
+        }
+        state.finish(&mut output, &options).unwrap();
+        assert_eq!(output, b"ab\nab");
+
+        // Unformatted input is passed on as it is
+        let options = Options::new();
+        let mut state = CatState::new(&options);
+        let mut output = Vec::new();
+        for input in [&b"a\r"[..], b"\nb"] {
+            cat_with_state(&mut &input[..], &mut output, &options, &mut state).unwrap();
+        }
+        state.finish(&mut output, &options).unwrap();
+        assert_eq!(output, b"a\r\nb");
+
+        // The limit is for all of the inputs, and a line they split is only
+        // written if it fits whole
+        let options = Options::new().max_bytes(Some(6));
+        let mut state = CatState::new(&options);
+        let mut output = Vec::new();
+        let mut truncated = false;
+        for input in [&b"ab\nc"[..], b"d\n", b"ef\n"] {
+            let stats = cat_with_state(&mut &input[..], &mut output, &options, &mut state);
+            truncated |= stats.unwrap().truncated;
+        }
+        state.finish(&mut output, &options).unwrap();
+        assert_eq!(output, b"ab\ncd\n");
+        assert!(truncated);
+
+        let options = Options::new().head(Some(1));
+        let mut state = CatState::new(&options);
+        let result = cat_with_state(&mut &b"a\n"[..], &mut Vec::new(), &options, &mut state);
+        assert!(
+            matches!(result, Err(CatError::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_cat_writer() {
+        let input = b"a\tb\r\n\n\n\n\x01c\r";
+        for options in [
+            Options::new(),
+            Options::new().show_all().number(NumberingMode::NonEmpty),
+            Options::new().squeeze_blank(true).show_ends(true),
+            Options::new().reverse_lines(true),
+            Options::new().max_bytes(Some(6)),
+            Options::new().max_bytes(Some(2)),
+            Options::new()
+                .max_bytes(Some(18))
+                .number(NumberingMode::All),
+        ] {
+            let mut writer = options.writer(Vec::new());
+            for chunk in input.chunks(3) {
+                writer.write_all(chunk).unwrap();
+            }
+            let output = writer.finish().unwrap();
+            assert_eq!(output, cat_bytes(input, &options).unwrap());
+        }
+
+        // Dropping the writer finishes it
+        let options = Options::new().show_ends(true);
+        let mut output = Vec::new();
+        write!(options.writer(&mut output), "a\r").unwrap();
+        assert_eq!(output, b"a\r");
+    }
+
+    #[test]
+    fn test_options_lines_empty() {
+        let options = Options::new();
+        let mut lines = options.lines(std::io::Cursor::new(b""));
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn test_cat_counted() {
+        let input = b"a\tb\n\x01\n\nlast";
+        let options = Options::new();
+        let stats = cat_counted(&mut std::io::Cursor::new(input), &mut Vec::new(), &options);
+        assert_eq!(
+            stats.unwrap(),
+            CatStats {
+                bytes_in: 11,
+                bytes_out: 11,
+                lines: 4,
+                blanks_squeezed: 0,
+                numbered: 0,
+                truncated: false,
+            }
+        );
+
+        let options = Options::new().show_nonprinting(true).show_tabs(true);
+        let mut output = Vec::new();
+        let stats = cat_counted(&mut std::io::Cursor::new(input), &mut output, &options);
+        assert_eq!(output, b"a^Ib\n^A\n\nlast");
+        assert_eq!(
+            stats.unwrap(),
+            CatStats {
+                bytes_in: 11,
+                bytes_out: 13,
+                lines: 4,
+                blanks_squeezed: 0,
+                numbered: 0,
+                truncated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_cat_counted_squeeze() {
+        let options = Options::new().squeeze_blank(true);
+        let stats = cat_counted(
+            &mut std::io::Cursor::new(b"a\n\n\n\nb\n"),
+            &mut Vec::new(),
+            &options,
+        );
+        assert_eq!(
+            stats.unwrap(),
+            CatStats {
+                bytes_in: 7,
+                bytes_out: 5,
+                lines: 3,
+                blanks_squeezed: 2,
+                numbered: 0,
+                truncated: false,
+            }
+        );
+
+        // Also counted when the output is limited to some lines
+        let options = options.head(Some(2));
+        let stats = cat_counted(
+            &mut std::io::Cursor::new(b"a\n\n\n\nb\n"),
+            &mut Vec::new(),
+            &options,
+        );
+        assert_eq!(stats.unwrap().blanks_squeezed, 2);
     }
 
     #[test]
@@ -313,10 +4477,28 @@ mod tests {
             &mut output,
             &options,
             State {
-                line_number: 0,
+                line_number: 1,
                 at_line_start: true,
                 skipped_carriage_return: false,
-                one_blank_kept: false,
+                blank_run: 0,
+                lines: 0,
+                numbered: 0,
+                column: 0,
+                wrap_column: 0,
+                indenting: true,
+                utf8_partial: Vec::new(),
+                nonprint: None,
+                input_line: 1,
+                input_offset: 0,
+                line_offset: 0,
+                skipped_content: false,
+                blanks_squeezed: 0,
+                ansi: AnsiState::Text,
+                escape_partial: Vec::new(),
+                filter_partial: Vec::new(),
+                uniq_line: None,
+                uniq_count: 0,
+                uniq_offset: 0,
             },
         );
         assert!(result.is_ok());
@@ -342,4 +4524,553 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(output, b"Hello, world!^H");
     }
+
+    #[test]
+    fn test_cat_bytes() {
+        let options = Options::new().show_nonprinting(true);
+        let output = cat_bytes(b"Hello, world!\x08", &options).unwrap();
+        assert_eq!(output, b"Hello, world!^H");
+
+        let output = cat_str("Hello, world!\x08\u{e9}", &options).unwrap();
+        assert_eq!(output, "Hello, world!^HM-CM-)");
+    }
+
+    #[test]
+    fn test_options_default() {
+        let options = Options::default();
+        assert_eq!(options, Options::new());
+        assert_eq!(
+            options.clone().show_ends(true),
+            Options::new().show_ends(true)
+        );
+        assert_ne!(options, Options::new().number(NumberingMode::All));
+
+        let options = Options {
+            show_tabs: true,
+            show_nonprinting: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            options,
+            Options::new().show_tabs(true).show_nonprinting(true)
+        );
+    }
+
+    #[test]
+    fn test_options_show_all() {
+        assert_eq!(
+            Options::new().show_all(),
+            Options::new()
+                .show_nonprinting(true)
+                .show_tabs(true)
+                .show_ends(true)
+        );
+    }
+
+    #[test]
+    fn test_options_stack_number() {
+        let (none, all, nonempty) = (
+            NumberingMode::None,
+            NumberingMode::All,
+            NumberingMode::NonEmpty,
+        );
+        // `number` keeps the last mode, `stack_number` prefers nonblank
+        assert_eq!(Options::new().number(nonempty).number(all).number, all);
+        assert_eq!(Options::new().number(all).number(none).number, none);
+        for (first, second, stacked) in [
+            (all, nonempty, nonempty),
+            (nonempty, all, nonempty),
+            (all, all, all),
+            (all, none, all),
+            (none, all, all),
+        ] {
+            let options = Options::new().stack_number(first).stack_number(second);
+            assert_eq!(options.number, stacked, "{first:?} then {second:?}");
+        }
+
+        // The command line stacks the same way
+        for line in [
+            ["ccc", "-b", "-n"],
+            ["ccc", "-n", "-b"],
+            ["ccc", "--number", "--number-nonblank"],
+        ] {
+            assert_eq!(args(&line).unwrap().options.number, nonempty);
+        }
+    }
+
+    fn args(args: &[&str]) -> Result<Args, ParseError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let defaults = args(&["ccc"]).unwrap();
+        assert_eq!(defaults.files, ["-"]);
+        assert!(!defaults.stats);
+
+        let parsed = args(&["ccc", "--files0-from=-"]).unwrap();
+        assert!(parsed.files.is_empty());
+        assert_eq!(parsed.files_from, Some(("-".into(), b'\0')));
+
+        let parsed = args(&["ccc", "--", "-E"]).unwrap();
+        assert_eq!(parsed.files, ["-E"]);
+        assert_eq!(parsed.options, defaults.options);
+
+        let parsed = args(&["ccc", "-nT", "--squeeze-b", "--head", "3", "a", "-"]).unwrap();
+        assert_eq!(parsed.files, ["a", "-"]);
+        assert_eq!(
+            parsed.options,
+            defaults
+                .options
+                .number(NumberingMode::All)
+                .show_tabs(true)
+                .squeeze_blank(true)
+                .head(Some(3))
+        );
+
+        let parsed = args(&["ccc", "--expand-leading", "--expand-l=2"]).unwrap();
+        assert_eq!(parsed.options.expand_leading, Some(2));
+        let parsed = args(&["ccc", "--expand-leading"]).unwrap();
+        assert_eq!(parsed.options.expand_leading, Some(8));
+
+        let parsed = args(&["ccc", "--nul", "replace:.", "--nul=keep"]).unwrap();
+        assert_eq!(parsed.options.nul, Some(NulMode::Keep));
+        let parsed = args(&["ccc", "--nul=replace:\u{2400}"]).unwrap();
+        assert_eq!(parsed.options.nul, Some(NulMode::Replace('\u{2400}')));
+        assert!(args(&["ccc", "--nul=replace:ab"]).is_err());
+        assert!(args(&["ccc", "--nul=replace:"]).is_err());
+
+        let parsed = args(&["ccc", "--offsets"]).unwrap();
+        assert_eq!(parsed.options.offsets, Some(OffsetFormat::Decimal));
+        let parsed = args(&["ccc", "--offsets=hex"]).unwrap();
+        assert_eq!(parsed.options.offsets, Some(OffsetFormat::Hex));
+        assert!(args(&["ccc", "--offsets=oct"]).is_err());
+
+        let parsed = args(&["ccc", "-v", "--invalid", "replace"]).unwrap();
+        assert!(parsed.options.unicode_aware);
+        assert_eq!(parsed.options.invalid_utf8, InvalidUtf8::Replace);
+        assert!(args(&["ccc", "--invalid=drop"]).is_err());
+
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+            let name = OsStr::from_bytes(b"a\xff");
+            let parsed = parse_args([OsStr::new("ccc"), OsStr::new("-n"), name]).unwrap();
+            assert_eq!(parsed.files, [name]);
+            assert_eq!(
+                parse_args([OsStr::new("ccc"), OsStr::new("--head"), name]),
+                Err(ParseError::InvalidArgument {
+                    option: "--head".to_string(),
+                    value: "a\u{fffd}".to_string(),
+                })
+            );
+        }
+
+        #[cfg(feature = "terminal")]
+        {
+            let parsed = args(&["ccc", "--wrap", "9", "--wrap=auto"]).unwrap();
+            assert!(parsed.wrap_auto);
+            assert_eq!(parsed.options.wrap, None);
+            assert!(!args(&["ccc", "--wrap=auto", "--wrap=9"]).unwrap().wrap_auto);
+        }
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert_eq!(
+            args(&["ccc", "-V"]),
+            Err(ParseError::Version {
+                color: ColorChoice::Auto
+            })
+        );
+        assert_eq!(
+            args(&["ccc", "--color=never", "-nh"]),
+            Err(ParseError::Help {
+                color: ColorChoice::Never
+            })
+        );
+        assert_eq!(
+            args(&["ccc", "-x"]),
+            Err(ParseError::InvalidOption("-x".to_string()))
+        );
+        assert_eq!(
+            args(&["ccc", "--files-from", "list", "a"]),
+            Err(ParseError::ExtraOperand("a".to_string()))
+        );
+        assert_eq!(
+            args(&["ccc", "--fd", "3", "a"]),
+            Err(ParseError::ExtraOperand("a".to_string()))
+        );
+        assert_eq!(
+            args(&["ccc", "--fd=-1"]),
+            Err(ParseError::InvalidArgument {
+                option: "--fd".to_string(),
+                value: "-1".to_string()
+            })
+        );
+        assert_eq!(
+            args(&["ccc", "--stats=yes"]),
+            Err(ParseError::InvalidOption("--stats=yes".to_string()))
+        );
+        assert_eq!(
+            args(&["ccc", "--num"]),
+            Err(ParseError::AmbiguousOption {
+                option: "num".to_string(),
+                candidates: vec![
+                    "number-nonblank",
+                    "number",
+                    "number-start",
+                    "number-reset-per-file",
+                ],
+            })
+        );
+        assert_eq!(
+            args(&["ccc", "--tail"]),
+            Err(ParseError::MissingArgument("--tail".to_string()))
+        );
+        assert_eq!(
+            args(&["ccc", "--lines=x,2"]),
+            Err(ParseError::InvalidArgument {
+                option: "--lines".to_string(),
+                value: "x,2".to_string(),
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_options_serde() {
+        let options = Options::new()
+            .number(NumberingMode::NonEmpty)
+            .end_marker("<EOL>")
+            .line_range(Some((Some(2), None)));
+        let json = serde_json::to_string(&options).unwrap();
+        assert!(json.contains(r#""number":"non-empty""#));
+        assert_eq!(serde_json::from_str::<Options>(&json).unwrap(), options);
+
+        // Missing fields keep their defaults
+        let options: Options =
+            serde_json::from_str(r#"{"number":"all","squeeze_blank":true}"#).unwrap();
+        assert_eq!(
+            options,
+            Options::new()
+                .number(NumberingMode::All)
+                .squeeze_blank(true)
+        );
+    }
+
+    #[test]
+    fn test_cat_end_marker() {
+        let options = Options::new().show_ends(true).end_marker("<EOL>");
+        let mut input = std::io::Cursor::new(b"a\n\nb");
+        let mut output = Vec::new();
+        cat(&mut input, &mut output, &options).unwrap();
+        assert_eq!(output, b"a<EOL>\n<EOL>\nb");
+
+        // The marker only applies when show_ends is set
+        let options = Options::new().end_marker("\u{23ce}").squeeze_blank(true);
+        let mut input = std::io::Cursor::new(b"a\n");
+        let mut output = Vec::new();
+        cat(&mut input, &mut output, &options).unwrap();
+        assert_eq!(output, b"a\n");
+    }
+
+    #[test]
+    fn test_cat_color() {
+        let options = Options::new()
+            .color(ColorChoice::Always)
+            .number(NumberingMode::All)
+            .show_ends(true)
+            .show_tabs(true)
+            .show_nonprinting(true);
+        let mut input = std::io::Cursor::new(b"a\tb\x01\n");
+        let mut output = Vec::new();
+        cat(&mut input, &mut output, &options).unwrap();
+        assert_eq!(
+            output,
+            b"\x1b[32m     1\x1b[0m\ta\x1b[36m^I\x1b[0mb\x1b[36m^A\x1b[0m\x1b[36m$\x1b[0m\n"
+        );
+
+        let options = options.color(ColorChoice::Never);
+        let mut input = std::io::Cursor::new(b"a\tb\x01\n");
+        let mut output = Vec::new();
+        cat(&mut input, &mut output, &options).unwrap();
+        assert_eq!(output, b"     1\ta^Ib^A$\n");
+    }
+
+    #[test]
+    fn test_cat_squeeze_leading_blanks() {
+        for buffer_size in 1..=4 {
+            let options = Options::new().squeeze_blank(true).buffer_size(buffer_size);
+            for (input, expected) in [
+                (&b"\n\n\n\n"[..], &b"\n"[..]),
+                (b"\n\n\ntext\n", b"\ntext\n"),
+            ] {
+                let mut output = Vec::new();
+                cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+                assert_eq!(output, expected, "buffer size {buffer_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_cat_number_crlf() {
+        let input = b"a\r\n\r\nb\r\n";
+        for buffer_size in 1..=4 {
+            // A line that is only \r\n is blank for -b, but still numbered by -n
+            let options = Options::new()
+                .number(NumberingMode::NonEmpty)
+                .buffer_size(buffer_size);
+            let mut output = Vec::new();
+            cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+            assert_eq!(output, b"     1\ta\r\n\r\n     2\tb\r\n");
+
+            let options = options.number(NumberingMode::All);
+            let mut output = Vec::new();
+            cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+            assert_eq!(output, b"     1\ta\r\n     2\t\r\n     3\tb\r\n");
+        }
+    }
+
+    #[test]
+    fn test_cat_show_ends_crlf() {
+        // Matches GNU cat: the \r before the end marker is shown as ^M
+        for buffer_size in 1..=6 {
+            for options in [
+                Options::new().show_ends(true),
+                Options::new().show_nonprinting(true).show_ends(true),
+                Options::new()
+                    .show_nonprinting(true)
+                    .show_tabs(true)
+                    .show_ends(true),
+            ] {
+                let options = options.buffer_size(buffer_size);
+                let mut output = Vec::new();
+                cat(
+                    &mut std::io::Cursor::new(b"line\r\n"),
+                    &mut output,
+                    &options,
+                )
+                .unwrap();
+                assert_eq!(output, b"line^M$\n");
+            }
+        }
+    }
+
+    #[test]
+    fn test_cat_normalize_crlf() {
+        let lf = b"a\n\n\n\nb\rc\n";
+        let crlf = b"a\r\n\r\n\r\n\r\nb\rc\r\n";
+        for buffer_size in 1..=6 {
+            let options = Options::new().normalize_crlf(true).buffer_size(buffer_size);
+            assert_eq!(cat_bytes(crlf, &options).unwrap(), crlf);
+
+            // One $ per line, either way
+            let options = options.show_ends(true);
+            assert_eq!(cat_bytes(lf, &options).unwrap(), b"a$\n$\n$\n$\nb\rc$\n");
+            assert_eq!(
+                cat_bytes(crlf, &options).unwrap(),
+                b"a$\r\n$\r\n$\r\n$\r\nb\rc$\r\n"
+            );
+
+            // With -v the \r is still shown
+            let options = options.show_nonprinting(true);
+            assert_eq!(cat_bytes(lf, &options).unwrap(), b"a$\n$\n$\n$\nb^Mc$\n");
+            assert_eq!(
+                cat_bytes(crlf, &options).unwrap(),
+                b"a^M$\n^M$\n^M$\n^M$\nb^Mc^M$\n"
+            );
+
+            // Lines of only \r\n are blank, like lines of only \n
+            let options = Options::new()
+                .normalize_crlf(true)
+                .squeeze_blank(true)
+                .number(NumberingMode::NonEmpty)
+                .show_ends(true)
+                .buffer_size(buffer_size);
+            assert_eq!(
+                cat_bytes(lf, &options).unwrap(),
+                b"     1\ta$\n$\n     2\tb\rc$\n"
+            );
+            assert_eq!(
+                cat_bytes(crlf, &options).unwrap(),
+                b"     1\ta$\r\n$\r\n     2\tb\rc$\r\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_cat_show_all_crlf() {
+        // Expected outputs are those of GNU cat -vET and -nvET
+        let input = b"a\tb\r\n\r\n\r\r\nc\rd\r";
+        for buffer_size in 1..=6 {
+            let options = Options::new()
+                .show_nonprinting(true)
+                .show_ends(true)
+                .show_tabs(true)
+                .buffer_size(buffer_size);
+            let output = cat_bytes(input, &options).unwrap();
+            assert_eq!(output, b"a^Ib^M$\n^M$\n^M^M$\nc^Md^M");
+
+            let options = options.number(NumberingMode::All);
+            let output = cat_bytes(input, &options).unwrap();
+            assert_eq!(
+                output,
+                b"     1\ta^Ib^M$\n     2\t^M$\n     3\t^M^M$\n     4\tc^Md^M"
+            );
+        }
+    }
+
+    #[test]
+    fn test_cat_no_trailing_newline() {
+        // Expected outputs are those of GNU cat
+        let input = b"a\n\n\n\nlast";
+        for buffer_size in 1..=6 {
+            for (options, expected) in [
+                (
+                    Options::new().number(NumberingMode::All),
+                    &b"     1\ta\n     2\t\n     3\t\n     4\t\n     5\tlast"[..],
+                ),
+                (
+                    Options::new().number(NumberingMode::NonEmpty),
+                    b"     1\ta\n\n\n\n     2\tlast",
+                ),
+                (Options::new().show_ends(true), b"a$\n$\n$\n$\nlast"),
+                (Options::new().squeeze_blank(true), b"a\n\nlast"),
+            ] {
+                let options = options.buffer_size(buffer_size);
+                let output = cat_bytes(input, &options).unwrap();
+                assert_eq!(output, expected, "{options:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_cat_cr_is_eol() {
+        let input = b"a\rb\nc\r\nd\r\re\r";
+        for buffer_size in 1..=4 {
+            let options = Options::new().cr_is_eol(true).buffer_size(buffer_size);
+            let output = cat_bytes(input, &options).unwrap();
+            assert_eq!(output, b"a\nb\nc\r\nd\n\ne\n");
+
+            let options = options.number(NumberingMode::NonEmpty).show_ends(true);
+            let output = cat_bytes(input, &options).unwrap();
+            assert_eq!(
+                output,
+                b"     1\ta$\n     2\tb$\n     3\tc^M$\n     4\td$\n$\n     5\te$\n"
+            );
+        }
+
+        // Lines are counted the same way by the other modes
+        let options = Options::new().cr_is_eol(true).tail(Some(2));
+        assert_eq!(cat_bytes(b"a\rb\rc", &options).unwrap(), b"b\nc");
+        let options = Options::new().cr_is_eol(true).hex_dump(true);
+        let hex = cat_bytes(b"a\rb", &options).unwrap();
+        assert!(hex.starts_with(b"00000000  61 0d 62"));
+    }
+
+    #[test]
+    fn test_cat_output_line_ending() {
+        let input = b"a\r\nb\n\r\n\rc";
+        for buffer_size in 1..=4 {
+            let options = Options::new()
+                .line_ending(OutputLineEnding::CrLf)
+                .buffer_size(buffer_size);
+            assert_eq!(cat_bytes(input, &options).unwrap(), b"a\r\nb\r\n\r\n\rc");
+
+            let options = options.show_ends(true);
+            assert_eq!(cat_bytes(input, &options).unwrap(), b"a$\r\nb$\r\n$\r\n\rc");
+
+            let options = options.show_nonprinting(true).number(NumberingMode::All);
+            assert_eq!(
+                cat_bytes(input, &options).unwrap(),
+                b"     1\ta$\r\n     2\tb$\r\n     3\t$\r\n     4\t^Mc"
+            );
+
+            let options = Options::new()
+                .line_ending(OutputLineEnding::Lf)
+                .buffer_size(buffer_size);
+            assert_eq!(cat_bytes(input, &options).unwrap(), b"a\nb\n\n\rc");
+
+            let options = options.cr_is_eol(true).squeeze_blank(true);
+            assert_eq!(cat_bytes(input, &options).unwrap(), b"a\nb\n\nc");
+        }
+    }
+
+    #[test]
+    fn test_cat_number_format() {
+        let options = Options::new()
+            .number(NumberingMode::All)
+            .number_width(3)
+            .number_sep(": ");
+        let output = cat_bytes(b"a\n\nb\n", &options).unwrap();
+        assert_eq!(output, b"  1: a\n  2: \n  3: b\n");
+
+        // Numbers wider than the width are not cut off
+        let options = options.number_width(0).number_start(10);
+        let output = cat_bytes(b"a\n", &options).unwrap();
+        assert_eq!(output, b"10: a\n");
+    }
+
+    #[test]
+    fn test_cat_number_align() {
+        let options = Options::new()
+            .number(NumberingMode::All)
+            .number_width(4)
+            .number_sep("|");
+        let output = cat_bytes(b"a\nb\n", &options).unwrap();
+        assert_eq!(output, b"   1|a\n   2|b\n");
+
+        let options = options.number_align(Align::Left);
+        let output = cat_bytes(b"a\nb\n", &options).unwrap();
+        assert_eq!(output, b"1   |a\n2   |b\n");
+    }
+
+    #[test]
+    fn test_cat_max_blank() {
+        let input = b"a\n\n\n\n\n\nb\n\nc\n";
+        let squeezed = |options: Options| {
+            let mut output = Vec::new();
+            cat(&mut std::io::Cursor::new(input), &mut output, &options).unwrap();
+            output
+        };
+        assert_eq!(
+            squeezed(Options::new().squeeze_blank(true)),
+            b"a\n\nb\n\nc\n"
+        );
+        assert_eq!(
+            squeezed(Options::new().squeeze_blank(true).max_blank(2)),
+            b"a\n\n\nb\n\nc\n"
+        );
+        assert_eq!(
+            squeezed(Options::new().squeeze_blank(true).max_blank(0)),
+            b"a\nb\nc\n"
+        );
+        // max_blank has no effect unless squeeze_blank is set
+        assert_eq!(
+            squeezed(Options::new().max_blank(0).show_ends(true)),
+            b"a$\n$\n$\n$\n$\n$\nb$\n$\nc$\n"
+        );
+    }
+
+    #[test]
+    fn test_cat_number_starts_at_one() {
+        let options = Options::new().number(NumberingMode::All);
+        let mut input = std::io::Cursor::new(b"a\nb\n");
+        let mut output = Vec::new();
+        let result = cat(&mut input, &mut output, &options);
+        assert!(result.is_ok());
+        assert_eq!(output, b"     1\ta\n     2\tb\n");
+    }
+
+    #[test]
+    fn test_cat_number_nonempty_starts_at_one() {
+        let options = Options::new().number(NumberingMode::NonEmpty);
+        let mut input = std::io::Cursor::new(b"a\n\nb\n");
+        let mut output = Vec::new();
+        let result = cat(&mut input, &mut output, &options);
+        assert!(result.is_ok());
+        assert_eq!(output, b"     1\ta\n\n     2\tb\n");
+    }
 }
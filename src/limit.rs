@@ -0,0 +1,189 @@
+use std::cell::Cell;
+use std::io::Read;
+use std::io::Write;
+
+/// A writer that passes on at most `remaining` bytes, for `max_bytes`.
+///
+/// Each line is held back until its end, so the output stops after the last
+/// whole line that fits. Only when not even the first line fits is it cut off
+/// in the middle. Once the output is full, `full` is set and everything else
+/// written is dropped; call [`finish`](Self::finish) at the end to write a
+/// last line without a terminator.
+pub(crate) struct LimitWriter<'a, W: Write> {
+    inner: &'a mut W,
+    remaining: usize,
+
+    /// The number of bytes passed on
+    written: usize,
+
+    /// The last byte of each line
+    terminator: u8,
+
+    /// The start of a line not yet passed on
+    line: Vec<u8>,
+
+    /// Whether a whole line has been passed on
+    wrote_line: bool,
+
+    /// Whether bytes were dropped to keep within the limit
+    pub(crate) truncated: bool,
+
+    /// Set once nothing more will be passed on, shared with a [`LimitReader`]
+    full: &'a Cell<bool>,
+}
+
+impl<'a, W: Write> LimitWriter<'a, W> {
+    pub(crate) fn new(
+        inner: &'a mut W,
+        remaining: usize,
+        terminator: u8,
+        full: &'a Cell<bool>,
+    ) -> Self {
+        Self::resume(inner, LimitState::new(remaining), terminator, full)
+    }
+
+    /// Carry on from where the writer that [`suspend`](Self::suspend) returned
+    /// `state` for left off
+    pub(crate) fn resume(
+        inner: &'a mut W,
+        state: LimitState,
+        terminator: u8,
+        full: &'a Cell<bool>,
+    ) -> Self {
+        full.set(state.full);
+        Self {
+            inner,
+            remaining: state.remaining,
+            written: 0,
+            terminator,
+            line: state.line,
+            wrote_line: state.wrote_line,
+            truncated: false,
+            full,
+        }
+    }
+
+    /// Stop without writing out the last line, and return the number of bytes
+    /// passed on and where to carry on from
+    pub(crate) fn suspend(self) -> (usize, LimitState) {
+        let state = LimitState {
+            remaining: self.remaining,
+            line: self.line,
+            wrote_line: self.wrote_line,
+            full: self.full.get(),
+        };
+        (self.written, state)
+    }
+
+    /// Write out the last line if it fits, and return the number of bytes
+    /// passed on
+    pub(crate) fn finish(mut self) -> std::io::Result<usize> {
+        if !self.full.get() {
+            self.inner.write_all(&self.line)?;
+            self.written += self.line.len();
+            self.line.clear();
+        }
+
+        Ok(self.written)
+    }
+}
+
+/// Where a [`LimitWriter`] left off, for another one to carry on from
+pub(crate) struct LimitState {
+    remaining: usize,
+    line: Vec<u8>,
+    wrote_line: bool,
+    full: bool,
+}
+
+impl LimitState {
+    /// The state of a writer that has written nothing yet
+    pub(crate) fn new(remaining: usize) -> Self {
+        Self {
+            remaining,
+            line: Vec::new(),
+            wrote_line: false,
+            full: remaining == 0,
+        }
+    }
+
+    /// Whether nothing more will be passed on
+    pub(crate) fn is_full(&self) -> bool {
+        self.full
+    }
+
+    /// Pass `bytes` on to `inner` as a [`LimitWriter`] carrying on from here
+    /// would, for writers that can't hold one across calls
+    pub(crate) fn write<W: Write>(
+        &mut self,
+        inner: &mut W,
+        terminator: u8,
+        bytes: &[u8],
+    ) -> std::io::Result<()> {
+        let full = Cell::new(false);
+        let state = std::mem::replace(self, Self::new(0));
+        let mut limited = LimitWriter::resume(inner, state, terminator, &full);
+        let result = limited.write_all(bytes);
+        *self = limited.suspend().1;
+        result
+    }
+
+    /// Write out the last line to `inner` if it fits, like
+    /// [`LimitWriter::finish`]
+    pub(crate) fn finish<W: Write>(self, inner: &mut W, terminator: u8) -> std::io::Result<()> {
+        let full = Cell::new(false);
+        LimitWriter::resume(inner, self, terminator, &full).finish()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for LimitWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for segment in buf.split_inclusive(|c| *c == self.terminator) {
+            if self.full.get() {
+                self.truncated = true;
+                break;
+            }
+            self.line.extend_from_slice(segment);
+            if self.line.len() > self.remaining {
+                if !self.wrote_line {
+                    self.inner.write_all(&self.line[..self.remaining])?;
+                    self.written += self.remaining;
+                }
+                self.line.clear();
+                self.truncated = true;
+                self.full.set(true);
+                break;
+            }
+            if segment.last() == Some(&self.terminator) {
+                self.inner.write_all(&self.line)?;
+                self.written += self.line.len();
+                self.remaining -= self.line.len();
+                self.line.clear();
+                self.wrote_line = true;
+                self.full.set(self.remaining == 0);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader that ends as soon as the [`LimitWriter`] sharing `full` is full,
+/// so the rest of the input is not read
+pub(crate) struct LimitReader<'a, R: Read> {
+    pub(crate) inner: &'a mut R,
+    pub(crate) full: &'a Cell<bool>,
+}
+
+impl<R: Read> Read for LimitReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.full.get() {
+            return Ok(0);
+        }
+        self.inner.read(buf)
+    }
+}
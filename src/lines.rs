@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::io::Read;
+
+use crate::cat_buffer;
+use crate::finish;
+use crate::limit::LimitState;
+use crate::CatResult;
+use crate::Options;
+use crate::State;
+use crate::LINES_BUFFER_SIZE;
+
+/// An iterator over the formatted output lines of a reader.
+///
+/// Each item is one output line, including its end-of-line marker. The last
+/// line has no line terminator if the input did not end with a newline. With
+/// `max_bytes`, the lines end once the output is full.
+pub struct CatLines<'a, R: Read> {
+    reader: R,
+    options: &'a Options,
+    pub(crate) state: State,
+    inbuf: Vec<u8>,
+
+    /// Formatted output that does not yet form a complete line
+    pending: Vec<u8>,
+
+    /// How much more may be yielded for `max_bytes`
+    limit: Option<LimitState>,
+
+    /// Formatted output waiting to be kept within `max_bytes`
+    formatted: Vec<u8>,
+
+    /// Complete lines waiting to be yielded
+    lines: VecDeque<Vec<u8>>,
+
+    /// Whether the reader has reached end of file or failed
+    done: bool,
+
+    /// The number of bytes read so far
+    pub(crate) bytes_in: usize,
+}
+
+impl<'a, R: Read> CatLines<'a, R> {
+    pub(crate) fn new(reader: R, options: &'a Options, state: State) -> Self {
+        Self {
+            reader,
+            options,
+            state,
+            inbuf: vec![0; options.buffer_size_or(LINES_BUFFER_SIZE)],
+            pending: Vec::new(),
+            limit: options.max_bytes.map(LimitState::new),
+            formatted: Vec::new(),
+            lines: VecDeque::new(),
+            done: false,
+            bytes_in: 0,
+        }
+    }
+
+    /// Read and format the next chunk of input, queueing any completed lines
+    fn fill(&mut self) -> CatResult<()> {
+        let n = loop {
+            match self.reader.read(&mut self.inbuf) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        if n == 0 {
+            self.done = true;
+            let output = match self.limit {
+                Some(_) => &mut self.formatted,
+                None => &mut self.pending,
+            };
+            finish(output, self.options, &mut self.state)?;
+            self.pass_on()?;
+            if let Some(limit) = self.limit.take() {
+                limit.finish(&mut self.pending, self.options.line_end())?;
+            }
+            if !self.pending.is_empty() {
+                self.lines.push_back(std::mem::take(&mut self.pending));
+            }
+            return Ok(());
+        }
+        self.bytes_in += n;
+
+        let output = match self.limit {
+            Some(_) => &mut self.formatted,
+            None => &mut self.pending,
+        };
+        cat_buffer(&self.inbuf[..n], output, self.options, &mut self.state)?;
+        self.pass_on()?;
+        let terminator = self.options.line_terminator.as_bytes();
+        if !terminator.is_empty() {
+            while let Some(p) = self
+                .pending
+                .windows(terminator.len())
+                .position(|w| w == terminator)
+            {
+                let rest = self.pending.split_off(p + terminator.len());
+                self.lines
+                    .push_back(std::mem::replace(&mut self.pending, rest));
+            }
+        }
+        // The rest of the input is not read once the output is full
+        if self.limit.as_ref().is_some_and(LimitState::is_full) {
+            self.done = true;
+            if !self.pending.is_empty() {
+                self.lines.push_back(std::mem::take(&mut self.pending));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move the formatted output on to `pending`, keeping within `max_bytes`
+    fn pass_on(&mut self) -> std::io::Result<()> {
+        if let Some(limit) = &mut self.limit {
+            limit.write(&mut self.pending, self.options.line_end(), &self.formatted)?;
+            self.formatted.clear();
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for CatLines<'_, R> {
+    type Item = CatResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(line) = self.lines.pop_front() {
+                return Some(Ok(line));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.fill() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl Options {
+    /// Iterate over the formatted lines of `reader`
+    pub fn lines<R: Read>(&self, reader: R) -> CatLines<'_, R> {
+        CatLines::new(reader, self, State::new(self))
+    }
+}
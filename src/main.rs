@@ -1,179 +1,752 @@
-use carboncopycat::cat_files;
+use std::ffi::OsString;
+use std::io::BufWriter;
+use std::io::IsTerminal;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::fd::FromRawFd;
+use std::process::ExitCode;
+
+use carboncopycat::cat_counted;
+use carboncopycat::cat_file_list_to;
+use carboncopycat::cat_files_to;
+use carboncopycat::parse_args;
+use carboncopycat::Args;
 use carboncopycat::CatFilesError;
+use carboncopycat::CatStats;
+use carboncopycat::Checksum;
+use carboncopycat::ChecksumWriter;
+use carboncopycat::ColorChoice;
 use carboncopycat::NumberingMode;
 use carboncopycat::Options;
+use carboncopycat::OptionsError;
+use carboncopycat::ParseError;
+use carboncopycat::SplitWriter;
+use carboncopycat::TeeWriter;
 use owo_colors::OwoColorize;
+use owo_colors::Stream::Stderr;
+use owo_colors::Stream::Stdout;
+use owo_colors::Style;
+#[cfg(windows)]
+use windows_sys::Win32::Globalization::CP_UTF8;
+#[cfg(windows)]
+use windows_sys::Win32::System::Console::GetConsoleMode;
+#[cfg(windows)]
+use windows_sys::Win32::System::Console::GetConsoleOutputCP;
+#[cfg(windows)]
+use windows_sys::Win32::System::Console::GetStdHandle;
+#[cfg(windows)]
+use windows_sys::Win32::System::Console::SetConsoleOutputCP;
+#[cfg(windows)]
+use windows_sys::Win32::System::Console::STD_OUTPUT_HANDLE;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 fn usage(program: &str) {
-    let program_color = program.bright_green();
-    let option_args = "[OPTION]...".bright_blue();
-    let file_args = "[FILE]...".bright_yellow();
+    let heading = Style::new().bold().underline();
+    let program_color = program.if_supports_color(Stdout, |text| text.bright_green());
+    let option_args = "[OPTION]...".if_supports_color(Stdout, |text| text.bright_blue());
+    let file_args = "[FILE]...".if_supports_color(Stdout, |text| text.bright_yellow());
     println!();
-    print!("{}", "Usage:".bold().underline());
+    print!(
+        "{}",
+        "Usage:".if_supports_color(Stdout, |text| text.style(heading))
+    );
     println!(" {program_color} {option_args} {file_args}\n",);
     println!(
         "\
-With no FILE, or when FILE is -, read standard input.
+With no FILE, or when FILE is -, read standard input. Arguments after -- are
+always files, even if they start with -.
 
     -A, --show-all           equivalent to -vET
     -b, --number-nonblank    number nonempty output lines, overrides -n
     -e                       equivalent to -vE
     -E, --show-ends          display $ at end of each line
+        --show-lf[=HOW]      display line feeds as \\n, after any $; HOW is
+                             'break' (the default) to still end the line, or
+                             'inline' to run the lines together
+        --normalize-crlf     treat \\r\\n as one line ending, so -E writes $ before
+                             it, and lines of only \\r\\n are blank
     -n, --number             number all output lines
+        --number-start N     number lines starting from N instead of 1
+        --number-reset-per-file
+                             start numbering again at each file, instead of
+                             carrying on from the last one
+        --offsets[=BASE]     start each line with the offset of its first byte
+                             in the input instead of a line number; BASE is
+                             'dec' (the default) or 'hex'. Offsets are counted
+                             after --skip-bytes, decompression and decoding
     -s, --squeeze-blank      suppress repeated empty output lines
+        --squeeze-repeats    suppress repeated output lines of any kind
     -t                       equivalent to -vT
     -T, --show-tabs          display TAB characters as ^I
+        --expand-leading[=N] turn the tabs indenting each line into spaces,
+                             with tab stops every N columns (default 8)
     -u                       (ignored)
     -v, --show-nonprinting   use ^ and M- notation, except for LFD and TAB
-        --help               display this help and exit
-        --version            output version information and exit
+        --nul HOW            write NUL bytes unchanged ('keep'), as ^@
+                             ('caret') or as the character C ('replace:C'),
+                             with or without -v
+        --invalid HOW        with -v, write valid UTF-8 as it is, and bytes that
+                             aren't valid UTF-8 escaped ('escape'), as U+FFFD
+                             ('replace') or not at all ('skip')
+        --lines N,M          output only input lines N through M; either may be
+                             omitted, as in N, or ,M
+        --match TEXT         output only input lines containing TEXT
+        --invert             with --match, output only lines not containing it
+        --uniq               output only the first of repeated input lines
+        --uniq-count         like --uniq, prefixing lines with their count
+        --head N             output only the first N lines
+        --tail N             output only the last N lines
+        --wrap N             break lines longer than N columns"
+    );
+    #[cfg(feature = "terminal")]
+    println!("                             or, with 'auto', the terminal's width");
+    #[cfg(feature = "regex")]
+    println!(
+        "        --highlight REGEX    color the parts of lines matching REGEX, each
+                             pattern given in the next color"
+    );
+    println!(
+        "        --line-terminator WHICH
+                             end output lines with 'NUL' or 'LF' (the default)
+        --ensure-trailing-newline
+                             end each file with a line terminator, adding one
+                             if it's missing"
+    );
+    #[cfg(feature = "encoding")]
+    println!(
+        "        --output-encoding ENC
+                             write the output as 'utf-8' (the default),
+                             'latin1' or 'utf-16le'
+        --unmappable WHAT    'replace' (the default) or 'drop' characters that
+                             the output encoding can't represent"
+    );
+    println!(
+        "        --strip-ansi         remove ANSI escape sequences, such as colors
+        --trim-trailing      remove spaces and tabs at the end of each line
+        --interpret-escapes  turn backslash escapes such as '\\t' and '\\x41' into
+                             the bytes they stand for
+        --hex                display a hex dump of the input instead
+        --json               write each line as a JSON object with its text,
+                             and its number with -n or -b
+        --max-bytes N        stop after N bytes of output, at the end of a line
+                             if one fits
+        --skip-bytes N       discard the first N bytes of each file
+        --files-from LIST    read the files to concatenate from LIST, one per
+                             line, instead of the command line; '-' reads the
+                             list from standard input
+        --files0-from LIST   like --files-from, with names ended by NUL
+        --fd N               read the inherited file descriptor N instead of
+                             files (unix only)
+        --count              print only the number of lines, counting only
+                             non-blank lines with -b
+        --split-lines N      write the output to files of N lines each instead
+                             of stdout, named PREFIX00, PREFIX01 and so on
+        --output-prefix PREFIX
+                             start the --split-lines file names with PREFIX
+                             instead of 'x'
+        --tee FILE           also write the output to FILE
+        --checksum ALGO      print a checksum of the output to stderr when done;
+                             ALGO is 'crc32'"
+    );
+    #[cfg(feature = "sha256")]
+    println!("                             or 'sha256'");
+    println!(
+        "        --stats              print line, byte and squeezed blank line counts to
+                             stderr when done
+        --with-filename      start each line with the name of its file, before
+                             any line number
+        --headers            write a '==> FILE <==' header before each file
+        --follow             after the last FILE, keep writing data appended to
+                             it until interrupted
+        --binary-check       skip files that look binary, unless -v is given
+        --check              only check that each FILE can be read, reporting
+                             the ones that can't, and write nothing
+        --progress           show how much of each file has been read on stderr,
+                             if it is a terminal
+        --flush WHEN         flush the output after every 'line', whenever its
+                             buffer is full ('block', the default) or only at
+                             the end ('never')
+        --color[=WHEN]       color line numbers, markers and messages; WHEN is
+                             'auto' (the default), 'always' or 'never'
+    -h, --help               display this help and exit
+    -V, --version            output version information and exit
+
+Exit status is 0 if all files were written, and 1 if any file could not be
+read or an option was invalid.
 "
     );
-    print!("{}", "Examples:".bold().underline());
+    print!(
+        "{}",
+        "Examples:".if_supports_color(Stdout, |text| text.style(heading))
+    );
     println!(
         "
     {} f - g  Output f's contents, then standard input, then g's contents.
     {}        Copy standard input to standard output.
 ",
-        program.bright_green(),
-        program.bright_green(),
+        program.if_supports_color(Stdout, |text| text.bright_green()),
+        program.if_supports_color(Stdout, |text| text.bright_green()),
     );
 
     println!(
         "Source code: {}",
-        "<https://github.com/pilleye/carboncopycat>".bright_blue()
+        "<https://github.com/pilleye/carboncopycat>"
+            .if_supports_color(Stdout, |text| text.bright_blue())
+    );
+}
+
+fn version(program: &str) {
+    println!(
+        "{} v{}",
+        program.if_supports_color(Stdout, |text| text.bright_green()),
+        VERSION
     );
 }
 
 fn invalid_option(program: &str, option: &str) {
-    eprint!("{}: ", program.bright_green());
-    eprint!("{}", "invalid option -- '".bright_red());
-    eprint!("{}", option.bright_blue());
-    eprintln!("{}", "'".bright_red());
+    eprint!(
+        "{}: ",
+        program.if_supports_color(Stderr, |text| text.bright_green())
+    );
+    eprint!(
+        "{}",
+        "invalid option -- '".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    eprint!(
+        "{}",
+        option.if_supports_color(Stderr, |text| text.bright_blue())
+    );
+    eprintln!(
+        "{}",
+        "'".if_supports_color(Stderr, |text| text.bright_red())
+    );
     eprintln!(
         "Try '{}' for more information.",
-        format!("{} --help", program).bright_green()
-    );
-}
-
-fn parse_args(args: &[String]) -> (Vec<String>, Options) {
-    let mut file_paths = Vec::new();
-    let mut options = Options::new();
-    for arg in args.iter().skip(1) {
-        if arg.starts_with("--") {
-            let option = arg.split_at(2).1;
-            match option {
-                "show-all" => {
-                    options = options
-                        .show_nonprinting(true)
-                        .show_tabs(true)
-                        .show_ends(true);
-                }
-                "number-nonblank" => {
-                    options = options.number(NumberingMode::NonEmpty);
-                }
-                "show-ends" => {
-                    options = options.show_ends(true);
-                }
-                "number" => {
-                    if options.number == NumberingMode::None {
-                        options = options.number(NumberingMode::All);
-                    }
-                }
-                "squeeze-blank" => {
-                    options = options.squeeze_blank(true);
-                }
-                "show-tabs" => {
-                    options = options.show_tabs(true);
-                }
-                "show-nonprinting" => {
-                    options = options.show_nonprinting(true);
-                }
-                "help" => {
-                    usage(&args[0]);
-                    std::process::exit(0);
-                }
-                "version" => {
-                    println!("{} v{}", &args[0].bright_green(), VERSION);
-                    std::process::exit(0);
-                }
-                _ => {
-                    invalid_option(&args[0], arg);
-                    std::process::exit(1);
-                }
+        format!("{} --help", program).if_supports_color(Stderr, |text| text.bright_green())
+    );
+}
+
+/// Print the number of lines for `--count`: with `-b`, only the non-blank ones.
+/// Returns whether that failed for any reason but the reader going away
+fn print_count(program: &str, options: &Options, stats: &CatStats) -> bool {
+    let count = match options.number {
+        NumberingMode::NonEmpty => stats.numbered,
+        _ => stats.lines,
+    };
+    match writeln!(std::io::stdout().lock(), "{count}") {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+            report_error(program, &CatFilesError::Output(e));
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Print the digest for `--checksum`
+fn print_checksum(program: &str, checksum: Checksum, digest: &str) {
+    let name = match checksum {
+        Checksum::Crc32 => "crc32",
+        #[cfg(feature = "sha256")]
+        Checksum::Sha256 => "sha256",
+    };
+    eprintln!(
+        "{}: {name} {digest}",
+        program.if_supports_color(Stderr, |text| text.bright_green()),
+    );
+}
+
+/// Print line, byte and squeezed blank line counts for `--stats`
+fn print_stats(program: &str, stats: &CatStats) {
+    eprintln!(
+        "{}: {} lines, {} bytes in, {} bytes out, {} blank lines squeezed",
+        program.if_supports_color(Stderr, |text| text.bright_green()),
+        stats.lines,
+        stats.bytes_in,
+        stats.bytes_out,
+        stats.blanks_squeezed,
+    );
+}
+
+fn ambiguous_option(program: &str, option: &str, candidates: &[&str]) {
+    eprint!(
+        "{}: ",
+        program.if_supports_color(Stderr, |text| text.bright_green())
+    );
+    eprint!(
+        "{}",
+        "option '--".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    eprint!(
+        "{}",
+        option.if_supports_color(Stderr, |text| text.bright_blue())
+    );
+    eprint!(
+        "{}",
+        "' is ambiguous; possibilities:".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    for candidate in candidates {
+        eprint!(
+            " '--{}'",
+            candidate.if_supports_color(Stderr, |text| text.bright_blue())
+        );
+    }
+    eprintln!();
+    eprintln!(
+        "Try '{}' for more information.",
+        format!("{} --help", program).if_supports_color(Stderr, |text| text.bright_green())
+    );
+}
+
+fn missing_argument(program: &str, option: &str) {
+    eprint!(
+        "{}: ",
+        program.if_supports_color(Stderr, |text| text.bright_green())
+    );
+    eprint!(
+        "{}",
+        "option '".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    eprint!(
+        "{}",
+        option.if_supports_color(Stderr, |text| text.bright_blue())
+    );
+    eprintln!(
+        "{}",
+        "' requires an argument".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    eprintln!(
+        "Try '{}' for more information.",
+        format!("{} --help", program).if_supports_color(Stderr, |text| text.bright_green())
+    );
+}
+
+fn invalid_argument(program: &str, option: &str, value: &str) {
+    eprint!(
+        "{}: ",
+        program.if_supports_color(Stderr, |text| text.bright_green())
+    );
+    eprint!(
+        "{}",
+        "invalid argument '".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    eprint!(
+        "{}",
+        value.if_supports_color(Stderr, |text| text.bright_blue())
+    );
+    eprint!(
+        "{}",
+        "' for '".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    eprint!(
+        "{}",
+        option.if_supports_color(Stderr, |text| text.bright_blue())
+    );
+    eprintln!(
+        "{}",
+        "'".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    eprintln!(
+        "Try '{}' for more information.",
+        format!("{} --help", program).if_supports_color(Stderr, |text| text.bright_green())
+    );
+}
+
+fn extra_operand(program: &str, file: &str) {
+    eprint!(
+        "{}: ",
+        program.if_supports_color(Stderr, |text| text.bright_green())
+    );
+    eprint!(
+        "{}",
+        "extra operand '".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    eprint!(
+        "{}",
+        file.if_supports_color(Stderr, |text| text.bright_blue())
+    );
+    eprintln!(
+        "{}",
+        "'".if_supports_color(Stderr, |text| text.bright_red())
+    );
+    eprintln!("file operands cannot be combined with --files-from, --files0-from or --fd");
+    eprintln!(
+        "Try '{}' for more information.",
+        format!("{} --help", program).if_supports_color(Stderr, |text| text.bright_green())
+    );
+}
+
+/// Print a message for an error in the command line
+fn report_parse_error(program: &str, error: &ParseError) {
+    match error {
+        ParseError::InvalidOption(option) => invalid_option(program, option),
+        ParseError::AmbiguousOption { option, candidates } => {
+            ambiguous_option(program, option, candidates)
+        }
+        ParseError::MissingArgument(option) => missing_argument(program, option),
+        ParseError::InvalidArgument { option, value } => invalid_argument(program, option, value),
+        ParseError::ExtraOperand(file) => extra_operand(program, file),
+        ParseError::Help { .. } | ParseError::Version { .. } => {}
+    }
+}
+
+/// The command line option that sets the [`Options`] field `field`, for the
+/// messages of [`OptionsError`]
+fn option_flag(field: &str) -> String {
+    let long = match field {
+        "hex_dump" => "hex",
+        "filter" => "match",
+        "invert_filter" => "invert",
+        // Only --invalid turns these on
+        "unicode_aware" | "invalid_utf8" => "invalid",
+        _ => return format!("--{}", field.replace('_', "-")),
+    };
+    format!("--{long}")
+}
+
+/// Print a message for options that can't be used together, naming them as
+/// they were given on the command line
+fn report_options_error(program: &str, error: &OptionsError) {
+    let message = match error {
+        OptionsError::Conflict(first, second) => format!(
+            "{} can't be combined with {}",
+            option_flag(first),
+            option_flag(second)
+        ),
+        OptionsError::Requires(first, second) => {
+            format!("{} needs {}", option_flag(first), option_flag(second))
+        }
+        _ => error.to_string(),
+    };
+    eprintln!(
+        "{}: {}",
+        program.if_supports_color(Stderr, |text| text.bright_green()),
+        message.if_supports_color(Stderr, |text| text.bright_red()),
+    );
+}
+
+/// Make the messages of the binary follow `color`
+fn set_color(color: ColorChoice) {
+    match color {
+        ColorChoice::Auto => owo_colors::unset_override(),
+        ColorChoice::Always => owo_colors::set_override(true),
+        ColorChoice::Never => owo_colors::set_override(false),
+    }
+}
+
+/// Print a message for an error that stopped a file from being read
+fn report_error(program: &str, error: &CatFilesError) {
+    match error {
+        CatFilesError::NotFound(file) => {
+            eprintln!(
+                "{}: {}: {}",
+                program.if_supports_color(Stderr, |text| text.bright_green()),
+                file.if_supports_color(Stderr, |text| text.bright_yellow()),
+                "No such file or directory".if_supports_color(Stderr, |text| text.bright_blue()),
+            );
+        }
+        CatFilesError::IsDirectory(file) => {
+            eprintln!(
+                "{}: {}: {}",
+                program.if_supports_color(Stderr, |text| text.bright_green()),
+                file.if_supports_color(Stderr, |text| text.bright_yellow()),
+                "Is a directory".if_supports_color(Stderr, |text| text.bright_blue()),
+            );
+        }
+        CatFilesError::PermissionDenied(file) => {
+            eprintln!(
+                "{}: {}: {}",
+                program.if_supports_color(Stderr, |text| text.bright_green()),
+                file.if_supports_color(Stderr, |text| text.bright_yellow()),
+                "Permission denied".if_supports_color(Stderr, |text| text.bright_blue()),
+            );
+        }
+        CatFilesError::Binary(file) => {
+            eprintln!(
+                "{}: {}: {}",
+                program.if_supports_color(Stderr, |text| text.bright_green()),
+                file.if_supports_color(Stderr, |text| text.bright_yellow()),
+                "binary file \u{2014} use -v to view"
+                    .if_supports_color(Stderr, |text| text.bright_blue()),
+            );
+        }
+        CatFilesError::Io { path, source } => {
+            eprintln!(
+                "{}: {}: {}",
+                program.if_supports_color(Stderr, |text| text.bright_green()),
+                path.if_supports_color(Stderr, |text| text.bright_yellow()),
+                source.if_supports_color(Stderr, |text| text.bright_blue()),
+            );
+        }
+        CatFilesError::StdinInList => {
+            eprintln!(
+                "{}: {}",
+                program.if_supports_color(Stderr, |text| text.bright_green()),
+                error.if_supports_color(Stderr, |text| text.bright_blue()),
+            );
+        }
+        CatFilesError::Output(e) => {
+            eprintln!(
+                "{}: {}",
+                program.if_supports_color(Stderr, |text| text.bright_green()),
+                e
+            );
+        }
+        CatFilesError::Failed { errors, .. } => {
+            for error in errors {
+                report_error(program, error);
             }
-        } else if arg.starts_with("-") {
-            // FIXME: Accept "-" as a file path for stdin
-            for c in arg.chars().skip(1) {
-                match c {
-                    'A' => {
-                        options = options
-                            .show_nonprinting(true)
-                            .show_tabs(true)
-                            .show_ends(true);
-                    }
-                    'b' => {
-                        options = options.number(NumberingMode::NonEmpty);
-                    }
-                    'e' => {
-                        options = options.show_nonprinting(true).show_ends(true);
-                    }
-                    'E' => {
-                        options = options.show_ends(true);
-                    }
-                    'n' => {
-                        if options.number == NumberingMode::None {
-                            options = options.number(NumberingMode::All);
-                        }
-                    }
-                    's' => {
-                        options = options.squeeze_blank(true);
-                    }
-                    't' => {
-                        options = options.show_nonprinting(true).show_tabs(true);
-                    }
-                    'T' => {
-                        options = options.show_tabs(true);
-                    }
-                    'u' => {
-                        // Ignored
-                    }
-                    'v' => {
-                        options = options.show_nonprinting(true);
-                    }
-                    _ => {
-                        invalid_option(&args[0], arg);
-                        std::process::exit(1);
-                    }
-                }
+        }
+    }
+}
+
+/// The width to wrap to for `--wrap auto`: that of the terminal, less the
+/// line number gutter, or `None` when standard output is not a terminal. The
+/// width is read once, so resizing the terminal afterwards has no effect.
+#[cfg(feature = "terminal")]
+fn terminal_wrap(options: &Options) -> Option<usize> {
+    let (terminal_size::Width(width), _) = terminal_size::terminal_size()?;
+    let mut gutter = 0;
+    if options.number != NumberingMode::None {
+        gutter = options.number_width;
+        for c in options.number_sep.chars() {
+            gutter = if c == '\t' {
+                gutter / 8 * 8 + 8
+            } else {
+                gutter + 1
+            };
+        }
+    }
+    Some(usize::from(width).saturating_sub(gutter).max(1))
+}
+
+/// Let `--follow` finish writing its output on Ctrl-C, once it has got to the
+/// last file. Before and after that, Ctrl-C stops the program as usual.
+extern "C" fn interrupted(signal: libc::c_int) {
+    if !carboncopycat::stop_following() {
+        // SAFETY: both are async-signal-safe
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+/// Switches the Windows console that standard output writes to over to UTF-8
+/// until dropped, so that non-ASCII output shows up as written. Output
+/// redirected to a file or pipe is left alone.
+#[cfg(windows)]
+struct ConsoleUtf8 {
+    /// The code page to go back to
+    previous: u32,
+}
+
+#[cfg(windows)]
+impl ConsoleUtf8 {
+    fn enable() -> Option<Self> {
+        // SAFETY: these only query and set the state of the console, and the
+        // standard output handle isn't closed
+        unsafe {
+            let mut mode = 0;
+            // Fails unless standard output is a console
+            if GetConsoleMode(GetStdHandle(STD_OUTPUT_HANDLE), &mut mode) == 0 {
+                return None;
+            }
+            let previous = GetConsoleOutputCP();
+            if previous == CP_UTF8 || SetConsoleOutputCP(CP_UTF8) == 0 {
+                return None;
             }
-        } else {
-            file_paths.push(arg.clone());
+            Some(Self { previous })
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for ConsoleUtf8 {
+    fn drop(&mut self) {
+        // SAFETY: as in `enable`
+        unsafe {
+            SetConsoleOutputCP(self.previous);
         }
     }
-    (file_paths, options)
-}
-
-pub fn main() {
-    let args = std::env::args().collect::<Vec<String>>();
-    let (files, options) = parse_args(&args);
-    if let Err(e) = cat_files(&files, &options) {
-        match e {
-            CatFilesError::NotFound(file) => {
-                eprintln!(
-                    "{}: {}: {}",
-                    &args[0].bright_green(),
-                    file.bright_yellow(),
-                    "No such file or directory".bright_blue(),
-                );
-                std::process::exit(1);
+}
+
+/// Format the input read from the inherited file descriptor `fd`, for `--fd`
+#[cfg(unix)]
+fn cat_fd<W: Write>(fd: i32, output: &mut W, options: &Options) -> Result<CatStats, CatFilesError> {
+    // SAFETY: F_GETFD only reads the descriptor's flags
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(CatFilesError::Io {
+            path: format!("fd {fd}"),
+            source: std::io::Error::last_os_error(),
+        });
+    }
+    // SAFETY: the descriptor is open, and was handed to this process to read
+    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+    Ok(cat_counted(&mut file, output, options)?)
+}
+
+#[cfg(not(unix))]
+fn cat_fd<W: Write>(fd: i32, _: &mut W, _: &Options) -> Result<CatStats, CatFilesError> {
+    Err(CatFilesError::Io {
+        path: format!("fd {fd}"),
+        source: std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "--fd is only supported on unix",
+        ),
+    })
+}
+
+/// Concatenate the files named on the command line, in the `--files-from`
+/// list, or read from the `--fd` descriptor to `output` and flush it
+fn cat_to<W: Write>(
+    files: &[OsString],
+    files_from: Option<&(OsString, u8)>,
+    fd: Option<i32>,
+    output: &mut W,
+    options: &Options,
+) -> Result<CatStats, CatFilesError> {
+    let result = match (files_from, fd) {
+        (Some((list, separator)), _) => cat_file_list_to(list, *separator, output, options),
+        (None, Some(fd)) => cat_fd(fd, output, options),
+        (None, None) => cat_files_to(files, output, options),
+    };
+    // Flush even if some files failed, so their output precedes the errors
+    output.flush()?;
+
+    result
+}
+
+/// Run the program and return its exit status: 0 if every file was written
+/// (or help or the version was shown), 1 if any file could not be read, an
+/// option was invalid, or the output could not be written. A closed output
+/// pipe is not an error.
+pub fn main() -> ExitCode {
+    // File names may be any bytes, so they aren't required to be UTF-8
+    let args = std::env::args_os().collect::<Vec<OsString>>();
+    let program = args[0].to_string_lossy();
+    let Args {
+        files,
+        options,
+        stats,
+        count,
+        files_from,
+        fd,
+        checksum,
+        split_lines,
+        output_prefix,
+        tee,
+        #[cfg(feature = "terminal")]
+        wrap_auto,
+    } = match parse_args(args.iter().cloned()) {
+        Ok(args) => args,
+        Err(ParseError::Help { color }) => {
+            set_color(color);
+            usage(&program);
+            return ExitCode::SUCCESS;
+        }
+        Err(ParseError::Version { color }) => {
+            set_color(color);
+            version(&program);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            report_parse_error(&program, &e);
+            return ExitCode::FAILURE;
+        }
+    };
+    set_color(options.color);
+    if let Err(e) = options.validate() {
+        report_options_error(&program, &e);
+        return ExitCode::FAILURE;
+    }
+    // Progress is only for someone watching, so it stays out of redirected
+    // stderr
+    let options = if options.progress && !std::io::stderr().is_terminal() {
+        options.progress(false)
+    } else {
+        options
+    };
+    if options.follow {
+        // SAFETY: the handler only stores to an atomic flag
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                interrupted as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+    #[cfg(feature = "terminal")]
+    let options = if wrap_auto {
+        let wrap = terminal_wrap(&options);
+        options.wrap(wrap)
+    } else {
+        options
+    };
+    // Kept until the output is flushed at the end of `main`
+    #[cfg(windows)]
+    let _console = ConsoleUtf8::enable();
+    let mut output: Box<dyn Write> = if count {
+        Box::new(std::io::sink())
+    } else if let Some(lines) = split_lines {
+        Box::new(SplitWriter::new(&output_prefix, lines, &options))
+    } else {
+        Box::new(BufWriter::new(std::io::stdout().lock()))
+    };
+    if let Some(path) = &tee {
+        match std::fs::File::create(path) {
+            Ok(copy) => output = Box::new(TeeWriter::new(output, BufWriter::new(copy))),
+            Err(source) => {
+                let path = path.to_string_lossy().into_owned();
+                report_error(&program, &CatFilesError::Io { path, source });
+                return ExitCode::FAILURE;
             }
-            CatFilesError::Io(e) => {
-                eprintln!("{}: {}", &args[0].bright_green(), e);
-                std::process::exit(1);
+        }
+    }
+    let (result, digest) = match checksum {
+        Some(checksum) => {
+            let mut output = ChecksumWriter::new(&mut output, checksum);
+            let result = cat_to(&files, files_from.as_ref(), fd, &mut output, &options);
+            (result, Some((checksum, output.digest())))
+        }
+        None => (
+            cat_to(&files, files_from.as_ref(), fd, &mut output, &options),
+            None,
+        ),
+    };
+    match result {
+        Ok(counts) => {
+            if let Some((checksum, digest)) = &digest {
+                print_checksum(&program, *checksum, digest);
+            }
+            let failed = count && print_count(&program, &options, &counts);
+            if stats {
+                print_stats(&program, &counts);
+            }
+            if failed {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+        Err(CatFilesError::Output(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+            // The reader went away (e.g. `ccc file | head`); exit quietly like GNU cat
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            report_error(&program, &e);
+            if let CatFilesError::Failed { stats: counts, .. } = e {
+                if let Some((checksum, digest)) = &digest {
+                    print_checksum(&program, *checksum, digest);
+                }
+                if count {
+                    print_count(&program, &options, &counts);
+                }
+                if stats {
+                    print_stats(&program, &counts);
+                }
             }
+            ExitCode::FAILURE
         }
     }
 }
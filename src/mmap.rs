@@ -0,0 +1,20 @@
+use std::fs::File;
+
+use memmap2::Mmap;
+
+/// Files smaller than this are read normally, as mapping them costs more than
+/// the read calls it saves
+const MMAP_MIN_SIZE: u64 = 1 << 20;
+
+/// Map `file` into memory, or return `None` if it is not a regular file, is
+/// too small to be worth it, or cannot be mapped
+pub(crate) fn map_file(file: &File) -> Option<Mmap> {
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() || metadata.len() < MMAP_MIN_SIZE {
+        return None;
+    }
+    // SAFETY: the map is only read while the file is open. If another process
+    // truncates the file in the meantime, reading the map may fault, which is
+    // the accepted trade-off for mapping files, as in other tools that do.
+    unsafe { Mmap::map(file) }.ok()
+}
@@ -0,0 +1,56 @@
+use crate::options::NulMode;
+use crate::options::MARKER_COLOR;
+use crate::options::RESET_COLOR;
+
+/// What `show_nonprinting` writes for each byte, worked out once so that the
+/// formatting loop only has to look it up. Printable bytes stand for
+/// themselves, TAB for the tab representation, NUL for whatever `nul` says,
+/// and the rest for their `^` and `M-` notation, in color if enabled.
+pub(crate) struct NonprintTable {
+    /// The output for every byte, one after the other
+    bytes: Vec<u8>,
+    /// Where the output for each byte starts in `bytes`, and where the last
+    /// one ends
+    offsets: [u32; 257],
+}
+
+impl NonprintTable {
+    pub(crate) fn new(tab: &[u8], nul: NulMode, color: bool) -> Self {
+        let mut bytes = Vec::with_capacity(256 * 4);
+        let mut offsets = [0; 257];
+        for byte in 0..=255u8 {
+            match (byte, nul) {
+                (0, NulMode::Keep) => bytes.push(0),
+                (0, NulMode::Replace(c)) => {
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+                }
+                (9, _) => bytes.extend_from_slice(tab),
+                (32..=126, _) => bytes.push(byte),
+                _ => {
+                    if color {
+                        bytes.extend_from_slice(MARKER_COLOR.as_bytes());
+                    }
+                    match byte {
+                        0..=31 => bytes.extend_from_slice(&[b'^', byte + 64]),
+                        127 => bytes.extend_from_slice(b"^?"),
+                        128..=159 => bytes.extend_from_slice(&[b'M', b'-', b'^', byte - 64]),
+                        160..=254 => bytes.extend_from_slice(&[b'M', b'-', byte - 128]),
+                        _ => bytes.extend_from_slice(b"M-^?"),
+                    }
+                    if color {
+                        bytes.extend_from_slice(RESET_COLOR.as_bytes());
+                    }
+                }
+            }
+            offsets[byte as usize + 1] = bytes.len() as u32;
+        }
+        Self { bytes, offsets }
+    }
+
+    /// The output for `byte`
+    pub(crate) fn get(&self, byte: u8) -> &[u8] {
+        let start = self.offsets[byte as usize] as usize;
+        let end = self.offsets[byte as usize + 1] as usize;
+        &self.bytes[start..end]
+    }
+}
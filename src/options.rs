@@ -1,4 +1,28 @@
+use std::borrow::Cow;
+use std::io::IsTerminal;
+
+use once_cell::sync::Lazy;
+use thiserror::Error;
+
+#[cfg(feature = "regex")]
+use crate::Highlight;
+
+/// Escape sequence that colors line numbers
+pub(crate) const NUMBER_COLOR: &str = "\x1b[32m";
+/// Escape sequence that colors `$`, `^I` and other markers
+pub(crate) const MARKER_COLOR: &str = "\x1b[36m";
+/// Escape sequence that resets the color
+pub(crate) const RESET_COLOR: &str = "\x1b[0m";
+
+/// Whether `ColorChoice::Auto` colors, decided once for the whole process
+static AUTO_COLOR: Lazy<bool> = Lazy::new(|| {
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && std::io::stdout().is_terminal()
+});
+
 #[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
 pub enum NumberingMode {
     /// Do not number liens
     None,
@@ -8,22 +32,443 @@ pub enum NumberingMode {
     All,
 }
 
+/// How `offsets` writes the byte offset of each line
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum OffsetFormat {
+    /// In decimal, like `grep -b`
+    Decimal,
+    /// In lowercase hexadecimal, like the offsets of `hex_dump`
+    Hex,
+}
+
+/// Alignment of line numbers within their width
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Align {
+    /// Pad on the right
+    Left,
+    /// Pad on the left, like GNU cat
+    Right,
+}
+
+/// The character encoding of the input
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Encoding {
+    /// Detect the encoding from a byte order mark, falling back to UTF-8
+    Auto,
+    /// Pass the input through unchanged
+    Utf8,
+    /// Decode UTF-16 little endian input
+    Utf16Le,
+    /// Decode UTF-16 big endian input
+    Utf16Be,
+}
+
+/// The character encoding of the output
+#[cfg(feature = "encoding")]
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum OutputEncoding {
+    /// Write the output as it is
+    Utf8,
+    /// Encode the output as ISO-8859-1
+    Latin1,
+    /// Encode the output as UTF-16 little endian, without a byte order mark
+    Utf16Le,
+}
+
+/// What to write for output that `output_encoding` can't represent
+#[cfg(feature = "encoding")]
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Unmappable {
+    /// Leave it out
+    Drop,
+    /// Write `?` in Latin-1, or U+FFFD otherwise
+    Replace,
+}
+
+/// Compression of the input
+#[cfg(feature = "gzip")]
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Compression {
+    /// Read the input as is
+    None,
+    /// Decompress gzip input
+    Gzip,
+    /// Decompress the input if it starts with the gzip magic bytes
+    Auto,
+}
+
+/// The line terminator written for each line
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum OutputLineEnding {
+    /// Write line endings as they are in the input
+    Keep,
+    /// Write `\n`, removing the `\r` of `\r\n` endings
+    Lf,
+    /// Write `\r\n`, without doubling the `\r` of `\r\n` endings
+    CrLf,
+}
+
+/// How `show_lf` shows the line feeds of the input
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum ShowLf {
+    /// Write `\n`, then end the line as usual
+    Break,
+    /// Write only `\n`, so the lines of the input run together
+    Inline,
+}
+
+/// How `unicode_aware` writes bytes that are not valid UTF-8
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum InvalidUtf8 {
+    /// Escape each byte in `M-` and `^` notation
+    Escape,
+    /// Write U+FFFD for each invalid sequence, like `String::from_utf8_lossy`
+    Replace,
+    /// Leave them out
+    Skip,
+}
+
+/// How NUL bytes are written, for `nul`
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum NulMode {
+    /// Pass NUL through unchanged
+    Keep,
+    /// Write `^@`
+    Caret,
+    /// Write the given character instead
+    Replace(char),
+}
+
+/// When to color line numbers and markers
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum ColorChoice {
+    /// Color if stdout is a terminal and `NO_COLOR` is not set
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
+}
+
+/// When to flush the output
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum FlushMode {
+    /// Only once all the input is written
+    Never,
+    /// After every line, for output that should show up as soon as it is read
+    Line,
+    /// Only when the output's own buffer is full, as a `BufWriter` does
+    Block,
+}
+
 /// Options to format the output
+///
+/// Options can be built up with the builder methods, or, when setting many of
+/// them at once, written as a struct literal with the rest left at their
+/// defaults. Every field is public and has a default, so that this keeps
+/// working as options are added:
+///
+/// ```
+/// use carboncopycat::NumberingMode;
+/// use carboncopycat::Options;
+///
+/// let options = Options {
+///     number: NumberingMode::All,
+///     show_ends: true,
+///     squeeze_blank: true,
+///     ..Options::default()
+/// };
+/// assert_eq!(
+///     options,
+///     Options::new()
+///         .number(NumberingMode::All)
+///         .show_ends(true)
+///         .squeeze_blank(true)
+/// );
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Options {
     /// Setting to number lines
     pub number: NumberingMode,
 
+    /// Start each line with the offset of its first byte in the input, where
+    /// the line number would go, formatted with `number_width`, `number_align`
+    /// and `number_sep`. Offsets count the input as it reaches the formatting,
+    /// after `skip_bytes`, decompression and decoding.
+    pub offsets: Option<OffsetFormat>,
+
+    /// The number of the first numbered line
+    pub number_start: usize,
+
+    /// Start numbering again from `number_start` at each file, instead of
+    /// carrying on from the last file like GNU `cat`
+    pub number_reset_per_file: bool,
+
+    /// The minimum width of line numbers
+    pub number_width: usize,
+
+    /// Alignment of line numbers within `number_width`
+    pub number_align: Align,
+
+    /// Separator written between a line number and the line
+    pub number_sep: String,
+
     /// Display a `$` after the end of each line
     pub show_ends: bool,
 
+    /// Marker displayed at the end of each line instead of `$`
+    pub end_marker: Option<String>,
+
+    /// Display each line feed of the input as `\n`, after the `show_ends`
+    /// marker if any, and either keep or drop the line end itself
+    pub show_lf: Option<ShowLf>,
+
     /// Suppress repeated empty output lines
     pub squeeze_blank: bool,
 
+    /// The number of consecutive empty lines kept when `squeeze_blank` is set
+    pub max_blank: usize,
+
+    /// Suppress repeated output lines of any kind, keeping the first of each
+    /// run of identical lines. Unlike `uniq`, the dropped lines don't count
+    /// towards line numbering, so the kept line gets one number. With
+    /// `squeeze_blank`, runs of blank lines are left to it and `max_blank`.
+    pub squeeze_repeats: bool,
+
     /// Display TAB characters as `^I`
     pub show_tabs: bool,
 
-    /// Use `^` and `M-` notation, except for LFD and TAB
+    /// Representation of TAB characters to use instead of `^I`
+    pub tab_repr: Option<String>,
+
+    /// Expand TAB characters to spaces, with tab stops every N columns.
+    /// Takes precedence over `show_tabs`, but is ignored along with any of
+    /// `show_nonprinting`, `strip_nonprinting`, `strip_ansi` or
+    /// `interpret_escapes`.
+    pub expand_tabs: Option<usize>,
+
+    /// Like `expand_tabs`, but only expand the tabs in the indentation at the
+    /// start of each line, leaving the rest as they are. `expand_tabs` takes
+    /// precedence.
+    pub expand_leading: Option<usize>,
+
+    /// Break output lines longer than N columns, counting the columns of the
+    /// line as shown by the other options but not its line number. With
+    /// numbering or `offsets`, continuation lines are indented to line up
+    /// instead of getting a number of their own.
+    pub wrap: Option<usize>,
+
+    /// Use `^` and `M-` notation, except for LFD and TAB. Ignored along with
+    /// `strip_nonprinting`, `strip_ansi` or `interpret_escapes`.
     pub show_nonprinting: bool,
+
+    /// How to write NUL bytes, with or without `show_nonprinting`. With
+    /// `None`, NUL is `^@` with `show_nonprinting` and unchanged otherwise.
+    /// `strip_nonprinting` still drops it.
+    pub nul: Option<NulMode>,
+
+    /// With `show_nonprinting`, pass valid UTF-8 through unchanged and only
+    /// escape control characters and invalid bytes
+    pub unicode_aware: bool,
+
+    /// With `unicode_aware`, how to write bytes that are not valid UTF-8. The
+    /// text after them is read as UTF-8 again from the next byte that can
+    /// start a character.
+    pub invalid_utf8: InvalidUtf8,
+
+    /// Drop the bytes that `show_nonprinting` would escape, keeping TAB and
+    /// LFD. Takes precedence over `show_nonprinting` and `expand_tabs`, but is
+    /// ignored along with `strip_ansi` or `interpret_escapes`.
+    pub strip_nonprinting: bool,
+
+    /// Drop ANSI CSI escape sequences, such as color codes, and pass
+    /// everything else through. Takes precedence over `strip_nonprinting`,
+    /// `show_nonprinting` and `expand_tabs`, but is ignored along with
+    /// `interpret_escapes`.
+    pub strip_ansi: bool,
+
+    /// Drop spaces and tabs at the end of each line, before a carriage return
+    /// that ends it too. This comes before the other formatting, so
+    /// `show_ends` marks the trimmed end, `show_tabs` only shows the tabs left
+    /// inside the line, and a line of only spaces and tabs becomes blank.
+    pub trim_trailing: bool,
+
+    /// Turn backslash escapes such as `\t` and `\x41` into the bytes they
+    /// stand for, like `echo -e`. Takes precedence over `strip_ansi`,
+    /// `strip_nonprinting`, `show_nonprinting` and `expand_tabs`.
+    pub interpret_escapes: bool,
+
+    /// Treat a carriage return that is not followed by a line feed as the end
+    /// of a line, as in classic Mac files
+    pub cr_is_eol: bool,
+
+    /// Treat `\r\n` as a single line ending, written as it is after the
+    /// `show_ends` marker, so that `show_ends` puts one `$` on CRLF lines as on
+    /// LF lines and lines of only `\r\n` are blank. With `show_nonprinting`,
+    /// the carriage return is still shown as `^M`, before the marker. Only
+    /// matters with [`OutputLineEnding::Keep`].
+    pub normalize_crlf: bool,
+
+    /// The line terminator to write
+    pub line_ending: OutputLineEnding,
+
+    /// What to write at the end of each line instead of a line feed, such as
+    /// `"\0"`. With [`OutputLineEnding::CrLf`], it follows the carriage return.
+    pub line_terminator: String,
+
+    /// End the output of each input with a line terminator when its last line
+    /// doesn't have one, so the files of [`cat_files`](crate::cat_files) don't
+    /// run into each other. The added line end shows `show_ends` like any
+    /// other.
+    pub ensure_trailing_newline: bool,
+
+    /// Compression of the input, which is decompressed before decoding
+    #[cfg(feature = "gzip")]
+    pub decompress: Compression,
+
+    /// Encoding of the input, which is decoded to UTF-8 before formatting
+    pub encoding: Encoding,
+
+    /// The character encoding to write the output in. Bytes of the formatted
+    /// output that are not valid UTF-8 count as unmappable.
+    #[cfg(feature = "encoding")]
+    pub output_encoding: OutputEncoding,
+
+    /// What to write for output that `output_encoding` can't represent
+    #[cfg(feature = "encoding")]
+    pub unmappable: Unmappable,
+
+    /// Remove a UTF-8 byte order mark from the start of the input
+    pub strip_bom: bool,
+
+    /// Discard this many bytes from the start of the input, before any
+    /// decompression or decoding, so that line 1 starts after them
+    pub skip_bytes: usize,
+
+    /// Write the lines of each input in reverse order, like `tac`
+    pub reverse_lines: bool,
+
+    /// Write only the input lines from the first to the second line number,
+    /// inclusive. Either end may be left open. Skipped lines still count
+    /// towards line numbering.
+    pub line_range: Option<(Option<usize>, Option<usize>)>,
+
+    /// Write only the input lines that contain this string. Skipped lines
+    /// still count towards line numbering.
+    pub filter: Option<String>,
+
+    /// With `filter`, write only the lines that do not contain it instead
+    pub invert_filter: bool,
+
+    /// Write only the first of a run of identical input lines, like `uniq`.
+    /// The dropped lines still count towards line numbering. Applies before
+    /// `squeeze_blank`, so runs of blank lines are already cut to one.
+    pub uniq: bool,
+
+    /// With `uniq`, start each line with the length of its run, like
+    /// `uniq -c`. Lines are then numbered and ranged as written, and each one
+    /// is held back until the next different line.
+    pub uniq_count: bool,
+
+    /// Write only the first N lines of output
+    pub head: Option<usize>,
+
+    /// Write only the last N lines of output. If `head` is also set, this
+    /// selects the last N of the lines kept by `head`.
+    pub tail: Option<usize>,
+
+    /// Write at most this many bytes of output, stopping after the last whole
+    /// line that fits, or in the middle of the first line if it doesn't. The
+    /// rest of the input is not read. In `cat_files`, this is the total for
+    /// all the files.
+    pub max_bytes: Option<usize>,
+
+    /// Write a hex dump of the input, like `hexdump -C`, instead of applying
+    /// any other formatting
+    pub hex_dump: bool,
+
+    /// Write each line as a JSON object on a line of its own, like
+    /// `{"n": 1, "text": "..."}`. `n` is only there when the line is numbered,
+    /// and `text` is the line as it was read, with JSON string escaping
+    /// instead of `show_nonprinting` or other changes to the text.
+    pub json: bool,
+
+    /// When to color line numbers, end markers and escapes
+    pub color: ColorChoice,
+
+    /// Color the parts of each line that match these patterns, when `color`
+    /// allows. Matches are found in the line as formatted, and never span
+    /// lines.
+    #[cfg(feature = "regex")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub highlight: Vec<Highlight>,
+
+    /// In `cat_files`, start each line with the name of the file it came
+    /// from and a colon, like `grep -H`. Comes before the line number.
+    pub with_filename: bool,
+
+    /// In `cat_files`, write a `==> name <==` header before each file, with a
+    /// blank line before all but the first, like `tail`
+    pub headers: bool,
+
+    /// In `cat_files`, once the last file is written, keep polling it and
+    /// format data appended to it, like `tail -f`, until
+    /// [`stop_following`](crate::stop_following) is called. Numbering carries
+    /// on across the appended data. Standard input is not followed. Options
+    /// that need all of the input at once, such as `head` or `skip_bytes`,
+    /// can't be combined with it, nor can `max_bytes`, which would leave it
+    /// polling once the output is full.
+    pub follow: bool,
+
+    /// Skip files in `cat_files` whose start looks binary, unless
+    /// `show_nonprinting` is set
+    pub binary_check: bool,
+
+    /// In `cat_files`, keep a line on standard error showing how much of each
+    /// file has been read, with a percentage for regular files. The line is
+    /// cleared once the file is done.
+    pub progress: bool,
+
+    /// In `cat_files`, only open each file to check that it can be read,
+    /// writing nothing. Files that can't are reported like they would be
+    /// otherwise.
+    pub check: bool,
+
+    /// Memory-map large regular files in `cat_files` instead of reading them
+    #[cfg(feature = "mmap")]
+    pub mmap: bool,
+
+    /// Size of the read buffer in bytes, or 0 to use the built-in default
+    pub buffer_size: usize,
+
+    /// When to flush the output
+    pub flush: FlushMode,
 }
 
 impl Options {
@@ -31,68 +476,775 @@ impl Options {
     pub fn new() -> Self {
         Self {
             number: NumberingMode::None,
+            offsets: None,
+            number_start: 1,
+            number_reset_per_file: false,
+            number_width: 6,
+            number_align: Align::Right,
+            number_sep: "\t".to_string(),
             show_ends: false,
+            show_lf: None,
+            end_marker: None,
             squeeze_blank: false,
+            max_blank: 1,
+            squeeze_repeats: false,
             show_tabs: false,
+            tab_repr: None,
+            expand_tabs: None,
+            expand_leading: None,
+            wrap: None,
             show_nonprinting: false,
+            nul: None,
+            unicode_aware: false,
+            invalid_utf8: InvalidUtf8::Escape,
+            strip_nonprinting: false,
+            strip_ansi: false,
+            trim_trailing: false,
+            interpret_escapes: false,
+            cr_is_eol: false,
+            normalize_crlf: false,
+            line_ending: OutputLineEnding::Keep,
+            line_terminator: "\n".to_string(),
+            ensure_trailing_newline: false,
+            #[cfg(feature = "gzip")]
+            decompress: Compression::None,
+            encoding: Encoding::Utf8,
+            #[cfg(feature = "encoding")]
+            output_encoding: OutputEncoding::Utf8,
+            #[cfg(feature = "encoding")]
+            unmappable: Unmappable::Replace,
+            strip_bom: false,
+            skip_bytes: 0,
+            reverse_lines: false,
+            line_range: None,
+            filter: None,
+            invert_filter: false,
+            uniq: false,
+            uniq_count: false,
+            head: None,
+            tail: None,
+            max_bytes: None,
+            hex_dump: false,
+            json: false,
+            color: ColorChoice::Never,
+            #[cfg(feature = "regex")]
+            highlight: Vec::new(),
+            with_filename: false,
+            headers: false,
+            follow: false,
+            binary_check: false,
+            progress: false,
+            check: false,
+            #[cfg(feature = "mmap")]
+            mmap: false,
+            buffer_size: 0,
+            flush: FlushMode::Block,
         }
     }
 
-    /// Update with the number option
+    /// Update with the number option. This replaces any mode set before; use
+    /// [`stack_number`](Self::stack_number) to combine `-n` and `-b` like
+    /// `cat` does.
     pub fn number(mut self, number: NumberingMode) -> Self {
         self.number = number;
         self
     }
 
+    /// Update with the number option as `-n` and `-b` stack on the command
+    /// line: [`NumberingMode::NonEmpty`] wins over [`NumberingMode::All`],
+    /// whichever is given first, and [`NumberingMode::None`] changes nothing
+    pub fn stack_number(mut self, number: NumberingMode) -> Self {
+        match (self.number, number) {
+            (_, NumberingMode::None) | (NumberingMode::NonEmpty, _) => {}
+            _ => self.number = number,
+        }
+        self
+    }
+
+    /// Update with the offsets option
+    pub fn offsets(mut self, offsets: Option<OffsetFormat>) -> Self {
+        self.offsets = offsets;
+        self
+    }
+
+    /// Update with the number_start option
+    pub fn number_start(mut self, number_start: usize) -> Self {
+        self.number_start = number_start;
+        self
+    }
+
+    /// Update with the number_reset_per_file option
+    pub fn number_reset_per_file(mut self, number_reset_per_file: bool) -> Self {
+        self.number_reset_per_file = number_reset_per_file;
+        self
+    }
+
+    /// Enable show_nonprinting, show_tabs and show_ends, like `-A`
+    pub fn show_all(self) -> Self {
+        self.show_nonprinting(true).show_tabs(true).show_ends(true)
+    }
+
+    /// Update with the number_width option
+    pub fn number_width(mut self, number_width: usize) -> Self {
+        self.number_width = number_width;
+        self
+    }
+
+    /// Update with the number_align option
+    pub fn number_align(mut self, number_align: Align) -> Self {
+        self.number_align = number_align;
+        self
+    }
+
+    /// Update with the number_sep option
+    pub fn number_sep(mut self, number_sep: impl Into<String>) -> Self {
+        self.number_sep = number_sep.into();
+        self
+    }
+
     /// Update with the show_ends option
     pub fn show_ends(mut self, show_ends: bool) -> Self {
         self.show_ends = show_ends;
         self
     }
 
+    /// Update with the end_marker option
+    pub fn end_marker(mut self, end_marker: impl Into<String>) -> Self {
+        self.end_marker = Some(end_marker.into());
+        self
+    }
+
+    /// Update with the show_lf option
+    pub fn show_lf(mut self, show_lf: Option<ShowLf>) -> Self {
+        self.show_lf = show_lf;
+        self
+    }
+
     /// Update with the squeeze_blank option
     pub fn squeeze_blank(mut self, squeeze_blank: bool) -> Self {
         self.squeeze_blank = squeeze_blank;
         self
     }
 
+    /// Update with the max_blank option
+    pub fn max_blank(mut self, max_blank: usize) -> Self {
+        self.max_blank = max_blank;
+        self
+    }
+
+    /// Update with the squeeze_repeats option
+    pub fn squeeze_repeats(mut self, squeeze_repeats: bool) -> Self {
+        self.squeeze_repeats = squeeze_repeats;
+        self
+    }
+
     /// Update with the show_tabs option
     pub fn show_tabs(mut self, show_tabs: bool) -> Self {
         self.show_tabs = show_tabs;
         self
     }
 
+    /// Update with the tab_repr option
+    pub fn tab_repr(mut self, tab_repr: impl Into<String>) -> Self {
+        self.tab_repr = Some(tab_repr.into());
+        self
+    }
+
+    /// Update with the expand_tabs option
+    pub fn expand_tabs(mut self, expand_tabs: Option<usize>) -> Self {
+        self.expand_tabs = expand_tabs;
+        self
+    }
+
+    /// Update with the expand_leading option
+    pub fn expand_leading(mut self, expand_leading: Option<usize>) -> Self {
+        self.expand_leading = expand_leading;
+        self
+    }
+
+    /// Update with the wrap option
+    pub fn wrap(mut self, wrap: Option<usize>) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
     /// Update with the show_nonprinting option
     pub fn show_nonprinting(mut self, show_nonprinting: bool) -> Self {
         self.show_nonprinting = show_nonprinting;
         self
     }
+
+    /// Update with the nul option
+    pub fn nul(mut self, nul: Option<NulMode>) -> Self {
+        self.nul = nul;
+        self
+    }
+
+    /// Update with the decompress option
+    #[cfg(feature = "gzip")]
+    pub fn decompress(mut self, decompress: Compression) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Update with the cr_is_eol option
+    pub fn cr_is_eol(mut self, cr_is_eol: bool) -> Self {
+        self.cr_is_eol = cr_is_eol;
+        self
+    }
+
+    /// Update with the normalize_crlf option
+    pub fn normalize_crlf(mut self, normalize_crlf: bool) -> Self {
+        self.normalize_crlf = normalize_crlf;
+        self
+    }
+
+    /// Update with the line_ending option
+    pub fn line_ending(mut self, line_ending: OutputLineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Update with the line_terminator option
+    pub fn line_terminator(mut self, line_terminator: impl Into<String>) -> Self {
+        self.line_terminator = line_terminator.into();
+        self
+    }
+
+    /// Update with the ensure_trailing_newline option
+    pub fn ensure_trailing_newline(mut self, ensure_trailing_newline: bool) -> Self {
+        self.ensure_trailing_newline = ensure_trailing_newline;
+        self
+    }
+
+    /// Update with the encoding option
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Update with the output_encoding option
+    #[cfg(feature = "encoding")]
+    pub fn output_encoding(mut self, output_encoding: OutputEncoding) -> Self {
+        self.output_encoding = output_encoding;
+        self
+    }
+
+    /// Update with the unmappable option
+    #[cfg(feature = "encoding")]
+    pub fn unmappable(mut self, unmappable: Unmappable) -> Self {
+        self.unmappable = unmappable;
+        self
+    }
+
+    /// Update with the strip_bom option
+    pub fn strip_bom(mut self, strip_bom: bool) -> Self {
+        self.strip_bom = strip_bom;
+        self
+    }
+
+    /// Update with the skip_bytes option
+    pub fn skip_bytes(mut self, skip_bytes: usize) -> Self {
+        self.skip_bytes = skip_bytes;
+        self
+    }
+
+    /// Update with the reverse_lines option
+    pub fn reverse_lines(mut self, reverse_lines: bool) -> Self {
+        self.reverse_lines = reverse_lines;
+        self
+    }
+
+    /// Update with the invalid_utf8 option
+    pub fn invalid_utf8(mut self, invalid_utf8: InvalidUtf8) -> Self {
+        self.invalid_utf8 = invalid_utf8;
+        self
+    }
+
+    /// Update with the unicode_aware option
+    pub fn unicode_aware(mut self, unicode_aware: bool) -> Self {
+        self.unicode_aware = unicode_aware;
+        self
+    }
+
+    /// Update with the strip_nonprinting option
+    pub fn strip_nonprinting(mut self, strip_nonprinting: bool) -> Self {
+        self.strip_nonprinting = strip_nonprinting;
+        self
+    }
+
+    /// Update with the trim_trailing option
+    pub fn trim_trailing(mut self, trim_trailing: bool) -> Self {
+        self.trim_trailing = trim_trailing;
+        self
+    }
+
+    /// Update with the strip_ansi option
+    pub fn strip_ansi(mut self, strip_ansi: bool) -> Self {
+        self.strip_ansi = strip_ansi;
+        self
+    }
+
+    /// Update with the interpret_escapes option
+    pub fn interpret_escapes(mut self, interpret_escapes: bool) -> Self {
+        self.interpret_escapes = interpret_escapes;
+        self
+    }
+
+    /// Update with the line_range option
+    pub fn line_range(mut self, line_range: Option<(Option<usize>, Option<usize>)>) -> Self {
+        self.line_range = line_range;
+        self
+    }
+
+    /// Update with the filter option
+    pub fn filter(mut self, filter: Option<String>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Update with the invert_filter option
+    pub fn invert_filter(mut self, invert_filter: bool) -> Self {
+        self.invert_filter = invert_filter;
+        self
+    }
+
+    /// Update with the uniq option
+    pub fn uniq(mut self, uniq: bool) -> Self {
+        self.uniq = uniq;
+        self
+    }
+
+    /// Update with the uniq_count option
+    pub fn uniq_count(mut self, uniq_count: bool) -> Self {
+        self.uniq_count = uniq_count;
+        self
+    }
+
+    /// Update with the head option
+    pub fn head(mut self, head: Option<usize>) -> Self {
+        self.head = head;
+        self
+    }
+
+    /// Update with the tail option
+    pub fn tail(mut self, tail: Option<usize>) -> Self {
+        self.tail = tail;
+        self
+    }
+
+    /// Update with the max_bytes option
+    pub fn max_bytes(mut self, max_bytes: Option<usize>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Update with the hex_dump option
+    pub fn hex_dump(mut self, hex_dump: bool) -> Self {
+        self.hex_dump = hex_dump;
+        self
+    }
+
+    /// Update with the json option
+    pub fn json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    /// Update with the color option
+    pub fn color(mut self, color: ColorChoice) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Update with the highlight option
+    #[cfg(feature = "regex")]
+    pub fn highlight(mut self, highlight: Vec<Highlight>) -> Self {
+        self.highlight = highlight;
+        self
+    }
+
+    /// Update with the flush option
+    pub fn flush(mut self, flush: FlushMode) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    /// Update with the with_filename option
+    pub fn with_filename(mut self, with_filename: bool) -> Self {
+        self.with_filename = with_filename;
+        self
+    }
+
+    /// Update with the headers option
+    pub fn headers(mut self, headers: bool) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Update with the follow option
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// Update with the binary_check option
+    pub fn binary_check(mut self, binary_check: bool) -> Self {
+        self.binary_check = binary_check;
+        self
+    }
+
+    /// Update with the progress option
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Update with the check option
+    pub fn check(mut self, check: bool) -> Self {
+        self.check = check;
+        self
+    }
+
+    /// Update with the mmap option
+    #[cfg(feature = "mmap")]
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Update with the buffer_size option
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Check for options that contradict each other, or that have no effect
+    /// without another one, instead of silently ignoring some of them
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        let conflicts = [
+            (
+                self.hex_dump,
+                "hex_dump",
+                self.number != NumberingMode::None,
+                "number",
+            ),
+            (self.hex_dump, "hex_dump", self.offsets.is_some(), "offsets"),
+            (
+                self.offsets.is_some(),
+                "offsets",
+                self.number != NumberingMode::None,
+                "number",
+            ),
+            (self.hex_dump, "hex_dump", self.show_ends, "show_ends"),
+            (self.hex_dump, "hex_dump", self.show_lf.is_some(), "show_lf"),
+            (self.hex_dump, "hex_dump", self.nul.is_some(), "nul"),
+            (self.hex_dump, "hex_dump", self.json, "json"),
+            (
+                self.hex_dump,
+                "hex_dump",
+                self.show_nonprinting,
+                "show_nonprinting",
+            ),
+            (
+                self.hex_dump,
+                "hex_dump",
+                self.reverse_lines,
+                "reverse_lines",
+            ),
+            (self.uniq, "uniq", self.squeeze_repeats, "squeeze_repeats"),
+            // Following formats the file as it grows, so nothing that needs
+            // all of the input at once can be used with it
+            (self.follow, "follow", self.reverse_lines, "reverse_lines"),
+            (self.follow, "follow", self.tail.is_some(), "tail"),
+            (self.follow, "follow", self.head.is_some(), "head"),
+            (self.follow, "follow", self.hex_dump, "hex_dump"),
+            (self.follow, "follow", self.skip_bytes != 0, "skip_bytes"),
+            (self.follow, "follow", self.cr_is_eol, "cr_is_eol"),
+            (
+                self.follow,
+                "follow",
+                self.line_ending != OutputLineEnding::Keep,
+                "line_ending",
+            ),
+            (
+                self.follow,
+                "follow",
+                self.encoding != Encoding::Utf8,
+                "encoding",
+            ),
+            (self.follow, "follow", self.strip_bom, "strip_bom"),
+            #[cfg(feature = "gzip")]
+            (
+                self.follow,
+                "follow",
+                self.decompress != Compression::None,
+                "decompress",
+            ),
+            #[cfg(feature = "encoding")]
+            (
+                self.follow,
+                "follow",
+                self.output_encoding != OutputEncoding::Utf8,
+                "output_encoding",
+            ),
+            (self.follow, "follow", self.check, "check"),
+            (self.follow, "follow", self.max_bytes.is_some(), "max_bytes"),
+        ];
+        for (set, option, other_set, other) in conflicts {
+            if set && other_set {
+                return Err(OptionsError::Conflict(option, other));
+            }
+        }
+        let requirements = [
+            (self.uniq_count, "uniq_count", self.uniq, "uniq"),
+            (
+                self.invert_filter,
+                "invert_filter",
+                self.filter.is_some(),
+                "filter",
+            ),
+            (
+                self.unicode_aware,
+                "unicode_aware",
+                self.show_nonprinting,
+                "show_nonprinting",
+            ),
+            (
+                self.invalid_utf8 != InvalidUtf8::Escape,
+                "invalid_utf8",
+                self.unicode_aware,
+                "unicode_aware",
+            ),
+        ];
+        for (set, option, needed_set, needed) in requirements {
+            if set && !needed_set {
+                return Err(OptionsError::Requires(option, needed));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Why [`Options::validate`] rejected a set of options. More reasons may be
+/// added as options are.
+#[derive(Error, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum OptionsError {
+    /// The first option can't be combined with the second
+    #[error("`{0}` can't be combined with `{1}`")]
+    Conflict(&'static str, &'static str),
+    /// The first option has no effect without the second
+    #[error("`{0}` needs `{1}`")]
+    Requires(&'static str, &'static str),
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Options {
-    pub(crate) fn tab(&self) -> &'static str {
+    pub(crate) fn tab(&self) -> Cow<'_, str> {
         if self.show_tabs {
-            "^I"
+            let tab = self.tab_repr.as_deref().unwrap_or("^I");
+            if self.use_color() {
+                Cow::Owned(format!("{MARKER_COLOR}{tab}{RESET_COLOR}"))
+            } else {
+                Cow::Borrowed(tab)
+            }
         } else {
-            "\t"
+            Cow::Borrowed("\t")
         }
     }
 
-    pub(crate) fn end_of_line(&self) -> &'static str {
-        if self.show_ends {
-            "$\n"
+    /// What NUL bytes are written as when `show_nonprinting` is off, unless
+    /// they are passed through unchanged
+    pub(crate) fn nul_repr(&self) -> Option<Cow<'_, str>> {
+        if self.show_nonprinting {
+            return None;
+        }
+        match self.nul? {
+            NulMode::Keep => None,
+            NulMode::Caret if self.use_color() => {
+                Some(Cow::Owned(format!("{MARKER_COLOR}^@{RESET_COLOR}")))
+            }
+            NulMode::Caret => Some(Cow::Borrowed("^@")),
+            NulMode::Replace(c) => Some(Cow::Owned(c.to_string())),
+        }
+    }
+
+    /// Whether to color line numbers and markers
+    pub(crate) fn use_color(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => *AUTO_COLOR,
+        }
+    }
+
+    /// Whether there are `highlight` patterns to color
+    pub(crate) fn highlighting(&self) -> bool {
+        #[cfg(feature = "regex")]
+        if !self.highlight.is_empty() {
+            return self.use_color();
+        }
+        false
+    }
+
+    /// The read buffer size to use, falling back to `default` when unset
+    pub(crate) fn buffer_size_or(&self, default: usize) -> usize {
+        if self.buffer_size == 0 {
+            default
         } else {
-            "\n"
+            self.buffer_size
+        }
+    }
+
+    /// Whether input line number `line` is selected by `line_range`
+    pub(crate) fn in_line_range(&self, line: usize) -> bool {
+        match self.line_range {
+            Some((start, end)) => {
+                start.is_none_or(|start| line >= start) && end.is_none_or(|end| line <= end)
+            }
+            None => true,
+        }
+    }
+
+    /// Whether the input has to be formatted a whole line at a time
+    pub(crate) fn splits_lines(&self) -> bool {
+        self.filter.is_some()
+            || self.uniq
+            || self.squeeze_repeats
+            || self.trim_trailing
+            || self.json
+            || self.offsets.is_some()
+            || self.highlighting()
+    }
+
+    /// Whether an input line, without its line feed, passes `filter`
+    pub(crate) fn matches_filter(&self, line: &[u8]) -> bool {
+        let Some(filter) = &self.filter else {
+            return true;
+        };
+        let filter = filter.as_bytes();
+        let found = filter.is_empty() || line.windows(filter.len()).any(|w| w == filter);
+        found != self.invert_filter
+    }
+
+    /// Whether input line number `line` and all the lines after it are past the
+    /// end of `line_range`
+    pub(crate) fn past_line_range(&self, line: usize) -> bool {
+        matches!(self.line_range, Some((_, Some(end))) if line > end)
+    }
+
+    /// What ends an output line, without the `show_ends` marker
+    pub(crate) fn newline(&self) -> Cow<'_, str> {
+        match self.line_ending {
+            OutputLineEnding::CrLf => Cow::Owned(format!("\r{}", self.line_terminator)),
+            OutputLineEnding::Keep | OutputLineEnding::Lf => Cow::Borrowed(&self.line_terminator),
+        }
+    }
+
+    /// The last byte of each output line
+    pub(crate) fn line_end(&self) -> u8 {
+        *self.newline().as_bytes().last().unwrap_or(&b'\n')
+    }
+
+    pub(crate) fn end_of_line(&self) -> Cow<'_, str> {
+        self.end_of_line_with("")
+    }
+
+    /// Like `end_of_line`, for a `\r\n` kept whole by `normalize_crlf`
+    pub(crate) fn crlf_end_of_line(&self) -> Cow<'_, str> {
+        self.end_of_line_with("\r")
+    }
+
+    /// The end of an output line, with `cr` between the markers and the line
+    /// terminator
+    fn end_of_line_with(&self, cr: &str) -> Cow<'_, str> {
+        let newline = match self.show_lf {
+            Some(ShowLf::Inline) => Cow::Borrowed(""),
+            Some(ShowLf::Break) | None => self.newline(),
+        };
+        let newline = if cr.is_empty() {
+            newline
+        } else {
+            Cow::Owned(format!("{cr}{newline}"))
+        };
+        let marker = match (self.show_ends, &self.end_marker) {
+            (true, marker) => marker.as_deref().unwrap_or("$"),
+            (false, _) => "",
+        };
+        let lf = if self.show_lf.is_some() { "\\n" } else { "" };
+        if marker.is_empty() && lf.is_empty() {
+            newline
+        } else if self.use_color() {
+            Cow::Owned(format!("{MARKER_COLOR}{marker}{lf}{RESET_COLOR}{newline}"))
+        } else {
+            Cow::Owned(format!("{marker}{lf}{newline}"))
+        }
+    }
+
+    /// Whether the input can be formatted one buffer at a time
+    pub(crate) fn can_stream(&self) -> bool {
+        #[cfg(feature = "gzip")]
+        if self.decompress != Compression::None {
+            return false;
+        }
+        self.can_stream_decompressed()
+    }
+
+    /// Whether the input can be formatted one buffer at a time once it is
+    /// decompressed
+    pub(crate) fn can_stream_decompressed(&self) -> bool {
+        #[cfg(feature = "encoding")]
+        if self.output_encoding != OutputEncoding::Utf8 {
+            return false;
         }
+        self.encoding == Encoding::Utf8
+            && !self.strip_bom
+            && self.skip_bytes == 0
+            && !self.cr_is_eol
+            && self.line_ending == OutputLineEnding::Keep
+            && !self.hex_dump
+            && !self.reverse_lines
+            && self.head.is_none()
+            && self.tail.is_none()
     }
 
     /// We can write fast if we can simply copy the contents of the file to
     /// stdout, without augmenting the output with e.g. line numbers.
     pub(crate) fn can_write_fast(&self) -> bool {
         !(self.show_tabs
+            || self.expand_tabs.is_some()
+            || self.expand_leading.is_some()
+            || self.wrap.is_some()
             || self.show_nonprinting
+            || self.nul_repr().is_some()
+            || self.strip_nonprinting
+            || self.strip_ansi
+            || self.trim_trailing
+            || self.interpret_escapes
             || self.show_ends
+            || self.show_lf.is_some()
             || self.squeeze_blank
-            || self.number != NumberingMode::None)
+            || self.squeeze_repeats
+            || self.reverse_lines
+            || self.line_range.is_some()
+            || self.filter.is_some()
+            || self.uniq
+            || self.head.is_some()
+            || self.tail.is_some()
+            || self.hex_dump
+            || self.json
+            || self.highlighting()
+            || self.line_ending == OutputLineEnding::CrLf
+            || self.line_terminator != "\n"
+            || self.flush == FlushMode::Line
+            || self.number != NumberingMode::None
+            || self.offsets.is_some())
     }
 }
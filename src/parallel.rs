@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+
+use crate::cat_checked;
+use crate::cat_file;
+use crate::cat_files_to;
+use crate::is_stdin;
+use crate::open_file;
+use crate::write_header;
+use crate::CatFilesError;
+use crate::CatStats;
+use crate::FlushMode;
+use crate::NumberingMode;
+use crate::Options;
+
+/// The formatted output of a file and the result of reading it, or the error
+/// opening it
+type Formatted = Result<(Vec<u8>, Result<CatStats, CatFilesError>), CatFilesError>;
+
+/// Like [`cat_files`](crate::cat_files), but format up to `threads` files at
+/// once. A `threads` of 0 uses the available parallelism.
+pub fn cat_files_parallel<T: AsRef<Path> + Sync>(
+    files: &[T],
+    options: &Options,
+    threads: usize,
+) -> Result<CatStats, CatFilesError> {
+    let mut stdout = BufWriter::new(std::io::stdout().lock());
+    // Flush even if some files failed, so their output precedes the errors
+    let result = cat_files_parallel_to(files, &mut stdout, options, threads);
+    stdout.flush()?;
+
+    result
+}
+
+/// Like [`cat_files_to`](crate::cat_files_to), but format up to `threads` files
+/// at once, each into its own buffer, and write the buffers to `output` in the
+/// order of `files`. A `threads` of 0 uses the available parallelism.
+///
+/// Files that finish before the ones ahead of them are held in memory until
+/// their turn, so this trades memory for speed. Each file is formatted on its
+/// own, so blank lines are not squeezed across files and a last line without a
+/// line feed does not run into the next file. Numbered lines are formatted one
+/// file at a time, as their numbers carry on from file to file, unless
+/// `number_reset_per_file` is set, and so are files with `max_bytes` set, as
+/// it limits the output of all of them together.
+pub fn cat_files_parallel_to<W: Write, T: AsRef<Path> + Sync>(
+    files: &[T],
+    output: &mut W,
+    options: &Options,
+    threads: usize,
+) -> Result<CatStats, CatFilesError> {
+    // Each file's line numbers depend on the lines numbered before it
+    if options.number != NumberingMode::None && !options.number_reset_per_file {
+        return cat_files_to(files, output, options);
+    }
+    // The files share one budget of output
+    if options.max_bytes.is_some() {
+        return cat_files_to(files, output, options);
+    }
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(files.len());
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next, stop) = (&next, &stop);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(file) = files.get(index) else {
+                        break;
+                    };
+                    if sender
+                        .send((index, format_file(file.as_ref(), index == 0, options)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let result = write_in_order(files.len(), receiver, output, options);
+        stop.store(true, Ordering::Relaxed);
+        result
+    })
+}
+
+/// Write each file's output as soon as all the files before it are written
+fn write_in_order<W: Write>(
+    count: usize,
+    receiver: mpsc::Receiver<(usize, Formatted)>,
+    output: &mut W,
+    options: &Options,
+) -> Result<CatStats, CatFilesError> {
+    let mut stats = CatStats::default();
+    let mut errors = Vec::new();
+    let mut pending = HashMap::new();
+    for index in 0..count {
+        let formatted = loop {
+            if let Some(formatted) = pending.remove(&index) {
+                break formatted;
+            }
+            // Workers only stop early once this function has returned
+            let (done, formatted) = receiver.recv().expect("worker stopped early");
+            pending.insert(done, formatted);
+        };
+        match formatted {
+            Ok((buf, result)) => {
+                output.write_all(&buf)?;
+                // Lines are only written once their whole file is formatted
+                if options.flush == FlushMode::Line {
+                    output.flush()?;
+                }
+                match result {
+                    Ok(file_stats) => stats += file_stats,
+                    Err(e @ (CatFilesError::Binary(_) | CatFilesError::Io { .. })) => {
+                        errors.push(e)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(stats)
+    } else {
+        Err(CatFilesError::Failed { errors, stats })
+    }
+}
+
+fn format_file(file: &Path, first: bool, options: &Options) -> Formatted {
+    let mut opened = if is_stdin(file) {
+        None
+    } else {
+        Some(open_file(file)?)
+    };
+    let mut buf = Vec::new();
+    if options.check {
+        return Ok((buf, Ok(CatStats::default())));
+    }
+    let header = if options.headers {
+        write_header(&mut buf, file, first)?
+    } else {
+        0
+    };
+    let result = match &mut opened {
+        Some(opened) => cat_file(file, opened, &mut buf, options, None),
+        None => cat_checked(
+            file,
+            &mut std::io::stdin().lock(),
+            &mut buf,
+            options,
+            None,
+            None,
+        ),
+    }
+    .map(|mut stats| {
+        stats.bytes_out += header;
+        stats
+    });
+
+    Ok((buf, result))
+}
@@ -0,0 +1,54 @@
+use std::io::Write;
+
+/// A writer that starts every line written through it with a prefix, such as
+/// the name of the file the line came from
+pub(crate) struct PrefixWriter<'a, W: Write> {
+    inner: &'a mut W,
+    prefix: &'a [u8],
+
+    /// The last byte of each line
+    terminator: u8,
+
+    /// Whether the next byte written starts a line
+    at_line_start: bool,
+
+    /// The number of prefix bytes written
+    pub(crate) count: usize,
+}
+
+impl<'a, W: Write> PrefixWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W, prefix: &'a [u8], terminator: u8) -> Self {
+        Self {
+            inner,
+            prefix,
+            terminator,
+            at_line_start: true,
+            count: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for PrefixWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.at_line_start {
+            self.inner.write_all(self.prefix)?;
+            self.count += self.prefix.len();
+            self.at_line_start = false;
+        }
+        // Stop after the end of the line, so the next line gets its prefix
+        let end = buf
+            .iter()
+            .position(|c| *c == self.terminator)
+            .map_or(buf.len(), |p| p + 1);
+        let n = self.inner.write(&buf[..end])?;
+        self.at_line_start = n > 0 && buf[n - 1] == self.terminator;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,70 @@
+use std::io::Read;
+use std::io::Write;
+
+/// How many bytes are read between updates of the progress line
+const PROGRESS_INTERVAL: u64 = 4 << 20;
+
+/// A reader that keeps a progress line on `status` up to date, for
+/// `progress`. Each update starts with a carriage return to overwrite the last
+/// one, and the line is cleared when the reader is dropped.
+pub(crate) struct ProgressReader<R, E: Write> {
+    inner: R,
+    status: E,
+
+    /// The name of the input shown on the line
+    name: String,
+
+    /// The size of the input, if it is a regular file
+    size: Option<u64>,
+
+    /// The number of bytes read so far
+    read: u64,
+
+    /// `read` at the last update
+    reported: u64,
+}
+
+impl<R: Read, E: Write> ProgressReader<R, E> {
+    pub(crate) fn new(inner: R, status: E, name: String, size: Option<u64>) -> Self {
+        Self {
+            inner,
+            status,
+            name,
+            size,
+            read: 0,
+            reported: 0,
+        }
+    }
+
+    /// Write the progress line. It is only for show, so errors are ignored.
+    fn report(&mut self) {
+        let mut line = format!("\r{}: {} bytes", self.name, self.read);
+        if let Some(size) = self.size.filter(|size| *size > 0) {
+            line += &format!(" ({}%)", (self.read * 100 / size).min(100));
+        }
+        let _ = self.status.write_all(line.as_bytes());
+        let _ = self.status.flush();
+    }
+}
+
+impl<R: Read, E: Write> Read for ProgressReader<R, E> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        if self.read - self.reported >= PROGRESS_INTERVAL {
+            self.reported = self.read;
+            self.report();
+        }
+        Ok(n)
+    }
+}
+
+impl<R, E: Write> Drop for ProgressReader<R, E> {
+    fn drop(&mut self) {
+        if self.reported > 0 {
+            // Back to the start of the line, and erase it
+            let _ = self.status.write_all(b"\r\x1b[K");
+            let _ = self.status.flush();
+        }
+    }
+}
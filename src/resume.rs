@@ -0,0 +1,236 @@
+use std::cell::Cell;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+
+use crate::cat_buffer;
+use crate::finish;
+#[cfg(feature = "gzip")]
+use crate::gzip;
+use crate::limit::LimitReader;
+use crate::limit::LimitState;
+use crate::limit::LimitWriter;
+use crate::stats::CountingWriter;
+use crate::write_end_of_line;
+use crate::CatResult;
+use crate::CatStats;
+#[cfg(feature = "gzip")]
+use crate::Compression;
+use crate::Options;
+use crate::State;
+use crate::LINES_BUFFER_SIZE;
+
+/// What formatting carries over from one input to the next, for
+/// [`cat_with_state`]: the next line number, the run of blank lines for
+/// `squeeze_blank`, a carriage return held back at the end of the last input,
+/// any line or character it cut off, and what is left of `max_bytes`.
+pub struct CatState {
+    pub(crate) state: State,
+    limit: Option<LimitState>,
+}
+
+impl CatState {
+    /// Create the state for the start of the first input, formatted with
+    /// `options`. The state keeps what it works out from `options`, such as
+    /// the escapes for `show_nonprinting`, so every input given to
+    /// [`cat_with_state`] with it must use the same options.
+    pub fn new(options: &Options) -> Self {
+        Self {
+            state: State::new(options),
+            limit: options.max_bytes.map(LimitState::new),
+        }
+    }
+
+    /// The state for the files of [`cat_files`](crate::cat_files), which
+    /// applies `max_bytes` to all of them itself
+    pub(crate) fn for_files(options: &Options) -> Self {
+        Self {
+            state: State::new(options),
+            limit: None,
+        }
+    }
+
+    /// Start on the next of several files. Line ranges and offsets are of
+    /// each file, and so is numbering with `number_reset_per_file`.
+    pub(crate) fn start_file(&mut self, options: &Options) {
+        self.state.input_line = 1;
+        self.state.input_offset = 0;
+        self.state.skipped_content = false;
+        if options.number_reset_per_file {
+            self.state.line_number = options.number_start;
+        }
+    }
+
+    /// The number the next numbered line will get
+    pub fn line_number(&self) -> usize {
+        self.state.line_number
+    }
+
+    /// Write out anything still held back once all the input has been given
+    /// to [`cat_with_state`], such as a last line without a line feed that
+    /// `filter` has to see whole
+    pub fn finish<W: Write>(&mut self, output: &mut W, options: &Options) -> CatResult<()> {
+        let Some(limit) = self.limit.take() else {
+            return finish(output, options, &mut self.state);
+        };
+        let full = Cell::new(false);
+        let mut limited = LimitWriter::resume(output, limit, options.line_end(), &full);
+        finish(&mut limited, options, &mut self.state)?;
+        limited.finish()?;
+
+        Ok(())
+    }
+
+    /// Like [`finish`](Self::finish), returning what it wrote, along with a
+    /// last line without a line feed
+    pub(crate) fn finish_counted<W: Write>(
+        &mut self,
+        output: &mut W,
+        options: &Options,
+    ) -> CatResult<CatStats> {
+        let state = &self.state;
+        let (lines, blanks_squeezed, numbered) =
+            (state.lines, state.blanks_squeezed, state.numbered);
+        let mut output = CountingWriter::new(output);
+        self.finish(&mut output, options)?;
+        let state = &self.state;
+        let mut stats = CatStats {
+            bytes_out: output.count,
+            lines: state.lines - lines,
+            blanks_squeezed: state.blanks_squeezed - blanks_squeezed,
+            numbered: state.numbered - numbered,
+            ..CatStats::default()
+        };
+        if !state.at_line_start {
+            stats.lines += 1;
+        }
+
+        Ok(stats)
+    }
+
+    /// Like [`finish_counted`](Self::finish_counted), also ending a last line
+    /// without a line feed, so that what comes next starts a line of its own
+    pub(crate) fn end_line<W: Write>(
+        &mut self,
+        output: &mut W,
+        options: &Options,
+    ) -> CatResult<CatStats> {
+        let mut stats = self.finish_counted(output, options)?;
+        if !self.state.at_line_start {
+            let end_of_line = options.end_of_line();
+            write_end_of_line(output, end_of_line.as_bytes())?;
+            stats.bytes_out += end_of_line.len();
+            self.state.lines += 1;
+            self.state.at_line_start = true;
+        }
+
+        Ok(stats)
+    }
+}
+
+/// Like [`cat_counted`](crate::cat_counted), but carry on from where the last
+/// input formatted with `state` left off, as if it and `input` were one. Call
+/// [`CatState::finish`] after the last input. `max_bytes` is the total for all
+/// of the inputs. Each input is decompressed on its own.
+///
+/// The other options that [`CatWriter`](crate::CatWriter) holds back for,
+/// because they need to see all of the input at once, such as `head` or
+/// `skip_bytes`, can't be carried on across inputs and give an error of kind
+/// [`InvalidInput`](ErrorKind::InvalidInput).
+pub fn cat_with_state<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+    state: &mut CatState,
+) -> CatResult<CatStats> {
+    if !options.can_stream_decompressed() {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "options that need all of the input at once can't carry on across inputs",
+        )
+        .into());
+    }
+    #[cfg(feature = "gzip")]
+    if options.decompress != Compression::None {
+        let mut input = gzip::decompressed(input, options.decompress)?;
+        return cat_decompressed_with_state(&mut input, output, options, state);
+    }
+    cat_decompressed_with_state(input, output, options, state)
+}
+
+/// Like [`cat_with_state`], for input that is already decompressed
+fn cat_decompressed_with_state<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+    state: &mut CatState,
+) -> CatResult<CatStats> {
+    let Some(limit) = state.limit.take() else {
+        return cat_resumed(input, output, options, &mut state.state);
+    };
+    let full = Cell::new(false);
+    let mut limited = LimitWriter::resume(output, limit, options.line_end(), &full);
+    if full.get() {
+        // Nothing more fits, so the input only shows whether output is dropped
+        state.limit = Some(limited.suspend().1);
+        return Ok(CatStats {
+            truncated: has_more(input)?,
+            ..CatStats::default()
+        });
+    }
+    let mut input = LimitReader {
+        inner: input,
+        full: &full,
+    };
+    let result = cat_resumed(&mut input, &mut limited, options, &mut state.state);
+    let truncated = limited.truncated;
+    let (written, limit) = limited.suspend();
+    state.limit = Some(limit);
+    let mut stats = result?;
+    stats.bytes_out = written;
+    stats.truncated = truncated;
+
+    Ok(stats)
+}
+
+/// Whether any input is left to read
+fn has_more<R: Read>(input: &mut R) -> std::io::Result<bool> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(n) => return Ok(n > 0),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Format `input` with `state`, counting what was read and written for it
+fn cat_resumed<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &Options,
+    state: &mut State,
+) -> CatResult<CatStats> {
+    let (lines, blanks_squeezed, numbered) = (state.lines, state.blanks_squeezed, state.numbered);
+    let mut output = CountingWriter::new(output);
+    let mut stats = CatStats::default();
+    let mut inbuf = vec![0; options.buffer_size_or(LINES_BUFFER_SIZE)];
+    // The rest of the input is dropped once past the line range
+    while !options.past_line_range(state.input_line) {
+        let n = match input.read(&mut inbuf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        cat_buffer(&inbuf[..n], &mut output, options, state)?;
+        stats.bytes_in += n;
+    }
+    stats.bytes_out = output.count;
+    stats.lines = state.lines - lines;
+    stats.blanks_squeezed = state.blanks_squeezed - blanks_squeezed;
+    stats.numbered = state.numbered - numbered;
+
+    Ok(stats)
+}
@@ -0,0 +1,127 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use crate::cat_files_to;
+use crate::CatFilesError;
+use crate::CatStats;
+use crate::Options;
+
+/// Concatenate `files` like [`cat_files`](crate::cat_files), but into chunk
+/// files of `lines` output lines each instead of standard output, as
+/// [`SplitWriter`] does. Line numbers carry on from one chunk to the next.
+pub fn cat_files_split<T: AsRef<Path>>(
+    files: &[T],
+    prefix: impl AsRef<Path>,
+    lines: usize,
+    options: &Options,
+) -> Result<CatStats, CatFilesError> {
+    let mut output = SplitWriter::new(prefix, lines, options);
+    // Flush even if some files failed, so their output is kept
+    let result = cat_files_to(files, &mut output, options);
+    output.flush()?;
+
+    result
+}
+
+/// A writer that spreads the output over files named `prefix` followed by a
+/// number counting from `00`, like `split`, starting a new file after every
+/// `lines` lines. A file is only created once there is something to write to
+/// it. The numbers widen as `split` does theirs, so the names still sort in
+/// order: `89` is followed by `9000`, and `9899` by `990000`.
+pub struct SplitWriter {
+    prefix: OsString,
+    lines: usize,
+
+    /// The last byte of each line
+    terminator: u8,
+
+    /// The file being written, if it has been created
+    chunk: Option<BufWriter<File>>,
+
+    /// The number of files created
+    chunks: usize,
+
+    /// The number of lines written to `chunk`
+    written: usize,
+}
+
+impl SplitWriter {
+    /// Create a writer for lines ended as `options` ends them. A `lines` of 0
+    /// is taken as 1.
+    pub fn new(prefix: impl AsRef<Path>, lines: usize, options: &Options) -> Self {
+        Self {
+            prefix: prefix.as_ref().as_os_str().to_owned(),
+            lines: lines.max(1),
+            terminator: options.line_end(),
+            chunk: None,
+            chunks: 0,
+            written: 0,
+        }
+    }
+
+    /// The file being written, creating the next one if needed
+    fn chunk(&mut self) -> std::io::Result<&mut BufWriter<File>> {
+        if self.chunk.is_none() {
+            let mut name = self.prefix.clone();
+            name.push(chunk_suffix(self.chunks));
+            self.chunk = Some(BufWriter::new(File::create(name)?));
+            self.chunks += 1;
+            self.written = 0;
+        }
+        Ok(self.chunk.as_mut().unwrap())
+    }
+}
+
+/// The number ending the name of chunk `n`: `00` to `89`, then `9000` to
+/// `9899`, then `990000` to `998999` and so on, each step adding a `9` to the
+/// front and a digit to the number after it
+pub(crate) fn chunk_suffix(mut n: usize) -> String {
+    let mut nines = 0;
+    loop {
+        let digits = nines + 2;
+        // The numbers of `digits` digits that don't start with 9
+        let count = 9 * 10_usize.pow(digits as u32 - 1);
+        if n < count {
+            return format!("{}{n:0digits$}", "9".repeat(nines));
+        }
+        n -= count;
+        nines += 1;
+    }
+}
+
+impl Write for SplitWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            // Up to the end of the last line that fits in this file
+            let remaining = self.lines - self.written;
+            let mut lines = 0;
+            let len = rest
+                .iter()
+                .position(|c| {
+                    lines += usize::from(*c == self.terminator);
+                    lines == remaining
+                })
+                .map_or(rest.len(), |p| p + 1);
+            self.chunk()?.write_all(&rest[..len])?;
+            self.written += lines;
+            if self.written == self.lines {
+                if let Some(mut chunk) = self.chunk.take() {
+                    chunk.flush()?;
+                }
+            }
+            rest = &rest[len..];
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.chunk {
+            Some(chunk) => chunk.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::IoSlice;
+use std::io::Write;
+use std::ops::AddAssign;
+
+/// Byte and line counts gathered while catting
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy)]
+pub struct CatStats {
+    /// Number of bytes read from the input
+    pub bytes_in: usize,
+
+    /// Number of bytes written to the output
+    pub bytes_out: usize,
+
+    /// Number of lines written to the output, including a final line without
+    /// a trailing newline
+    pub lines: usize,
+
+    /// Number of blank lines dropped by `squeeze_blank`
+    pub blanks_squeezed: usize,
+
+    /// Number of lines given a line number, which with
+    /// [`NumberingMode::NonEmpty`](crate::NumberingMode::NonEmpty) is the
+    /// number of non-blank lines
+    pub numbered: usize,
+
+    /// Whether output was dropped to keep within
+    /// [`max_bytes`](crate::Options::max_bytes)
+    pub truncated: bool,
+}
+
+impl AddAssign for CatStats {
+    fn add_assign(&mut self, other: Self) {
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+        self.lines += other.lines;
+        self.blanks_squeezed += other.blanks_squeezed;
+        self.numbered += other.numbered;
+        self.truncated |= other.truncated;
+    }
+}
+
+/// A writer that counts the bytes successfully written through it
+pub(crate) struct CountingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    pub(crate) count: usize,
+}
+
+impl<'a, W: Write> CountingWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n;
+        Ok(n)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let n = self.inner.write_vectored(bufs)?;
+        self.count += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A writer that keeps track of whether the output written through it, and
+/// before it, ends in the middle of a line
+pub(crate) struct LineEndWriter<'a, W: Write> {
+    inner: &'a mut W,
+
+    /// The last byte of each line
+    terminator: u8,
+
+    /// Whether the last byte written did not end a line
+    pub(crate) mid_line: bool,
+}
+
+impl<'a, W: Write> LineEndWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W, terminator: u8, mid_line: bool) -> Self {
+        Self {
+            inner,
+            terminator,
+            mid_line,
+        }
+    }
+}
+
+impl<W: Write> Write for LineEndWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(last) = buf[..n].last() {
+            self.mid_line = *last != self.terminator;
+        }
+        Ok(n)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        let mut n = self.inner.write_vectored(bufs)?;
+        let written = n;
+        for buf in bufs {
+            if n == 0 {
+                break;
+            }
+            let part = &buf[..n.min(buf.len())];
+            if let Some(last) = part.last() {
+                self.mid_line = *last != self.terminator;
+            }
+            n -= part.len();
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
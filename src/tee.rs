@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use crate::cat_files_to;
+use crate::CatFilesError;
+use crate::CatStats;
+use crate::Options;
+
+/// Concatenate `files` like [`cat_files`](crate::cat_files), writing the output
+/// both to standard output and to the file at `path`, as `tee` does
+pub fn cat_files_tee<T: AsRef<Path>>(
+    files: &[T],
+    path: impl AsRef<Path>,
+    options: &Options,
+) -> Result<CatStats, CatFilesError> {
+    let path = path.as_ref();
+    let copy = File::create(path).map_err(|source| CatFilesError::Io {
+        path: path.to_string_lossy().into_owned(),
+        source,
+    })?;
+    let mut output = TeeWriter::new(
+        BufWriter::new(std::io::stdout().lock()),
+        BufWriter::new(copy),
+    );
+    // Flush even if some files failed, so their output precedes the errors
+    let result = cat_files_to(files, &mut output, options);
+    output.flush()?;
+
+    result
+}
+
+/// A writer that writes everything to both `first` and `second`. The first
+/// error from either stops the write and is returned.
+pub struct TeeWriter<W1: Write, W2: Write> {
+    first: W1,
+    second: W2,
+}
+
+impl<W1: Write, W2: Write> TeeWriter<W1, W2> {
+    /// Create a writer that passes everything on to `first`, then `second`
+    pub fn new(first: W1, second: W2) -> Self {
+        Self { first, second }
+    }
+
+    /// Return the underlying writers
+    pub fn into_inner(self) -> (W1, W2) {
+        (self.first, self.second)
+    }
+}
+
+impl<W1: Write, W2: Write> Write for TeeWriter<W1, W2> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // All of `buf` goes to both, so neither gets ahead of the other
+        self.first.write_all(buf)?;
+        self.second.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.first.flush()?;
+        self.second.flush()
+    }
+}
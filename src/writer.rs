@@ -0,0 +1,135 @@
+use std::io::Write;
+
+use crate::cat;
+use crate::cat_buffer;
+use crate::finish;
+use crate::limit::LimitState;
+use crate::CatError;
+use crate::CatResult;
+use crate::Options;
+use crate::State;
+
+/// A writer that formats everything written through it before passing it on.
+///
+/// Input is formatted as it is written, except with options that need to see
+/// more than one buffer at a time (a non-UTF-8 `encoding`, `strip_bom`,
+/// `skip_bytes`, `cr_is_eol`, `line_ending`, `decompress`, `output_encoding`,
+/// `hex_dump`, `reverse_lines`, `head` and `tail`). With those, it is held back
+/// until [`finish`](CatWriter::finish). `max_bytes` limits everything written
+/// through it together.
+///
+/// The end of a line may depend on bytes not yet written, so call `finish`
+/// once all the input is written. Dropping the writer finishes it, ignoring
+/// any errors.
+pub struct CatWriter<'a, W: Write> {
+    inner: Option<W>,
+    options: &'a Options,
+    pub(crate) state: State,
+
+    /// All the input so far, when it cannot be formatted as it comes
+    held: Option<Vec<u8>>,
+
+    /// Formatted output waiting to be written to `inner`
+    outbuf: Vec<u8>,
+
+    /// How much more may be written for `max_bytes`, when formatting as it
+    /// comes
+    limit: Option<LimitState>,
+}
+
+impl<'a, W: Write> CatWriter<'a, W> {
+    pub(crate) fn new(inner: W, options: &'a Options) -> Self {
+        Self {
+            inner: Some(inner),
+            options,
+            state: State::new(options),
+            held: (!options.can_stream()).then(Vec::new),
+            outbuf: Vec::new(),
+            limit: options.max_bytes.map(LimitState::new),
+        }
+    }
+
+    /// Write out anything held back and return the underlying writer
+    pub fn finish(mut self) -> CatResult<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().expect("writer is only taken by finish"))
+    }
+
+    fn try_finish(&mut self) -> CatResult<()> {
+        let inner = self.inner.as_mut().expect("writer is only taken by finish");
+        match self.held.take() {
+            Some(held) => cat(&mut held.as_slice(), inner, self.options)?,
+            None => {
+                finish(&mut self.outbuf, self.options, &mut self.state)?;
+                write_limited(inner, &mut self.limit, self.options, &self.outbuf)?;
+                self.outbuf.clear();
+                if let Some(limit) = self.limit.take() {
+                    limit.finish(inner, self.options.line_end())?;
+                }
+            }
+        }
+        inner.flush()?;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for CatWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(held) = &mut self.held {
+            held.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        let inner = self.inner.as_mut().expect("writer is only taken by finish");
+        if self.options.can_write_fast() {
+            write_limited(inner, &mut self.limit, self.options, buf)?;
+            return Ok(buf.len());
+        }
+        // The rest of the input is dropped once past the line range or once
+        // the output is full
+        let full = self.limit.as_ref().is_some_and(LimitState::is_full);
+        if !full && !self.options.past_line_range(self.state.input_line) {
+            cat_buffer(buf, &mut self.outbuf, self.options, &mut self.state)
+                .map_err(|CatError::Io(e)| e)?;
+            write_limited(inner, &mut self.limit, self.options, &self.outbuf)?;
+            self.outbuf.clear();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner
+            .as_mut()
+            .expect("writer is only taken by finish")
+            .flush()
+    }
+}
+
+/// Write `bytes` to `inner`, keeping within `max_bytes` if there is a `limit`
+fn write_limited<W: Write>(
+    inner: &mut W,
+    limit: &mut Option<LimitState>,
+    options: &Options,
+    bytes: &[u8],
+) -> std::io::Result<()> {
+    match limit {
+        Some(limit) => limit.write(inner, options.line_end(), bytes),
+        None => inner.write_all(bytes),
+    }
+}
+
+impl<W: Write> Drop for CatWriter<'_, W> {
+    fn drop(&mut self) {
+        // Already finished if the writer was taken
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+impl Options {
+    /// Wrap `output` in a writer that formats everything written through it
+    pub fn writer<W: Write>(&self, output: W) -> CatWriter<'_, W> {
+        CatWriter::new(output, self)
+    }
+}
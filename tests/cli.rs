@@ -0,0 +1,503 @@
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+const BIN: &str = env!("CARGO_BIN_EXE_carboncopycat");
+
+/// Write `contents` to a fresh file in the temp directory and return its path
+fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ccc-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_broken_pipe_exits_quietly() {
+    let path = temp_file("broken-pipe", &b"some line of text\n".repeat(1 << 20));
+    let mut child = Command::new(BIN)
+        .arg("-n")
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut buf = [0; 16];
+    child.stdout.take().unwrap().read_exact(&mut buf).unwrap();
+    // Dropping stdout closes the read end of the pipe
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    // Even when only the count is left to print
+    let mut child = Command::new(BIN)
+        .arg("--count")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    child.stdin.take().unwrap().write_all(b"a\nb\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_color() {
+    // Redirected output is plain unless color is forced
+    let output = Command::new(BIN).arg("--help").output().unwrap();
+    assert!(output.status.success());
+    assert!(!output.stdout.contains(&0x1b));
+
+    let output = Command::new(BIN).arg("--bogus").output().unwrap();
+    assert!(!output.status.success());
+    assert!(!output.stderr.is_empty());
+    assert!(!output.stderr.contains(&0x1b));
+
+    let output = Command::new(BIN)
+        .args(["--color=always", "--help"])
+        .output()
+        .unwrap();
+    assert!(output.stdout.contains(&0x1b));
+
+    let path = temp_file("color", b"a\n");
+    let output = Command::new(BIN)
+        .args(["--color", "-n"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.stdout, b"\x1b[32m     1\x1b[0m\ta\n");
+
+    let output = Command::new(BIN)
+        .args(["--color=sometimes"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_short_help_and_version() {
+    let output = Command::new(BIN).arg("-V").output().unwrap();
+    assert!(output.status.success());
+    let version = String::from_utf8(output.stdout).unwrap();
+    assert!(version.ends_with(&format!("v{}\n", env!("CARGO_PKG_VERSION"))));
+
+    // -h inside a bundle still shows help
+    let output = Command::new(BIN).arg("-nh").output().unwrap();
+    assert!(output.status.success());
+    let long = Command::new(BIN).arg("--help").output().unwrap();
+    assert_eq!(output.stdout, long.stdout);
+}
+
+#[test]
+fn test_number_start() {
+    let path = temp_file("number-start", b"a\n\nb\n");
+    let output = Command::new(BIN)
+        .args(["--number-start", "100", "-n"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"   100\ta\n   101\t\n   102\tb\n");
+
+    let output = Command::new(BIN)
+        .args(["-b", "--number-start=100"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.stdout, b"   100\ta\n\n   101\tb\n");
+}
+
+#[test]
+fn test_abbreviated_long_options() {
+    let path = temp_file("abbreviated", b"a\tb\n");
+    let output = Command::new(BIN)
+        .args(["--show-t", "--number-n"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"     1\ta^Ib\n");
+
+    // An exact match wins over the longer options it is a prefix of
+    let output = Command::new(BIN)
+        .args(["--number"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.stdout, b"     1\ta\tb\n");
+
+    let output = Command::new(BIN).arg("--show").output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("ambiguous"));
+    for option in [
+        "--show-all",
+        "--show-ends",
+        "--show-tabs",
+        "--show-nonprinting",
+    ] {
+        assert!(stderr.contains(option));
+    }
+
+    let output = Command::new(BIN).arg("--sho-a").output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("invalid option"));
+}
+
+#[test]
+fn test_conflicting_options() {
+    let output = Command::new(BIN)
+        .args(["--follow", "--tail", "1"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("--follow can't be combined with --tail"),
+        "{stderr}"
+    );
+
+    // Named after the option that was given, not the one it turns on
+    let output = Command::new(BIN).arg("--invalid=skip").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("--invalid needs --show-nonprinting"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_end_of_options() {
+    let dir = std::env::temp_dir().join(format!("ccc-{}-end-of-options", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("-E"), b"a\n").unwrap();
+    let output = Command::new(BIN)
+        .args(["--", "-E"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"a\n");
+}
+
+#[test]
+fn test_exit_status() {
+    let path = temp_file("exit-status", b"a\n");
+    let output = Command::new(BIN)
+        .arg(&path)
+        .arg("missing-file")
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    // The other files are still written before failing
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stdout, b"a\na\n");
+
+    let output = Command::new(BIN).arg("--help").output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let output = Command::new(BIN).arg("--head").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_binary_check() {
+    let binary = temp_file("binary-check", b"\x7fELF\x00\x01\x02\n");
+    let text = temp_file("binary-check-text", b"text\n");
+    let output = Command::new(BIN)
+        .arg("--binary-check")
+        .arg(&binary)
+        .arg(&text)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stdout, b"text\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("binary file \u{2014} use -v to view"));
+
+    let output = Command::new(BIN)
+        .args(["--binary-check", "-v"])
+        .arg(&binary)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&binary).unwrap();
+    std::fs::remove_file(&text).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"^?ELF^@^A^B\n");
+}
+
+#[test]
+fn test_stats_blanks_squeezed() {
+    let path = temp_file("stats-squeeze", b"a\n\n\n\nb\n\n\nc\n");
+    let output = Command::new(BIN)
+        .args(["-s", "--stats"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"a\n\nb\n\nc\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("5 lines, 11 bytes in, 8 bytes out, 3 blank lines squeezed"));
+}
+
+#[test]
+fn test_checksum() {
+    let path = temp_file("checksum", b"hello\nhello\n");
+    let output = Command::new(BIN)
+        .args(["-n", "--checksum", "crc32"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"     1\thello\n     2\thello\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with(": crc32 b7057422\n"), "{stderr}");
+}
+
+#[cfg(feature = "terminal")]
+#[test]
+fn test_wrap_auto_without_terminal() {
+    let path = temp_file("wrap-auto", &[b'x'; 300]);
+    let output = Command::new(BIN)
+        .args(["--wrap", "auto"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [b'x'; 300]);
+}
+
+#[test]
+fn test_strip_ansi() {
+    let path = temp_file("strip-ansi", b"\x1b[32mok\x1b[0m\n");
+    let output = Command::new(BIN)
+        .args(["--strip-ansi", "-n"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"     1\tok\n");
+}
+
+#[test]
+fn test_match() {
+    let path = temp_file("match", b"one\ntwo\nthree\n");
+    let output = Command::new(BIN)
+        .args(["-n", "--match", "t"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"     2\ttwo\n     3\tthree\n");
+
+    let output = Command::new(BIN)
+        .args(["--match=t", "--invert"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.stdout, b"one\n");
+}
+
+#[test]
+fn test_count() {
+    let path = temp_file("count", b"a\n\n\n\nb\n\nlast");
+    for (flags, expected) in [
+        (&[][..], &b"7\n"[..]),
+        (&["-s"], b"5\n"),
+        (&["-b"], b"3\n"),
+        (&["-bs"], b"3\n"),
+    ] {
+        let output = Command::new(BIN)
+            .arg("--count")
+            .args(flags)
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, expected);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_files_from_stdin() {
+    let first = temp_file("files-from-1", b"a\n");
+    let second = temp_file("files-from-2", b"b\n");
+    let mut child = Command::new(BIN)
+        .args(["-n", "--files-from", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let list = format!("{}\n{}\n", first.display(), second.display());
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(list.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&first).unwrap();
+    std::fs::remove_file(&second).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"     1\ta\n     2\tb\n");
+}
+
+#[test]
+fn test_files_from_stdin_names_stdin() {
+    // Standard input is already being read for the list
+    let path = temp_file("files-from-stdin", b"a\n");
+    let mut child = Command::new(BIN)
+        .args(["--files-from", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let list = format!("-\n{}\n", path.display());
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(list.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, b"a\n");
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("file name '-' not allowed when reading names from stdin"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fd() {
+    use std::os::fd::FromRawFd;
+
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for both ends of the pipe
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    // SAFETY: the pipe was just created, and each end is owned once
+    let (reader, mut writer) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+    writer.write_all(b"a\nb\n").unwrap();
+    drop(writer);
+    let output = Command::new(BIN)
+        .args(["-n", "--fd", &fds[0].to_string()])
+        .output()
+        .unwrap();
+    drop(reader);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"     1\ta\n     2\tb\n");
+
+    let output = Command::new(BIN).args(["--fd", "999"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("fd 999"));
+}
+
+#[test]
+fn test_progress_redirected() {
+    let path = temp_file("progress", &vec![b'a'; 8 << 20]);
+    let output = Command::new(BIN)
+        .arg("--progress")
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout.len(), 8 << 20);
+    // Not a terminal, so no progress
+    assert!(output.stderr.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_permission_denied() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let unreadable = temp_file("unreadable", b"secret\n");
+    let readable = temp_file("readable", b"a\n");
+    std::fs::set_permissions(&unreadable, std::fs::Permissions::from_mode(0o000)).unwrap();
+    if std::fs::File::open(&unreadable).is_ok() {
+        // Permissions don't apply to root
+        std::fs::remove_file(&unreadable).unwrap();
+        std::fs::remove_file(&readable).unwrap();
+        return;
+    }
+    let output = Command::new(BIN)
+        .arg(&unreadable)
+        .arg(&readable)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&unreadable).unwrap();
+    std::fs::remove_file(&readable).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    // The next file is still written
+    assert_eq!(output.stdout, b"a\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("{}: Permission denied", unreadable.display())));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_file_name() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = std::ffi::OsStr::from_bytes(b"ccc-non-utf8-\xff");
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, b"a\n").unwrap();
+    let output = Command::new(BIN).arg("-n").arg(&path).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"     1\ta\n");
+
+    // Names in a --files-from list are taken as the bytes they are
+    let mut list = path.as_os_str().as_bytes().to_vec();
+    list.push(b'\n');
+    let list = temp_file("non-utf8-list", &list);
+    let output = Command::new(BIN)
+        .arg("--files-from")
+        .arg(&list)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&list).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"a\n");
+
+    let output = Command::new(BIN).arg(&path).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ccc-non-utf8-\u{fffd}"));
+}
+
+#[cfg(windows)]
+#[test]
+fn test_windows_redirected_output_unchanged() {
+    // Only a console is switched to UTF-8; redirected output gets the bytes
+    let contents = "caf\u{e9} \u{1f600}\n";
+    let path = temp_file("windows-utf8", contents.as_bytes());
+    let output = Command::new(BIN).arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, contents.as_bytes());
+}